@@ -0,0 +1,68 @@
+/// A log group ARN such as
+/// `arn:aws:logs:us-west-2:123456789012:log-group:/aws/lambda/my-function:*`,
+/// as copied from the console.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogGroupArn {
+    pub partition: String,
+    pub region: String,
+    pub account_id: String,
+    pub name: String,
+}
+
+impl LogGroupArn {
+    /// `None` for anything that isn't a log group ARN, e.g. a plain name.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().splitn(6, ':');
+        let (Some("arn"), Some(partition), Some("logs"), Some(region), Some(account_id), Some(resource)) =
+            (parts.next(), parts.next(), parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+
+        let name = resource.strip_prefix("log-group:")?;
+        let name = name.strip_suffix(":*").unwrap_or(name);
+        if region.is_empty() || account_id.is_empty() || name.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            partition: partition.to_string(),
+            region: region.to_string(),
+            account_id: account_id.to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    /// The ARN in the form the `logGroupIdentifier` parameters accept, which
+    /// rejects the console's trailing `:*`.
+    pub fn identifier(&self) -> String {
+        format!("arn:{}:logs:{}:{}:log-group:{}", self.partition, self.region, self.account_id, self.name)
+    }
+}
+
+/// The group name for APIs that only take a name: the name part of an ARN,
+/// or `log_group` itself.
+pub fn group_name(log_group: &str) -> String {
+    LogGroupArn::parse(log_group).map_or_else(|| log_group.to_string(), |arn| arn.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_group_arn() {
+        let arn = LogGroupArn::parse("arn:aws:logs:eu-west-1:123456789012:log-group:/aws/lambda/my-fn:*").unwrap();
+        assert_eq!(arn.region, "eu-west-1");
+        assert_eq!(arn.account_id, "123456789012");
+        assert_eq!(arn.name, "/aws/lambda/my-fn");
+        assert_eq!(arn.identifier(), "arn:aws:logs:eu-west-1:123456789012:log-group:/aws/lambda/my-fn");
+
+        let gov = LogGroupArn::parse("arn:aws-us-gov:logs:us-gov-west-1:123456789012:log-group:app").unwrap();
+        assert_eq!(gov.identifier(), "arn:aws-us-gov:logs:us-gov-west-1:123456789012:log-group:app");
+
+        assert_eq!(LogGroupArn::parse("/aws/lambda/my-fn"), None);
+        assert_eq!(LogGroupArn::parse("arn:aws:s3:::my-bucket"), None);
+        assert_eq!(group_name("arn:aws:logs:eu-west-1:123456789012:log-group:app:*"), "app");
+    }
+}
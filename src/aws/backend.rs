@@ -0,0 +1,515 @@
+use anyhow::Result;
+use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
+use crate::aws::client::{CloudWatchClient, LogGroupInfo, PollInterval, StreamFilter};
+use crate::aws::dry_run::DryRunRequest;
+use std::future::Future;
+use std::time::Duration;
+
+/// The log operations `query` and `tail` need, so they can run against
+/// something other than CloudWatch. `CloudWatchClient` is the real
+/// implementation; see the `CloudWatchClient` methods of the same names for
+/// the semantics.
+pub trait LogsBackend {
+    /// `Some` in dry-run mode, in which case nothing else should be called.
+    fn dry_run(&self, operation: &'static str) -> Option<DryRunRequest>;
+
+    /// `Some(timeout)` once a fetch stopped early at the `--timeout` deadline.
+    fn timed_out(&self) -> Option<Duration>;
+
+    /// Message bytes of all events fetched so far.
+    fn bytes_returned(&self) -> u64;
+
+    fn list_log_groups(&self, prefix: Option<&str>) -> impl Future<Output = Result<Vec<String>>>;
+
+    fn describe_matching_log_groups(&self, pattern: &str) -> impl Future<Output = Result<Vec<LogGroupInfo>>>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn get_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        sorted: bool,
+    ) -> impl Future<Output = Result<Vec<FilteredLogEvent>>>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn get_log_events_from_token(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        next_token: Option<String>,
+        on_page: impl FnMut(&[FilteredLogEvent], Option<&str>) -> Result<()>,
+    ) -> impl Future<Output = Result<Vec<FilteredLogEvent>>>;
+
+    fn scan_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        on_page: impl FnMut(Vec<FilteredLogEvent>) -> Result<()>,
+    ) -> impl Future<Output = Result<()>>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn get_log_events_parallel(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: Option<usize>,
+        concurrency: usize,
+        progress: impl Fn(usize),
+    ) -> impl Future<Output = Result<Vec<FilteredLogEvent>>>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn get_log_events_multi(
+        &self,
+        log_groups: &[String],
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: Option<usize>,
+        newest: bool,
+        progress: impl Fn(usize),
+    ) -> impl Future<Output = Result<Vec<(String, FilteredLogEvent)>>>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn get_newest_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: usize,
+        concurrency: usize,
+        progress: impl FnMut(usize),
+    ) -> impl Future<Output = Result<Vec<FilteredLogEvent>>>;
+
+    fn tail_log_events(
+        &self,
+        log_group: &str,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        start_time: Option<i64>,
+        poll_interval: PollInterval,
+        callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> impl Future<Output = Result<()>>;
+
+    fn tail_single_stream(
+        &self,
+        log_group: &str,
+        log_stream: &str,
+        start_time: Option<i64>,
+        poll_interval: PollInterval,
+        callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> impl Future<Output = Result<()>>;
+
+    fn start_live_tail(
+        &self,
+        log_group: &str,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> impl Future<Output = Result<()>>;
+}
+
+impl LogsBackend for CloudWatchClient {
+    fn dry_run(&self, operation: &'static str) -> Option<DryRunRequest> {
+        CloudWatchClient::dry_run(self, operation)
+    }
+
+    fn timed_out(&self) -> Option<Duration> {
+        CloudWatchClient::timed_out(self)
+    }
+
+    fn bytes_returned(&self) -> u64 {
+        CloudWatchClient::bytes_returned(self)
+    }
+
+    async fn list_log_groups(&self, prefix: Option<&str>) -> Result<Vec<String>> {
+        CloudWatchClient::list_log_groups(self, prefix).await
+    }
+
+    async fn describe_matching_log_groups(&self, pattern: &str) -> Result<Vec<LogGroupInfo>> {
+        CloudWatchClient::describe_matching_log_groups(self, pattern).await
+    }
+
+    async fn get_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        sorted: bool,
+    ) -> Result<Vec<FilteredLogEvent>> {
+        CloudWatchClient::get_log_events(self, log_group, start_time, end_time, filter_pattern, streams, limit, sorted).await
+    }
+
+    async fn get_log_events_from_token(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        next_token: Option<String>,
+        on_page: impl FnMut(&[FilteredLogEvent], Option<&str>) -> Result<()>,
+    ) -> Result<Vec<FilteredLogEvent>> {
+        CloudWatchClient::get_log_events_from_token(self, log_group, start_time, end_time, filter_pattern, streams, limit, next_token, on_page).await
+    }
+
+    async fn scan_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        on_page: impl FnMut(Vec<FilteredLogEvent>) -> Result<()>,
+    ) -> Result<()> {
+        CloudWatchClient::scan_log_events(self, log_group, start_time, end_time, filter_pattern, on_page).await
+    }
+
+    async fn get_log_events_parallel(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: Option<usize>,
+        concurrency: usize,
+        progress: impl Fn(usize),
+    ) -> Result<Vec<FilteredLogEvent>> {
+        CloudWatchClient::get_log_events_parallel(
+            self, log_group, start_time, end_time, filter_pattern, limit, concurrency, progress,
+        ).await
+    }
+
+    async fn get_log_events_multi(
+        &self,
+        log_groups: &[String],
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: Option<usize>,
+        newest: bool,
+        progress: impl Fn(usize),
+    ) -> Result<Vec<(String, FilteredLogEvent)>> {
+        CloudWatchClient::get_log_events_multi(
+            self, log_groups, start_time, end_time, filter_pattern, limit, newest, progress,
+        ).await
+    }
+
+    async fn get_newest_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: usize,
+        concurrency: usize,
+        progress: impl FnMut(usize),
+    ) -> Result<Vec<FilteredLogEvent>> {
+        CloudWatchClient::get_newest_log_events(self, log_group, start_time, end_time, filter_pattern, limit, concurrency, progress).await
+    }
+
+    async fn tail_log_events(
+        &self,
+        log_group: &str,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        start_time: Option<i64>,
+        poll_interval: PollInterval,
+        callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> Result<()> {
+        CloudWatchClient::tail_log_events(
+            self, log_group, filter_pattern, streams, start_time, poll_interval, callback,
+        ).await
+    }
+
+    async fn tail_single_stream(
+        &self,
+        log_group: &str,
+        log_stream: &str,
+        start_time: Option<i64>,
+        poll_interval: PollInterval,
+        callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> Result<()> {
+        CloudWatchClient::tail_single_stream(self, log_group, log_stream, start_time, poll_interval, callback).await
+    }
+
+    async fn start_live_tail(
+        &self,
+        log_group: &str,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> Result<()> {
+        CloudWatchClient::start_live_tail(self, log_group, filter_pattern, streams, callback).await
+    }
+}
+
+/// A filter pattern and the stream names it was limited to.
+#[cfg(test)]
+pub type LiveTailCall = (Option<String>, Vec<String>);
+
+/// A backend serving canned events, for testing commands without AWS.
+/// Filter patterns match as plain substrings, and tailing replays the
+/// matching events once and returns. Unknown groups fail like missing ones
+/// do in CloudWatch. Events come back in the order they were
+/// added, except where `CloudWatchClient` sorts them.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockBackend {
+    pub groups: Vec<String>,
+    pub events: Vec<(String, FilteredLogEvent)>,
+    /// The filter pattern and stream names of each `start_live_tail` call,
+    /// shared so tests can check them after handing the backend over
+    pub live_tails: std::sync::Arc<std::sync::Mutex<Vec<LiveTailCall>>>,
+}
+
+#[cfg(test)]
+impl MockBackend {
+    /// Adds an event to `log_group`, which is registered if it's new.
+    pub fn with_event(mut self, log_group: &str, stream: &str, timestamp: i64, message: &str) -> Self {
+        if !self.groups.iter().any(|group| group == log_group) {
+            self.groups.push(log_group.to_string());
+        }
+        self.events.push((
+            log_group.to_string(),
+            FilteredLogEvent::builder()
+                .log_stream_name(stream)
+                .timestamp(timestamp)
+                .message(message)
+                .event_id(self.events.len().to_string())
+                .build(),
+        ));
+        self
+    }
+
+    fn matching(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+    ) -> Result<Vec<FilteredLogEvent>, crate::error::CwlError> {
+        if !self.groups.iter().any(|group| group == log_group) {
+            return Err(crate::error::CwlError::log_group_not_found(log_group, "us-east-1", &self.groups));
+        }
+        Ok(self.events.iter()
+            .filter(|(group, _)| group == log_group)
+            .map(|(_, event)| event.clone())
+            .filter(|event| {
+                let timestamp = event.timestamp.unwrap_or_default();
+                start_time.is_none_or(|start| timestamp >= start) && end_time.is_none_or(|end| timestamp <= end)
+            })
+            .filter(|event| {
+                filter_pattern.is_none_or(|pattern| event.message.as_deref().unwrap_or_default().contains(pattern))
+            })
+            .filter(|event| streams.matches(event.log_stream_name.as_deref().unwrap_or_default()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+impl LogsBackend for MockBackend {
+    fn dry_run(&self, _operation: &'static str) -> Option<DryRunRequest> {
+        None
+    }
+
+    fn timed_out(&self) -> Option<Duration> {
+        None
+    }
+
+    fn bytes_returned(&self) -> u64 {
+        0
+    }
+
+    async fn list_log_groups(&self, prefix: Option<&str>) -> Result<Vec<String>> {
+        Ok(self.groups.iter()
+            .filter(|group| prefix.is_none_or(|prefix| group.starts_with(prefix)))
+            .cloned()
+            .collect())
+    }
+
+    async fn describe_matching_log_groups(&self, pattern: &str) -> Result<Vec<LogGroupInfo>> {
+        Ok(self.groups.iter()
+            .filter(|group| crate::utils::format::glob_matches(pattern, group))
+            .map(|group| LogGroupInfo {
+                name: group.clone(),
+                creation_time: None,
+                retention_in_days: None,
+                stored_bytes: None,
+                arn: None,
+                metric_filter_count: None,
+                log_group_class: None,
+                last_event_time: None,
+            })
+            .collect())
+    }
+
+    async fn get_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        sorted: bool,
+    ) -> Result<Vec<FilteredLogEvent>> {
+        let mut events = self.matching(log_group, start_time, end_time, filter_pattern, streams)?;
+        events.truncate(limit.unwrap_or(usize::MAX));
+        if sorted {
+            crate::aws::client::sort_events(&mut events);
+        }
+        Ok(events)
+    }
+
+    /// Tokens are the index of the next event; each page holds one event.
+    async fn get_log_events_from_token(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        next_token: Option<String>,
+        mut on_page: impl FnMut(&[FilteredLogEvent], Option<&str>) -> Result<()>,
+    ) -> Result<Vec<FilteredLogEvent>> {
+        let mut events = self.matching(log_group, start_time, end_time, filter_pattern, streams)?;
+        events.truncate(limit.unwrap_or(usize::MAX));
+        let first = next_token.map_or(Ok(0), |token| token.parse::<usize>())?;
+        let events = events.split_off(first.min(events.len()));
+        for (i, event) in events.iter().enumerate() {
+            let next = (i + 1 < events.len()).then(|| (first + i + 1).to_string());
+            on_page(std::slice::from_ref(event), next.as_deref())?;
+        }
+        Ok(events)
+    }
+
+    /// Each page holds one event.
+    async fn scan_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        mut on_page: impl FnMut(Vec<FilteredLogEvent>) -> Result<()>,
+    ) -> Result<()> {
+        for event in self.matching(log_group, start_time, end_time, filter_pattern, StreamFilter::ALL)? {
+            on_page(vec![event])?;
+        }
+        Ok(())
+    }
+
+    async fn get_log_events_parallel(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: Option<usize>,
+        _concurrency: usize,
+        progress: impl Fn(usize),
+    ) -> Result<Vec<FilteredLogEvent>> {
+        let events = self.get_log_events(log_group, start_time, end_time, filter_pattern, StreamFilter::ALL, limit, true).await?;
+        progress(events.len());
+        Ok(events)
+    }
+
+    async fn get_log_events_multi(
+        &self,
+        log_groups: &[String],
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: Option<usize>,
+        newest: bool,
+        progress: impl Fn(usize),
+    ) -> Result<Vec<(String, FilteredLogEvent)>> {
+        let mut events: Vec<(String, FilteredLogEvent)> = Vec::new();
+        for group in log_groups {
+            for event in self.matching(group, start_time, end_time, filter_pattern, StreamFilter::ALL)? {
+                events.push((group.clone(), event));
+            }
+        }
+        events.sort_by_key(|(_, event)| (event.timestamp, event.ingestion_time));
+
+        if let Some(limit) = limit {
+            match newest {
+                true => drop(events.drain(..events.len().saturating_sub(limit))),
+                false => events.truncate(limit),
+            }
+        }
+        progress(events.len());
+        Ok(events)
+    }
+
+    async fn get_newest_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: usize,
+        _concurrency: usize,
+        mut progress: impl FnMut(usize),
+    ) -> Result<Vec<FilteredLogEvent>> {
+        let mut events = self.matching(log_group, start_time, end_time, filter_pattern, StreamFilter::ALL)?;
+        crate::aws::client::sort_events(&mut events);
+        events.drain(..events.len().saturating_sub(limit));
+        progress(events.len());
+        Ok(events)
+    }
+
+    async fn tail_log_events(
+        &self,
+        log_group: &str,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        start_time: Option<i64>,
+        _poll_interval: PollInterval,
+        mut callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> Result<()> {
+        for event in self.matching(log_group, start_time, None, filter_pattern, streams)? {
+            callback(event)?;
+        }
+        Ok(())
+    }
+
+    async fn tail_single_stream(
+        &self,
+        log_group: &str,
+        log_stream: &str,
+        start_time: Option<i64>,
+        poll_interval: PollInterval,
+        callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> Result<()> {
+        self.tail_log_events(log_group, None, StreamFilter { names: &[log_stream.to_string()], prefix: None }, start_time, poll_interval, callback).await
+    }
+
+    async fn start_live_tail(
+        &self,
+        log_group: &str,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> Result<()> {
+        self.live_tails.lock().unwrap().push((filter_pattern.map(str::to_string), streams.names.to_vec()));
+        self.tail_log_events(log_group, filter_pattern, streams, None, PollInterval::default(), callback).await
+    }
+}
@@ -1,37 +1,248 @@
 use anyhow::{Result, Context};
-use aws_config::BehaviorVersion;
+use aws_config::{BehaviorVersion, Region};
 use aws_sdk_cloudwatchlogs::{
     Client,
-    types::FilteredLogEvent,
+    error::{ProvideErrorMetadata, SdkError},
+    types::{FilteredLogEvent, InputLogEvent, LogStream, OrderBy, StartLiveTailResponseStream},
 };
+use crate::aws::arn::{group_name, LogGroupArn};
+use crate::aws::dry_run::DryRunRequest;
+use crate::error::CwlError;
+use crate::utils::output::OutputFormat;
+use crate::utils::format::glob_matches;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Targets a log group by name, or for ARNs by `logGroupIdentifier`, which
+/// also reaches groups shared from other accounts.
+macro_rules! target_log_group {
+    ($request:expr, $log_group:expr) => {
+        match LogGroupArn::parse($log_group) {
+            Some(arn) => $request.log_group_identifier(arn.identifier()),
+            None => $request.log_group_name($log_group),
+        }
+    };
+}
+
+/// Used when no region is configured anywhere.
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// The `log_group_class` of Infrequent Access log groups, which are cheaper
+/// to store but support fewer operations than Standard ones.
+pub const INFREQUENT_ACCESS: &str = "INFREQUENT_ACCESS";
+
+/// How many log groups are queried at once when fanning out.
+pub const MAX_CONCURRENT_GROUPS: usize = 5;
+
+/// The pause before replacing a live tail session that ended, doubled
+/// while sessions keep ending empty.
+const LIVE_TAIL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+fn env_region(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|region| !region.trim().is_empty())
+}
+
+#[derive(Debug, Clone)]
+pub struct LogGroupInfo {
+    pub name: String,
+    pub creation_time: Option<i64>,
+    pub retention_in_days: Option<i32>,
+    pub stored_bytes: Option<i64>,
+    pub arn: Option<String>,
+    pub metric_filter_count: Option<i32>,
+    /// `STANDARD`, `INFREQUENT_ACCESS` or `DELIVERY`
+    pub log_group_class: Option<String>,
+    /// Newest event across the group's streams. Only filled in by
+    /// `fill_last_event_times`, since it costs an extra call per group.
+    pub last_event_time: Option<i64>,
+}
+
+/// A metric filter and the metrics it publishes matches to.
+#[derive(Debug, Clone)]
+pub struct MetricFilterInfo {
+    pub name: String,
+    /// Empty when the filter matches every event
+    pub pattern: String,
+    /// `namespace/name` of each metric transformation
+    pub metrics: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogStreamInfo {
+    pub name: String,
+    pub creation_time: Option<i64>,
+    pub last_event_time: Option<i64>,
+    pub stored_bytes: Option<i64>,
+}
+
+impl LogStreamInfo {
+    /// `None` for the (in practice nonexistent) streams without a name.
+    fn from_sdk(stream: LogStream) -> Option<Self> {
+        #[allow(deprecated)]
+        let stored_bytes = stream.stored_bytes;
+
+        Some(Self {
+            name: stream.log_stream_name?,
+            creation_time: stream.creation_time,
+            last_event_time: stream.last_event_timestamp,
+            stored_bytes,
+        })
+    }
+}
+
+/// How often `tail_log_events` polls once it has caught up. The delay starts
+/// at `base`, doubles after each poll that returns nothing up to `max`, and
+/// resets as soon as new events arrive.
+#[derive(Debug, Clone, Copy)]
+pub struct PollInterval {
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Default for PollInterval {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Which of a log group's streams a read covers: those listed in `names`,
+/// or those whose names start with `prefix`. CloudWatch takes one or the
+/// other, not both. The default covers every stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamFilter<'a> {
+    pub names: &'a [String],
+    pub prefix: Option<&'a str>,
+}
+
+impl StreamFilter<'_> {
+    pub const ALL: StreamFilter<'static> = StreamFilter { names: &[], prefix: None };
+
+    pub fn matches(&self, stream: &str) -> bool {
+        (self.names.is_empty() || self.names.iter().any(|name| name == stream))
+            && self.prefix.is_none_or(|prefix| stream.starts_with(prefix))
+    }
+}
 
 #[derive(Clone)]
 pub struct CloudWatchClient {
     pub client: Arc<Client>,
+    /// Ask FilterLogEvents to return fields masked by data protection policies
+    unmask: bool,
+    region: String,
+    profile: Option<String>,
+    /// Output format for `--dry-run` requests; `None` sends requests as usual
+    dry_run: Option<OutputFormat>,
+    /// When `--timeout` runs out, and the timeout it was set from
+    deadline: Option<(Instant, Duration)>,
+    /// Set once a fetch stopped early at the deadline; shared between clones
+    timed_out: Arc<AtomicBool>,
+    /// Message bytes of every event FilterLogEvents returned; shared between
+    /// clones
+    bytes_returned: Arc<AtomicU64>,
 }
 
 impl CloudWatchClient {
-    pub async fn new(profile: Option<&str>, region: Option<&str>) -> Result<Self> {
+    /// Region precedence: `region` (from `--region` or the cwl profile),
+    /// then `AWS_REGION`, then `AWS_DEFAULT_REGION`, then `default_region`
+    /// (`defaults.region` in the config), then the SDK's default chain (e.g.
+    /// the AWS profile's region), and finally us-east-1. `endpoint_url`
+    /// points the client at e.g. LocalStack.
+    pub async fn new(profile: Option<&str>, region: Option<&str>, default_region: Option<&str>, endpoint_url: Option<&str>) -> Result<Self> {
         let mut config_loader = aws_config::defaults(BehaviorVersion::latest());
 
         if let Some(profile) = profile {
             config_loader = config_loader.profile_name(profile);
         }
 
-        // Use provided region, or default to us-east-1
-        let region_to_use = region.unwrap_or("us-east-1");
-        config_loader = config_loader.region(aws_config::Region::new(region_to_use.to_string()));
+        let region = region.map(str::to_string)
+            .or_else(|| env_region("AWS_REGION"))
+            .or_else(|| env_region("AWS_DEFAULT_REGION"))
+            .or_else(|| default_region.map(str::to_string));
+        if let Some(region) = region {
+            config_loader = config_loader.region(Region::new(region));
+        }
+
+        if let Some(endpoint_url) = endpoint_url {
+            config_loader = config_loader.endpoint_url(endpoint_url);
+        }
 
         let config = config_loader.load().await;
-        let client = Client::new(&config);
+        let (client, region) = match config.region() {
+            Some(region) => (Client::new(&config), region.to_string()),
+            None => {
+                let conf = aws_sdk_cloudwatchlogs::config::Builder::from(&config)
+                    .region(Region::new(DEFAULT_REGION))
+                    .build();
+                (Client::from_conf(conf), DEFAULT_REGION.to_string())
+            }
+        };
 
         Ok(Self {
             client: Arc::new(client),
+            unmask: false,
+            region,
+            profile: profile.map(str::to_string),
+            dry_run: None,
+            deadline: None,
+            timed_out: Arc::new(AtomicBool::new(false)),
+            bytes_returned: Arc::new(AtomicU64::new(0)),
         })
     }
 
+    /// Switches to dry-run mode, where commands print their resolved request
+    /// in `output` format instead of calling AWS.
+    pub fn with_dry_run(mut self, dry_run: bool, output: OutputFormat) -> Self {
+        self.dry_run = dry_run.then_some(output);
+        self
+    }
+
+    /// In dry-run mode, starts describing the `operation` a command would
+    /// send. Commands print it and return early when this is `Some`.
+    pub fn dry_run(&self, operation: &'static str) -> Option<DryRunRequest> {
+        self.dry_run.map(|output| DryRunRequest::new(operation, self.region.clone(), self.profile.clone(), output))
+    }
+
+    /// Stops fetching event pages once `timeout` has passed, keeping the
+    /// events fetched so far; see `timed_out`.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.deadline = timeout.map(|timeout| (Instant::now() + timeout, timeout));
+        self
+    }
+
+    /// Total size of the messages fetched so far. FilterLogEvents doesn't
+    /// report how much it scanned to find them, so this is a lower bound.
+    pub fn bytes_returned(&self) -> u64 {
+        self.bytes_returned.load(Ordering::Relaxed)
+    }
+
+    /// The `--timeout` that cut a fetch short, if one was.
+    pub fn timed_out(&self) -> Option<Duration> {
+        self.deadline
+            .filter(|_| self.timed_out.load(Ordering::Relaxed))
+            .map(|(_, timeout)| timeout)
+    }
+
+    /// Reveals data-protection-masked fields in fetched events. The caller
+    /// needs the `logs:Unmask` permission, otherwise fetches fail.
+    pub fn with_unmask(mut self, unmask: bool) -> Self {
+        self.unmask = unmask;
+        self
+    }
+
     pub async fn list_log_groups(&self, prefix: Option<&str>) -> Result<Vec<String>> {
+        let groups = self.describe_log_groups(prefix).await?;
+        Ok(groups.into_iter().map(|g| g.name).collect())
+    }
+
+    pub async fn describe_log_groups(&self, prefix: Option<&str>) -> Result<Vec<LogGroupInfo>> {
         let mut groups = Vec::new();
         let mut next_token = None;
 
@@ -47,12 +258,22 @@ impl CloudWatchClient {
             }
 
             let response = request.send().await
+                .map_err(CwlError::from)
                 .context("Failed to list log groups")?;
 
             if let Some(log_groups) = response.log_groups {
                 for group in log_groups {
                     if let Some(name) = group.log_group_name {
-                        groups.push(name);
+                        groups.push(LogGroupInfo {
+                            name,
+                            creation_time: group.creation_time,
+                            retention_in_days: group.retention_in_days,
+                            stored_bytes: group.stored_bytes,
+                            arn: group.arn,
+                            metric_filter_count: group.metric_filter_count,
+                            log_group_class: group.log_group_class.map(|class| class.as_str().to_string()),
+                            last_event_time: None,
+                        });
                     }
                 }
             }
@@ -66,23 +287,373 @@ impl CloudWatchClient {
         Ok(groups)
     }
 
+    /// Describes the log groups matching a `*` wildcard pattern, listing by
+    /// the literal prefix before the first wildcard.
+    pub async fn describe_matching_log_groups(&self, pattern: &str) -> Result<Vec<LogGroupInfo>> {
+        let prefix = pattern.split('*').next().unwrap_or_default();
+        let groups = self.describe_log_groups((!prefix.is_empty()).then_some(prefix)).await?;
+
+        Ok(groups.into_iter().filter(|group| glob_matches(pattern, &group.name)).collect())
+    }
+
+    /// The class of `log_group` (see `LogGroupInfo::log_group_class`), or
+    /// `None` if no group in this account has that name.
+    pub async fn log_group_class(&self, log_group: &str) -> Result<Option<String>> {
+        let name = group_name(log_group);
+        let groups = self.describe_log_groups(Some(&name)).await?;
+        Ok(groups.into_iter()
+            .find(|group| group.name == name)
+            .and_then(|group| group.log_group_class))
+    }
+
+    pub async fn put_retention_policy(&self, log_group: &str, days: i32) -> Result<()> {
+        self.client.put_retention_policy()
+            .log_group_name(group_name(log_group))
+            .retention_in_days(days)
+            .send()
+            .await
+            .map_err(CwlError::from)
+            .context(format!("Failed to set retention for group: {}", log_group))?;
+
+        Ok(())
+    }
+
+    /// Writes one event to `stream`, creating the stream first if it doesn't
+    /// exist. Sequence tokens are no longer required by the API, but older
+    /// endpoints (and some emulators) still reject a put without the expected
+    /// token, so a rejected token is retried once with the one they ask for.
+    pub async fn put_log_event(&self, log_group: &str, stream: &str, message: &str, timestamp: i64) -> Result<()> {
+        if let Err(err) = self.client.create_log_stream()
+            .log_group_name(group_name(log_group))
+            .log_stream_name(stream)
+            .send()
+            .await
+        {
+            if err.code() != Some("ResourceAlreadyExistsException") {
+                return Err(CwlError::from(err))
+                    .context(format!("Failed to create log stream {} in {}", stream, log_group));
+            }
+        }
+
+        let event = InputLogEvent::builder()
+            .timestamp(timestamp)
+            .message(message)
+            .build()
+            .context("Failed to build log event")?;
+
+        let mut sequence_token = None;
+        let mut retried = false;
+        loop {
+            let result = self.client.put_log_events()
+                .log_group_name(group_name(log_group))
+                .log_stream_name(stream)
+                .log_events(event.clone())
+                .set_sequence_token(sequence_token.clone())
+                .send()
+                .await;
+
+            let err = match result {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+            match (err.code(), expected_sequence_token(err.message())) {
+                // A retried put whose first attempt actually went through
+                (Some("DataAlreadyAcceptedException"), _) => return Ok(()),
+                (Some("InvalidSequenceTokenException"), Some(token)) if !retried => {
+                    sequence_token = Some(token);
+                    retried = true;
+                }
+                _ => {
+                    return Err(CwlError::from(err))
+                        .context(format!("Failed to put log event to {}/{}", log_group, stream));
+                }
+            }
+        }
+    }
+
+    /// Lists the streams of a log group, most recently active first. Paging
+    /// stops after `limit` streams, or at the first stream whose last event is
+    /// older than `active_since`.
+    pub async fn describe_log_streams(
+        &self,
+        log_group: &str,
+        limit: Option<usize>,
+        active_since: Option<i64>,
+    ) -> Result<Vec<LogStreamInfo>> {
+        let mut streams = Vec::new();
+        let mut next_token = None;
+
+        // DescribeLogStreams returns at most 50 streams per request
+        const MAX_STREAMS_PER_REQUEST: usize = 50;
+
+        'pages: loop {
+            let remaining = limit.map_or(MAX_STREAMS_PER_REQUEST, |l| l.saturating_sub(streams.len()));
+            if remaining == 0 {
+                break;
+            }
+
+            let mut request = target_log_group!(self.client.describe_log_streams(), log_group)
+                .order_by(OrderBy::LastEventTime)
+                .descending(true)
+                .limit(remaining.min(MAX_STREAMS_PER_REQUEST) as i32);
+
+            if let Some(token) = next_token {
+                request = request.next_token(token);
+            }
+
+            let response = request.send().await
+                .map_err(CwlError::from)
+                .context(format!("Failed to describe log streams for group: {}", log_group))?;
+
+            for stream in response.log_streams.unwrap_or_default() {
+                if let Some(cutoff) = active_since {
+                    if stream.last_event_timestamp.unwrap_or(0) < cutoff {
+                        break 'pages;
+                    }
+                }
+
+                streams.extend(LogStreamInfo::from_sdk(stream));
+            }
+
+            next_token = response.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(streams)
+    }
+
+    /// Lists the streams of a log group, optionally only those whose names
+    /// start with `prefix`, ordered by name or with `order_by_last_event`
+    /// most recently active first, up to `limit`. DescribeLogStreams can't
+    /// filter by prefix and order by last event at once, so with both every
+    /// matching stream is fetched and sorted here; otherwise paging stops
+    /// once `limit` is reached.
+    pub async fn list_log_streams(
+        &self,
+        log_group: &str,
+        prefix: Option<&str>,
+        order_by_last_event: bool,
+        limit: Option<usize>,
+    ) -> Result<Vec<LogStreamInfo>> {
+        let mut streams = Vec::new();
+        let mut next_token = None;
+        let server_order = order_by_last_event && prefix.is_none();
+        let sorted_locally = order_by_last_event && !server_order;
+
+        loop {
+            let mut request = target_log_group!(self.client.describe_log_streams(), log_group);
+
+            request = match prefix {
+                Some(prefix) => request.log_stream_name_prefix(prefix),
+                None if server_order => request.order_by(OrderBy::LastEventTime).descending(true),
+                None => request.order_by(OrderBy::LogStreamName),
+            };
+
+            if let Some(token) = next_token {
+                request = request.next_token(token);
+            }
+
+            let response = request.send().await
+                .map_err(CwlError::from)
+                .context(format!("Failed to list log streams for group: {}", log_group))?;
+
+            streams.extend(response.log_streams.unwrap_or_default().into_iter().filter_map(LogStreamInfo::from_sdk));
+
+            next_token = response.next_token;
+            let enough = !sorted_locally && limit.is_some_and(|limit| streams.len() >= limit);
+            if next_token.is_none() || enough {
+                break;
+            }
+        }
+
+        if sorted_locally {
+            streams.sort_by_key(|stream| std::cmp::Reverse(stream.last_event_time));
+        }
+        if let Some(limit) = limit {
+            streams.truncate(limit);
+        }
+
+        Ok(streams)
+    }
+
+    /// Sets `last_event_time` on each group from its most recently active
+    /// stream, checking up to `MAX_CONCURRENT_GROUPS` groups at once.
+    /// `progress` is called with the number of groups checked so far.
+    pub async fn fill_last_event_times(&self, groups: &mut [LogGroupInfo], progress: impl Fn(usize)) -> Result<()> {
+        let checked = AtomicUsize::new(0);
+        let last_event_times: Vec<Option<i64>> = stream::iter(groups.iter())
+            .map(|group| async {
+                let streams = self.describe_log_streams(&group.name, Some(1), None).await?;
+                progress(checked.fetch_add(1, Ordering::Relaxed) + 1);
+                Ok::<_, anyhow::Error>(streams.first().and_then(|stream| stream.last_event_time))
+            })
+            .buffered(MAX_CONCURRENT_GROUPS)
+            .try_collect()
+            .await?;
+
+        for (group, last_event_time) in groups.iter_mut().zip(last_event_times) {
+            group.last_event_time = last_event_time;
+        }
+
+        Ok(())
+    }
+
+    pub async fn describe_metric_filters(&self, log_group: &str) -> Result<Vec<MetricFilterInfo>> {
+        let mut filters = Vec::new();
+        let mut next_token = None;
+
+        loop {
+            let mut request = self.client.describe_metric_filters()
+                .log_group_name(group_name(log_group));
+
+            if let Some(token) = next_token {
+                request = request.next_token(token);
+            }
+
+            let response = request.send().await
+                .map_err(CwlError::from)
+                .with_context(|| format!("Failed to describe metric filters of {}", log_group))?;
+
+            for filter in response.metric_filters.unwrap_or_default() {
+                filters.push(MetricFilterInfo {
+                    name: filter.filter_name.unwrap_or_default(),
+                    pattern: filter.filter_pattern.unwrap_or_default(),
+                    metrics: filter.metric_transformations.unwrap_or_default().into_iter()
+                        .map(|metric| format!("{}/{}", metric.metric_namespace, metric.metric_name))
+                        .collect(),
+                });
+            }
+
+            next_token = response.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(filters)
+    }
+
+    /// The metric filters of each of `groups`, in order, describing up to
+    /// `MAX_CONCURRENT_GROUPS` groups at once. Groups that DescribeLogGroups
+    /// reported no metric filters for are skipped. `progress` gets the number
+    /// of groups done so far.
+    pub async fn describe_group_metric_filters(&self, groups: &[LogGroupInfo], progress: impl Fn(usize)) -> Result<Vec<Vec<MetricFilterInfo>>> {
+        let checked = AtomicUsize::new(0);
+        stream::iter(groups)
+            .map(|group| async {
+                let filters = match group.metric_filter_count {
+                    Some(0) => Vec::new(),
+                    _ => self.describe_metric_filters(&group.name).await?,
+                };
+                progress(checked.fetch_add(1, Ordering::Relaxed) + 1);
+                Ok::<_, anyhow::Error>(filters)
+            })
+            .buffered(MAX_CONCURRENT_GROUPS)
+            .try_collect()
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_log_events(
         &self,
         log_group: &str,
         start_time: Option<i64>,
         end_time: Option<i64>,
         filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        sorted: bool,
+    ) -> Result<Vec<FilteredLogEvent>> {
+        let mut events = self.get_log_events_with_progress(log_group, start_time, end_time, filter_pattern, streams, limit, |_| {}).await?;
+        if sorted {
+            sort_events(&mut events);
+        }
+        Ok(events)
+    }
+
+    /// Like `get_log_events`, calling `progress` after each page with the
+    /// number of events fetched so far.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_log_events_with_progress(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
         limit: Option<usize>,
+        mut progress: impl FnMut(usize),
+    ) -> Result<Vec<FilteredLogEvent>> {
+        let mut fetched = 0;
+        self.get_log_events_from_token(log_group, start_time, end_time, filter_pattern, streams, limit, None, |page, _| {
+            fetched += page.len();
+            progress(fetched);
+            Ok(())
+        }).await
+    }
+
+    /// Like `get_log_events`, but starts at `next_token` (as handed to
+    /// `on_page` by an earlier call) and calls `on_page` with each page's
+    /// events and the token of the page after it, so an interrupted fetch can
+    /// carry on where it stopped. An error from `on_page` stops the fetch.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_log_events_from_token(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        next_token: Option<String>,
+        mut on_page: impl FnMut(&[FilteredLogEvent], Option<&str>) -> Result<()>,
     ) -> Result<Vec<FilteredLogEvent>> {
         let mut events = Vec::new();
-        let mut next_token = None;
+        self.fetch_pages(log_group, start_time, end_time, filter_pattern, streams, limit, next_token, |page, next_token| {
+            on_page(&page, next_token)?;
+            events.extend(page);
+            Ok(())
+        }).await?;
+        Ok(events)
+    }
+
+    /// Like `get_log_events`, but hands each page's events to `on_page`
+    /// instead of collecting them, so memory doesn't grow with the time
+    /// range. Events come in the order CloudWatch returns them.
+    pub async fn scan_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        mut on_page: impl FnMut(Vec<FilteredLogEvent>) -> Result<()>,
+    ) -> Result<()> {
+        self.fetch_pages(log_group, start_time, end_time, filter_pattern, StreamFilter::ALL, None, None, |page, _| on_page(page)).await
+    }
+
+    /// The FilterLogEvents paging loop behind `get_log_events_from_token` and
+    /// `scan_log_events`.
+    #[allow(clippy::too_many_arguments)]
+    async fn fetch_pages(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        limit: Option<usize>,
+        mut next_token: Option<String>,
+        mut on_page: impl FnMut(Vec<FilteredLogEvent>, Option<&str>) -> Result<()>,
+    ) -> Result<()> {
+        let mut fetched = 0;
 
         // CloudWatch API has a max of 10,000 events per request
         const MAX_EVENTS_PER_REQUEST: i32 = 10000;
 
         loop {
-            let mut request = self.client.filter_log_events()
-                .log_group_name(log_group);
+            let mut request = target_log_group!(self.client.filter_log_events(), log_group);
 
             if let Some(start) = start_time {
                 request = request.start_time(start);
@@ -96,9 +667,17 @@ impl CloudWatchClient {
                 request = request.filter_pattern(pattern);
             }
 
+            for stream in streams.names {
+                request = request.log_stream_names(stream);
+            }
+
+            if let Some(prefix) = streams.prefix {
+                request = request.log_stream_name_prefix(prefix);
+            }
+
             // Calculate how many events to request in this batch
             let batch_limit = if let Some(user_limit) = limit {
-                let remaining = user_limit.saturating_sub(events.len());
+                let remaining = user_limit.saturating_sub(fetched);
                 if remaining == 0 {
                     break;
                 }
@@ -113,20 +692,40 @@ impl CloudWatchClient {
                 request = request.next_token(token);
             }
 
-            let response = request.send().await
-                .context(format!("Failed to get log events for group: {}", log_group))?;
+            if self.unmask {
+                request = request.unmask(true);
+            }
+
+            // At the deadline, the events so far are returned as they are
+            let response = match self.deadline {
+                Some((deadline, _)) => match tokio::time::timeout_at(deadline, request.send()).await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        self.timed_out.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                },
+                None => request.send().await,
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(err) => return Err(self.read_error(log_group, "FilterLogEvents", err).await)
+                    .context(format!("Failed to get log events for group: {}", log_group)),
+            };
 
-            if let Some(log_events) = response.events {
-                events.extend(log_events);
+            let mut page = response.events.unwrap_or_default();
+            // Never more than the limit requested
+            if let Some(user_limit) = limit {
+                page.truncate(user_limit - fetched);
             }
+            fetched += page.len();
+            let page_bytes: usize = page.iter().filter_map(|event| event.message.as_ref()).map(String::len).sum();
+            self.bytes_returned.fetch_add(page_bytes as u64, Ordering::Relaxed);
+            on_page(page, response.next_token.as_deref())?;
 
             // Check if we've reached the user-specified limit
-            if let Some(user_limit) = limit {
-                if events.len() >= user_limit {
-                    // Truncate to exactly the limit requested
-                    events.truncate(user_limit);
-                    break;
-                }
+            if limit.is_some_and(|user_limit| fetched >= user_limit) {
+                break;
             }
 
             next_token = response.next_token;
@@ -136,26 +735,204 @@ impl CloudWatchClient {
             }
         }
 
+        Ok(())
+    }
+
+    /// Fetches events by splitting `[start_time, end_time]` into `concurrency`
+    /// sub-ranges queried concurrently, then merging them in timestamp order.
+    /// Events repeated at chunk boundaries are dropped. Falls back to a single
+    /// sequential fetch when there is no start time to split from. `progress`
+    /// receives the total fetched across all sub-ranges after each page.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_log_events_parallel(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: Option<usize>,
+        concurrency: usize,
+        progress: impl Fn(usize),
+    ) -> Result<Vec<FilteredLogEvent>> {
+        let Some(start) = start_time.filter(|_| concurrency > 1) else {
+            let mut events = self.get_log_events_with_progress(log_group, start_time, end_time, filter_pattern, StreamFilter::ALL, limit, progress).await?;
+            sort_events(&mut events);
+            return Ok(events);
+        };
+        let end = end_time.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+        let total = AtomicUsize::new(0);
+
+        let ranges = split_time_range(start, end, concurrency);
+        let chunks: Vec<Vec<FilteredLogEvent>> = stream::iter(ranges.iter().copied())
+            .map(|(chunk_start, chunk_end)| {
+                self.get_log_events_with_progress(
+                    log_group,
+                    Some(chunk_start),
+                    Some(chunk_end),
+                    filter_pattern,
+                    StreamFilter::ALL,
+                    limit,
+                    accumulate(&total, &progress),
+                )
+            })
+            .buffered(concurrency)
+            .try_collect()
+            .await?;
+
+        let mut events = merge_chunks(chunks, &ranges);
+        sort_events(&mut events);
+
+        if let Some(limit) = limit {
+            events.truncate(limit);
+        }
+
+        Ok(events)
+    }
+
+    /// Fetches events from several log groups concurrently and merges them in
+    /// timestamp order, tagging each with its source group. `limit` applies to
+    /// the merged result. `progress` receives the total fetched across all
+    /// groups after each page.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_log_events_multi(
+        &self,
+        log_groups: &[String],
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: Option<usize>,
+        newest: bool,
+        progress: impl Fn(usize),
+    ) -> Result<Vec<(String, FilteredLogEvent)>> {
+        let total = AtomicUsize::new(0);
+        let (total, progress) = (&total, &progress);
+
+        let per_group: Vec<(String, Vec<FilteredLogEvent>)> = stream::iter(log_groups)
+            .map(|group| async move {
+                let progress = accumulate(total, progress);
+                let events = match limit {
+                    Some(limit) if newest => self.get_newest_log_events(group, start_time, end_time, filter_pattern, limit, 1, progress).await?,
+                    _ => self.get_log_events_with_progress(group, start_time, end_time, filter_pattern, StreamFilter::ALL, limit, progress).await?,
+                };
+                Ok::<_, anyhow::Error>((group.clone(), events))
+            })
+            .buffer_unordered(MAX_CONCURRENT_GROUPS)
+            .try_collect()
+            .await?;
+
+        let mut events: Vec<(String, FilteredLogEvent)> = per_group.into_iter()
+            .flat_map(|(group, events)| events.into_iter().map(move |event| (group.clone(), event)))
+            .collect();
+        events.sort_by_key(|(_, event)| event_order(event));
+
+        if let Some(limit) = limit {
+            if newest {
+                events.drain(..events.len().saturating_sub(limit));
+            } else {
+                events.truncate(limit);
+            }
+        }
+
         Ok(events)
     }
 
+    /// Fetches the newest `limit` events in the range, returned oldest first.
+    /// FilterLogEvents only pages forward in time, so this scans backwards from
+    /// the end in windows that double in size until enough events are found,
+    /// fetching up to `concurrency` windows at once. That costs extra API
+    /// calls compared to an oldest-first fetch, and each window is fetched in
+    /// full.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_newest_log_events(
+        &self,
+        log_group: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+        filter_pattern: Option<&str>,
+        limit: usize,
+        concurrency: usize,
+        progress: impl FnMut(usize),
+    ) -> Result<Vec<FilteredLogEvent>> {
+        const INITIAL_WINDOW_MS: i64 = 60_000;
+
+        let start = start_time.unwrap_or(0);
+        let concurrency = concurrency.max(1);
+        let mut window_end = end_time.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+        let mut window = INITIAL_WINDOW_MS;
+        let mut batches = Vec::new();
+        let mut exhausted = false;
+        let total = AtomicUsize::new(0);
+        let progress = RefCell::new(progress);
+        let progress = |count| progress.borrow_mut()(count);
+
+        loop {
+            let mut windows = Vec::new();
+            while windows.len() < concurrency && !exhausted {
+                let window_start = (window_end - window + 1).max(start);
+                windows.push((window_start, window_end));
+                exhausted = window_start <= start;
+                window_end = window_start - 1;
+                window = window.saturating_mul(2);
+            }
+
+            let fetched: Vec<Vec<FilteredLogEvent>> = stream::iter(windows)
+                .map(|(window_start, window_end)| self.get_log_events_with_progress(
+                    log_group,
+                    Some(window_start),
+                    Some(window_end),
+                    filter_pattern,
+                    StreamFilter::ALL,
+                    None,
+                    accumulate(&total, &progress),
+                ))
+                .buffered(concurrency)
+                .try_collect()
+                .await?;
+            batches.extend(fetched);
+
+            if total.load(Ordering::Relaxed) >= limit || exhausted || self.timed_out().is_some() {
+                break;
+            }
+        }
+
+        let mut events: Vec<FilteredLogEvent> = batches.into_iter().rev().flatten().collect();
+        sort_events(&mut events);
+        events.drain(..events.len().saturating_sub(limit));
+
+        Ok(events)
+    }
+
+    /// Follows a log group by polling FilterLogEvents, optionally limited to
+    /// some of its `streams`. Works for any number of streams and filter
+    /// patterns, but each poll searches the group.
     pub async fn tail_log_events(
         &self,
         log_group: &str,
         filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        start_time: Option<i64>,
+        poll_interval: PollInterval,
         mut callback: impl FnMut(FilteredLogEvent) -> Result<()>,
     ) -> Result<()> {
         let mut next_forward_token: Option<String> = None;
-        let mut last_event_time = None;
+        let mut last_event_time = start_time;
+        let mut delay = poll_interval.base;
 
         loop {
-            let mut request = self.client.filter_log_events()
-                .log_group_name(log_group);
+            let mut request = target_log_group!(self.client.filter_log_events(), log_group);
 
             if let Some(pattern) = filter_pattern {
                 request = request.filter_pattern(pattern);
             }
 
+            for stream in streams.names {
+                request = request.log_stream_names(stream);
+            }
+
+            if let Some(prefix) = streams.prefix {
+                request = request.log_stream_name_prefix(prefix);
+            }
+
             if let Some(last_time) = last_event_time {
                 request = request.start_time(last_time);
             } else {
@@ -168,10 +945,20 @@ impl CloudWatchClient {
                 request = request.next_token(token.clone());
             }
 
-            let response = request.send().await?;
+            if self.unmask {
+                request = request.unmask(true);
+            }
 
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(err) => return Err(self.read_error(log_group, "FilterLogEvents", err).await)
+                    .context(format!("Failed to tail log group: {}", log_group)),
+            };
+
+            let mut received = false;
             if let Some(events) = response.events {
                 for event in events {
+                    received = true;
                     if let Some(timestamp) = event.timestamp {
                         last_event_time = Some(timestamp + 1);
                     }
@@ -182,8 +969,408 @@ impl CloudWatchClient {
             next_forward_token = response.next_token;
 
             if next_forward_token.is_none() {
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                if received {
+                    delay = poll_interval.base;
+                }
+                tokio::time::sleep(delay).await;
+                if !received {
+                    delay = delay.saturating_mul(2).min(poll_interval.max.max(poll_interval.base));
+                }
+            }
+        }
+    }
+
+    /// Follows a single stream with GetLogEvents. The first call reads forward
+    /// from `start_time` (default: the last minute); later calls resume from
+    /// the stream's forward token, so each poll only reads what was appended. Cheaper and lower latency than `tail_log_events`,
+    /// but unfiltered.
+    pub async fn tail_single_stream(
+        &self,
+        log_group: &str,
+        log_stream: &str,
+        start_time: Option<i64>,
+        poll_interval: PollInterval,
+        mut callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> Result<()> {
+        let mut next_forward_token: Option<String> = None;
+        let mut delay = poll_interval.base;
+
+        loop {
+            let mut request = target_log_group!(self.client.get_log_events(), log_group)
+                .log_stream_name(log_stream);
+
+            // Reading from the head, so the first page starts at `start_time`
+            // rather than at the stream's end, and forward tokens are honored
+            request = match next_forward_token {
+                Some(ref token) => request.next_token(token.clone()),
+                None => request.start_time(start_time.unwrap_or_else(|| chrono::Utc::now().timestamp_millis() - 60000)),
+            }.start_from_head(true);
+
+            if self.unmask {
+                request = request.unmask(true);
+            }
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(err) => return Err(self.read_error(log_group, "GetLogEvents", err).await)
+                    .context(format!("Failed to tail log stream: {}", log_stream)),
+            };
+
+            let events = response.events.unwrap_or_default();
+            let received = !events.is_empty();
+            for event in events {
+                callback(FilteredLogEvent::builder()
+                    .log_stream_name(log_stream)
+                    .set_timestamp(event.timestamp)
+                    .set_message(event.message)
+                    .set_ingestion_time(event.ingestion_time)
+                    .build())?;
+            }
+
+            // At the end of the stream the same forward token comes back
+            next_forward_token = response.next_forward_token.or(next_forward_token);
+
+            if received {
+                delay = poll_interval.base;
+            }
+            tokio::time::sleep(delay).await;
+            if !received {
+                delay = delay.saturating_mul(2).min(poll_interval.max.max(poll_interval.base));
+            }
+        }
+    }
+
+    /// Follows a log group through a StartLiveTail session, which pushes
+    /// events as they're ingested instead of being polled for. Only events
+    /// from the session's start on are seen. Sessions end after at most three
+    /// hours; a new one is started in its place, so anything ingested while
+    /// reconnecting is missed.
+    pub async fn start_live_tail(
+        &self,
+        log_group: &str,
+        filter_pattern: Option<&str>,
+        streams: StreamFilter<'_>,
+        mut callback: impl FnMut(FilteredLogEvent) -> Result<()>,
+    ) -> Result<()> {
+        let identifier = self.live_tail_identifier(log_group).await?;
+        let mut delay = LIVE_TAIL_RECONNECT_DELAY;
+
+        loop {
+            let mut request = self.client.start_live_tail().log_group_identifiers(&identifier);
+
+            if let Some(pattern) = filter_pattern {
+                request = request.log_event_filter_pattern(pattern);
+            }
+
+            for stream in streams.names {
+                request = request.log_stream_names(stream);
+            }
+
+            if let Some(prefix) = streams.prefix {
+                request = request.log_stream_name_prefixes(prefix);
+            }
+
+            let mut session = match request.send().await {
+                Ok(output) => output.response_stream,
+                Err(err) => return Err(self.read_error(log_group, "StartLiveTail", err).await)
+                    .context(format!("Failed to start live tail of log group: {}", log_group)),
+            };
+
+            let mut received = false;
+            loop {
+                let update = match session.recv().await {
+                    Ok(Some(StartLiveTailResponseStream::SessionUpdate(update))) => update,
+                    Ok(Some(_)) => continue,
+                    Ok(None) => break,
+                    Err(err) if err.code() == Some("SessionTimeoutException") => break,
+                    Err(err) => return Err(CwlError::from(err))
+                        .context(format!("Live tail of log group {} failed", log_group)),
+                };
+
+                for event in update.session_results.unwrap_or_default() {
+                    received = true;
+                    callback(FilteredLogEvent::builder()
+                        .set_log_stream_name(event.log_stream_name)
+                        .set_timestamp(event.timestamp)
+                        .set_message(event.message)
+                        .set_ingestion_time(event.ingestion_time)
+                        .build())?;
+                }
+            }
+
+            // A session that ends without delivering anything is retried
+            // more and more slowly rather than in a tight loop
+            if received {
+                delay = LIVE_TAIL_RECONNECT_DELAY;
+            }
+            tokio::time::sleep(delay).await;
+            if !received {
+                delay = delay.saturating_mul(2).min(Duration::from_secs(30));
+            }
+        }
+    }
+
+    /// The ARN StartLiveTail needs for `log_group`, which only accepts ARNs,
+    /// looking it up when a plain name is given.
+    async fn live_tail_identifier(&self, log_group: &str) -> Result<String> {
+        if let Some(arn) = LogGroupArn::parse(log_group) {
+            return Ok(arn.identifier());
+        }
+
+        let groups = self.describe_log_groups(Some(log_group)).await?;
+        match groups.iter().find(|group| group.name == log_group).and_then(|group| group.arn.as_deref()) {
+            Some(arn) => Ok(arn.strip_suffix(":*").unwrap_or(arn).to_string()),
+            None => {
+                let names = self.suggestion_candidates(log_group).await.unwrap_or_default();
+                Err(CwlError::log_group_not_found(log_group, &self.region, &names).into())
+            }
+        }
+    }
+
+    /// Categorizes a failure to read log events. With unmasking requested, an
+    /// access denial most likely means the `logs:Unmask` permission is missing.
+    fn filter_error<E, R>(&self, err: SdkError<E, R>) -> CwlError
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+        R: std::fmt::Debug,
+    {
+        match CwlError::from(err) {
+            CwlError::Auth(message) if self.unmask => CwlError::Auth(format!(
+                "--unmask requires the logs:Unmask permission on this log group: {}",
+                message
+            )),
+            err => err,
+        }
+    }
+
+    /// Like `filter_error`, but explains a rejected `operation` on an
+    /// Infrequent Access log group, which CloudWatch reports as a bare
+    /// parameter error, and suggests similar names for a missing group. Only
+    /// failures trigger the extra lookups.
+    async fn read_error<E, R>(&self, log_group: &str, operation: &str, err: SdkError<E, R>) -> CwlError
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+        R: std::fmt::Debug,
+    {
+        match self.filter_error(err) {
+            err @ (CwlError::InvalidArgument(_) | CwlError::Aws(_)) => {
+                let class = self.log_group_class(log_group).await.ok().flatten();
+                class_error(err, log_group, operation, class.as_deref())
             }
+            CwlError::LogGroupNotFound(message) => {
+                let existing = self.suggestion_candidates(&group_name(log_group)).await.unwrap_or_default();
+                not_found_error(message, log_group, &self.region, &existing)
+            }
+            err => err,
+        }
+    }
+
+    /// Group names to suggest instead of a missing `name`: those sharing its
+    /// path up to the last `/`, or every group when there are none.
+    async fn suggestion_candidates(&self, name: &str) -> Result<Vec<String>> {
+        let prefix = name.rfind('/').map(|end| &name[..=end]).filter(|prefix| *prefix != "/");
+        let groups = self.list_log_groups(prefix).await?;
+        if groups.is_empty() && prefix.is_some() {
+            return self.list_log_groups(None).await;
         }
+        Ok(groups)
     }
-}
\ No newline at end of file
+}
+
+/// Explains `err` from a rejected `operation` on a log group of `class`:
+/// CloudWatch reports an operation Infrequent Access groups don't support as
+/// a bare parameter error.
+fn class_error(err: CwlError, log_group: &str, operation: &str, class: Option<&str>) -> CwlError {
+    match class {
+        Some(INFREQUENT_ACCESS) => CwlError::InvalidArgument(format!(
+            "{} is an Infrequent Access log group, which doesn't support {}; use a Standard class group for this ({})",
+            group_name(log_group),
+            operation,
+            err
+        )),
+        _ => err,
+    }
+}
+
+/// The error for a read that found no `log_group`, suggesting the closest of
+/// the `existing` groups. When the group does exist, something else was
+/// missing (GetLogEvents also reports a missing stream this way), so the
+/// original `message` stands.
+fn not_found_error(message: String, log_group: &str, region: &str, existing: &[String]) -> CwlError {
+    let name = group_name(log_group);
+    if existing.contains(&name) {
+        return CwlError::LogGroupNotFound(message);
+    }
+    CwlError::log_group_not_found(&name, region, existing)
+}
+
+/// Pulls the token out of an InvalidSequenceTokenException message such as
+/// "The given sequenceToken is invalid. The next expected sequenceToken is: 4961...".
+fn expected_sequence_token(message: Option<&str>) -> Option<String> {
+    let (_, token) = message?.rsplit_once("sequenceToken is:")?;
+    let token = token.trim();
+    (!token.is_empty() && token != "null").then(|| token.to_string())
+}
+
+/// Orders events oldest first by timestamp, with the ingestion time breaking
+/// ties within the same millisecond. FilterLogEvents interleaves streams as
+/// it scans them, so its pages aren't strictly in order. The sort is stable:
+/// events equal in both keep the order CloudWatch returned them in.
+pub fn sort_events(events: &mut [FilteredLogEvent]) {
+    events.sort_by_key(event_order);
+}
+
+fn event_order(event: &FilteredLogEvent) -> (i64, i64) {
+    (event.timestamp.unwrap_or(0), event.ingestion_time.unwrap_or(0))
+}
+
+/// Joins the results of fetching adjacent `ranges`. Neighbouring ranges
+/// share their boundary millisecond, so events stamped exactly on a boundary
+/// are fetched twice; only those are deduplicated, by event id. Repeated
+/// events anywhere else are kept.
+fn merge_chunks(chunks: Vec<Vec<FilteredLogEvent>>, ranges: &[(i64, i64)]) -> Vec<FilteredLogEvent> {
+    let boundaries: HashSet<i64> = ranges.iter().skip(1).map(|&(chunk_start, _)| chunk_start).collect();
+    let mut seen = HashSet::new();
+    chunks.into_iter()
+        .flatten()
+        .filter(|event| match event.timestamp {
+            Some(timestamp) if boundaries.contains(&timestamp) => seen.insert(match &event.event_id {
+                Some(id) => (Some(id.clone()), None, None),
+                None => (None, event.message.clone(), event.log_stream_name.clone()),
+            }),
+            _ => true,
+        })
+        .collect()
+}
+
+/// Adapts a per-fetch progress callback so several concurrent fetches report
+/// one combined total.
+fn accumulate<'a>(total: &'a AtomicUsize, progress: &'a impl Fn(usize)) -> impl FnMut(usize) + 'a {
+    let mut seen = 0;
+    move |count| {
+        let added = count - seen;
+        seen = count;
+        progress(total.fetch_add(added, Ordering::Relaxed) + added);
+    }
+}
+
+/// Splits `[start, end]` into at most `chunks` contiguous sub-ranges of
+/// roughly equal length. Adjacent ranges share their boundary timestamp.
+pub fn split_time_range(start: i64, end: i64, chunks: usize) -> Vec<(i64, i64)> {
+    let span = (end - start).max(0);
+    let chunks = (chunks.max(1) as i64).min(span.max(1));
+    let step = span / chunks;
+
+    (0..chunks)
+        .map(|i| {
+            let chunk_start = start + i * step;
+            let chunk_end = if i == chunks - 1 { end.max(start) } else { chunk_start + step };
+            (chunk_start, chunk_end)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_events_breaks_ties_by_ingestion_time() {
+        let event = |id: &str, timestamp, ingestion_time| FilteredLogEvent::builder()
+            .event_id(id)
+            .timestamp(timestamp)
+            .ingestion_time(ingestion_time)
+            .build();
+        let mut events = vec![event("c", 2_000, 2_100), event("b", 1_000, 1_500), event("a", 1_000, 1_200), event("d", 2_000, 2_100)];
+
+        sort_events(&mut events);
+        let ids: Vec<&str> = events.iter().filter_map(|event| event.event_id.as_deref()).collect();
+        assert_eq!(ids, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_stream_filter_matches() {
+        let names = vec!["web/1".to_string(), "web/2".to_string()];
+        assert!(StreamFilter::ALL.matches("anything"));
+        assert!(StreamFilter { names: &names, prefix: None }.matches("web/2"));
+        assert!(!StreamFilter { names: &names, prefix: None }.matches("web/3"));
+        assert!(StreamFilter { names: &[], prefix: Some("web/") }.matches("web/3"));
+        assert!(!StreamFilter { names: &[], prefix: Some("web/") }.matches("worker/1"));
+    }
+
+    #[test]
+    fn test_split_time_range() {
+        assert_eq!(split_time_range(0, 100, 4), vec![(0, 25), (25, 50), (50, 75), (75, 100)]);
+        assert_eq!(split_time_range(0, 10, 3), vec![(0, 3), (3, 6), (6, 10)]);
+        assert_eq!(split_time_range(5, 7, 8), vec![(5, 6), (6, 7)]);
+        assert_eq!(split_time_range(5, 5, 4), vec![(5, 5)]);
+    }
+
+    #[test]
+    fn test_merge_chunks_only_dedups_boundary_events() {
+        let event = |id: &str, timestamp| FilteredLogEvent::builder()
+            .event_id(id)
+            .timestamp(timestamp)
+            .message("OK")
+            .log_stream_name("web/1")
+            .build();
+        let ranges = split_time_range(0, 100, 2);
+        let chunks = vec![
+            vec![event("a", 10), event("a", 10), event("c", 50)],
+            vec![event("c", 50), event("d", 70)],
+        ];
+
+        let events = merge_chunks(chunks, &ranges);
+        let ids: Vec<&str> = events.iter().filter_map(|event| event.event_id.as_deref()).collect();
+        assert_eq!(ids, ["a", "a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_accumulate_combines_fetches() {
+        let total = AtomicUsize::new(0);
+        let reported = std::cell::Cell::new(0);
+        let report = |count| reported.set(count);
+
+        let mut first = accumulate(&total, &report);
+        let mut second = accumulate(&total, &report);
+        first(10);
+        second(5);
+        first(25);
+        assert_eq!(reported.get(), 30);
+    }
+
+    #[test]
+    fn test_class_error_explains_infrequent_access() {
+        let err = || CwlError::Aws("Parameter validation failed".to_string());
+
+        let explained = class_error(err(), "/app/archive", "FilterLogEvents", Some(INFREQUENT_ACCESS));
+        assert_eq!(explained.to_string(), "/app/archive is an Infrequent Access log group, which doesn't support FilterLogEvents; use a Standard class group for this (Parameter validation failed)");
+        assert_eq!(explained.exit_code(), CwlError::InvalidArgument(String::new()).exit_code());
+
+        let unchanged = class_error(err(), "/app/archive", "FilterLogEvents", Some("STANDARD"));
+        assert_eq!(unchanged.to_string(), "Parameter validation failed");
+        assert_eq!(unchanged.exit_code(), err().exit_code());
+        assert_eq!(class_error(err(), "/app/archive", "FilterLogEvents", None).to_string(), "Parameter validation failed");
+    }
+
+    #[test]
+    fn test_not_found_error_suggests_existing_groups() {
+        let existing = vec!["/app/api".to_string(), "/app/worker".to_string()];
+
+        // The group exists, so something else (like a stream) was missing
+        let err = not_found_error("stream not found".to_string(), "/app/api", "us-east-1", &existing);
+        assert_eq!(err.to_string(), "stream not found");
+
+        let err = not_found_error("group not found".to_string(), "/app/apj", "us-east-1", &existing);
+        assert!(err.to_string().starts_with("Log group '/app/apj' not found in region us-east-1; did you mean '/app/api'?"));
+        assert_eq!(err.exit_code(), 3);
+    }
+
+    #[test]
+    fn test_expected_sequence_token() {
+        let message = "The given sequenceToken is invalid. The next expected sequenceToken is: 49612345";
+        assert_eq!(expected_sequence_token(Some(message)), Some("49612345".to_string()));
+        assert_eq!(expected_sequence_token(Some("The next expected sequenceToken is: null")), None);
+        assert_eq!(expected_sequence_token(None), None);
+    }
+}
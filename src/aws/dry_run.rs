@@ -0,0 +1,105 @@
+use chrono_tz::Tz;
+use colored::Colorize;
+use crate::aws::arn::LogGroupArn;
+use crate::utils::output::OutputFormat;
+use crate::utils::time;
+use serde_json::{Map, Value};
+
+/// An API request resolved by `--dry-run` and printed instead of being sent.
+/// Created through `CloudWatchClient::dry_run`, which fills in the region and
+/// profile.
+pub struct DryRunRequest {
+    operation: &'static str,
+    region: String,
+    profile: Option<String>,
+    output: OutputFormat,
+    params: Vec<(&'static str, Value, Option<String>)>,
+}
+
+impl DryRunRequest {
+    pub(crate) fn new(operation: &'static str, region: String, profile: Option<String>, output: OutputFormat) -> Self {
+        Self {
+            operation,
+            region,
+            profile,
+            output,
+            params: Vec::new(),
+        }
+    }
+
+    /// Adds a request parameter. Absent (`None`) values are left out.
+    pub fn param(mut self, name: &'static str, value: impl Into<Value>) -> Self {
+        let value = value.into();
+        if !value.is_null() {
+            self.params.push((name, value, None));
+        }
+        self
+    }
+
+    /// Adds the target log group as `logGroupName`, or as
+    /// `logGroupIdentifier` for ARNs, the way the client sends it.
+    pub fn log_group(self, log_group: &str) -> Self {
+        match LogGroupArn::parse(log_group) {
+            Some(arn) => self.param("logGroupIdentifier", arn.identifier()),
+            None => self.param("logGroupName", log_group),
+        }
+    }
+
+    /// Adds an epoch-millisecond parameter, shown alongside its time in `tz`.
+    pub fn time(mut self, name: &'static str, millis: Option<i64>, tz: Tz) -> Self {
+        if let Some(millis) = millis {
+            let readable = time::format_timestamp(millis, tz, "%Y-%m-%d %H:%M:%S%.3f");
+            self.params.push((name, Value::from(millis), readable));
+        }
+        self
+    }
+
+    pub fn print(&self) {
+        if self.output.is_json() {
+            let mut params = Map::new();
+            for (name, value, readable) in &self.params {
+                params.insert(name.to_string(), value.clone());
+                if let Some(readable) = readable {
+                    params.insert(format!("{}Readable", name), Value::String(readable.clone()));
+                }
+            }
+            let request = serde_json::json!({
+                "operation": self.operation,
+                "region": self.region,
+                "profile": self.profile,
+                "params": params,
+            });
+            let rendered = match self.output {
+                OutputFormat::Ndjson => serde_json::to_string(&request),
+                _ => serde_json::to_string_pretty(&request),
+            };
+            println!("{}", rendered.unwrap_or_default());
+            return;
+        }
+
+        println!("{} {} {}",
+            "Dry run:".bright_magenta().bold(),
+            self.operation.bright_white().bold(),
+            "(not sent)".bright_black()
+        );
+
+        let profile = self.profile.as_deref().unwrap_or("(default)");
+        let mut rows: Vec<(&str, String)> = vec![("region", self.region.clone()), ("profile", profile.to_string())];
+        for (name, value, readable) in &self.params {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            rows.push((name, match readable {
+                Some(readable) => format!("{} ({})", value, readable),
+                None => value,
+            }));
+        }
+
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, value) in rows {
+            let label = format!("{:<width$}", format!("{}:", name), width = width + 1);
+            println!("  {}  {}", label.bright_blue(), value.bright_yellow());
+        }
+    }
+}
@@ -1 +1,4 @@
-pub mod client;
\ No newline at end of file
+pub mod client;
+pub mod dry_run;
+pub mod backend;
+pub mod arn;
\ No newline at end of file
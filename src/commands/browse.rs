@@ -0,0 +1,549 @@
+use anyhow::{anyhow, bail, Result};
+use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
+use chrono_tz::Tz;
+use crate::aws::client::{CloudWatchClient, PollInterval, StreamFilter};
+use crate::error::CwlError;
+use crate::utils::{format, json_formatter};
+use crate::utils::progress::ProgressMode;
+use crate::utils::time::{self, TimeFormat};
+use indicatif::{ProgressBar, ProgressStyle};
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::io::IsTerminal;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How often the screen is redrawn while no key is pressed and no live
+/// event arrives, so a live mode that stopped is noticed.
+const REFRESH: Duration = Duration::from_millis(200);
+/// Rows moved by PageUp and PageDown.
+const PAGE: isize = 20;
+
+pub struct BrowseOptions {
+    pub log_group: String,
+    pub since: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub default_since: Option<String>,
+    pub filter: Option<String>,
+    /// Load at most this many events, the newest
+    pub limit: usize,
+    pub tz: Tz,
+    pub time_format: TimeFormat,
+    pub progress: ProgressMode,
+    pub quiet: bool,
+}
+
+/// What the bottom line is currently editing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Input {
+    None,
+    Filter,
+    Search,
+}
+
+/// Requests from a key press that need more than the app state.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Quit,
+    ToggleLive,
+}
+
+struct App {
+    events: Vec<FilteredLogEvent>,
+    /// Indices into `events` of the ones matching `filter`
+    visible: Vec<usize>,
+    /// Case-insensitive text every listed event contains
+    filter: String,
+    search: String,
+    input: Input,
+    list: ListState,
+    live: bool,
+    /// Show the selected message as a hex dump
+    hex: bool,
+    /// Replaces the key help until the next key press
+    status: Option<String>,
+}
+
+impl App {
+    fn new(events: Vec<FilteredLogEvent>) -> Self {
+        let mut app = Self {
+            events,
+            visible: Vec::new(),
+            filter: String::new(),
+            search: String::new(),
+            input: Input::None,
+            list: ListState::default(),
+            live: false,
+            hex: false,
+            status: None,
+        };
+        app.refilter();
+        app
+    }
+
+    fn matches(event: &FilteredLogEvent, needle: &str) -> bool {
+        needle.is_empty()
+            || event.message.as_deref().is_some_and(|message| message.to_lowercase().contains(needle))
+            || event.log_stream_name.as_deref().is_some_and(|stream| stream.to_lowercase().contains(needle))
+    }
+
+    /// Rebuilds `visible` after the filter changed, keeping the selected
+    /// event selected when it still matches and the newest one otherwise.
+    fn refilter(&mut self) {
+        let selected = self.selected_index();
+        let needle = self.filter.to_lowercase();
+        self.visible = (0..self.events.len())
+            .filter(|&i| Self::matches(&self.events[i], &needle))
+            .collect();
+        let row = selected
+            .and_then(|selected| self.visible.iter().position(|&i| i == selected))
+            .or_else(|| self.visible.len().checked_sub(1));
+        self.list.select(row);
+    }
+
+    /// Appends a live event. The selection follows along when it was on
+    /// the newest event, like `tail -f`.
+    fn push(&mut self, event: FilteredLogEvent) {
+        let following = self.list.selected().is_none_or(|row| row + 1 >= self.visible.len());
+        let shown = Self::matches(&event, &self.filter.to_lowercase());
+        self.events.push(event);
+        if shown {
+            self.visible.push(self.events.len() - 1);
+            if following {
+                self.list.select(Some(self.visible.len() - 1));
+            }
+        }
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.list.selected().and_then(|row| self.visible.get(row).copied())
+    }
+
+    fn selected(&self) -> Option<&FilteredLogEvent> {
+        self.selected_index().map(|i| &self.events[i])
+    }
+
+    fn move_by(&mut self, rows: isize) {
+        let Some(last) = self.visible.len().checked_sub(1) else {
+            return;
+        };
+        let row = self.list.selected().unwrap_or(0).saturating_add_signed(rows).min(last);
+        self.list.select(Some(row));
+    }
+
+    /// Selects the next listed event (previous when `!forward`) containing
+    /// the search term, wrapping around at either end.
+    fn find(&mut self, forward: bool) {
+        if self.search.is_empty() || self.visible.is_empty() {
+            return;
+        }
+        let needle = self.search.to_lowercase();
+        let len = self.visible.len();
+        let current = self.list.selected().unwrap_or(0);
+        let found = (1..=len)
+            .map(|step| if forward { (current + step) % len } else { (current + len - step % len) % len })
+            .find(|&row| Self::matches(&self.events[self.visible[row]], &needle));
+        match found {
+            Some(row) => self.list.select(Some(row)),
+            None => self.status = Some(format!("No match for \"{}\"", self.search)),
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Some(Action::Quit);
+        }
+        self.status = None;
+
+        match self.input {
+            Input::Filter => match key.code {
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.refilter();
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.refilter();
+                }
+                KeyCode::Enter => self.input = Input::None,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.refilter();
+                    self.input = Input::None;
+                }
+                _ => {}
+            },
+            Input::Search => match key.code {
+                KeyCode::Char(c) => self.search.push(c),
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Enter => {
+                    self.input = Input::None;
+                    self.find(true);
+                }
+                KeyCode::Esc => {
+                    self.search.clear();
+                    self.input = Input::None;
+                }
+                _ => {}
+            },
+            Input::None => match key.code {
+                KeyCode::Char('q') => return Some(Action::Quit),
+                KeyCode::Esc if !self.filter.is_empty() => {
+                    self.filter.clear();
+                    self.refilter();
+                }
+                KeyCode::Esc => return Some(Action::Quit),
+                KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+                KeyCode::PageDown => self.move_by(PAGE),
+                KeyCode::PageUp => self.move_by(-PAGE),
+                KeyCode::Home | KeyCode::Char('g') => self.move_by(isize::MIN),
+                KeyCode::End | KeyCode::Char('G') => self.move_by(isize::MAX),
+                KeyCode::Char('f') => self.input = Input::Filter,
+                KeyCode::Char('/') => {
+                    self.search.clear();
+                    self.input = Input::Search;
+                }
+                KeyCode::Char('n') => self.find(true),
+                KeyCode::Char('N') => self.find(false),
+                KeyCode::Char('l') => return Some(Action::ToggleLive),
+                KeyCode::Char('x') => self.hex = !self.hex,
+                _ => {}
+            },
+        }
+        None
+    }
+}
+
+/// Restores the terminal however the browser exits, including when the
+/// `--timeout` drops it mid-loop.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+/// Opens a full-screen browser over a log group's events: a scrollable list
+/// with live filtering and search, and the selected event pretty-printed
+/// beside it. `l` keeps appending new events as they arrive.
+pub async fn run(client: CloudWatchClient, options: BrowseOptions) -> Result<()> {
+    let BrowseOptions {
+        log_group,
+        since,
+        start,
+        end,
+        default_since,
+        filter,
+        limit,
+        tz,
+        time_format,
+        progress,
+        quiet,
+    } = options;
+
+    let (start_time, end_time) = time::parse_time_range(since, start, end, default_since.as_deref())?;
+
+    if let Some(request) = client.dry_run("FilterLogEvents") {
+        request
+            .log_group(&log_group)
+            .time("startTime", start_time, tz)
+            .time("endTime", end_time, tz)
+            .param("filterPattern", filter)
+            .param("limit", Some(limit))
+            .print();
+        return Ok(());
+    }
+
+    if !std::io::stdout().is_terminal() {
+        bail!(CwlError::InvalidArgument(
+            "browse needs an interactive terminal; use `cwl query` to print events".to_string()
+        ));
+    }
+
+    let spinner = progress.bar(quiet, "Fetching log events...", || ProgressBar::new_spinner().with_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap()
+    ));
+    let events = client.get_newest_log_events(&log_group, start_time, end_time, filter.as_deref(), limit, 1, |_| {}).await?;
+    spinner.finish_and_clear();
+
+    let view = View { log_group, filter, tz, time_format };
+    let mut terminal = ratatui::init();
+    let _guard = TerminalGuard;
+    browse(&mut terminal, App::new(events), &client, &view).await
+}
+
+/// How events are rendered, and what live mode follows.
+struct View {
+    log_group: String,
+    filter: Option<String>,
+    tz: Tz,
+    time_format: TimeFormat,
+}
+
+async fn browse(terminal: &mut DefaultTerminal, mut app: App, client: &CloudWatchClient, view: &View) -> Result<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut live: Option<JoinHandle<Result<()>>> = None;
+    // Read keys without blocking the runtime the live tail runs on
+    let mut keys = EventStream::new();
+
+    loop {
+        while let Ok(event) = receiver.try_recv() {
+            app.push(event);
+        }
+        if let Some(handle) = live.take_if(|handle| handle.is_finished()) {
+            app.live = false;
+            if let Ok(Err(err)) = handle.await {
+                app.status = Some(format!("Live mode stopped: {:#}", err));
+            }
+        }
+
+        terminal.draw(|frame| draw(frame, &mut app, view))?;
+
+        let key = tokio::select! {
+            key = keys.next() => match key {
+                Some(Ok(Event::Key(key))) => key,
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => return Err(err.into()),
+                None => break,
+            },
+            Some(event) = receiver.recv() => {
+                app.push(event);
+                continue;
+            }
+            _ = tokio::time::sleep(REFRESH) => continue,
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match app.handle_key(key) {
+            Some(Action::Quit) => break,
+            Some(Action::ToggleLive) => match live.take() {
+                Some(handle) => {
+                    handle.abort();
+                    app.live = false;
+                }
+                None => {
+                    let from = app.events.last()
+                        .and_then(|event| event.timestamp)
+                        .map_or_else(|| chrono::Utc::now().timestamp_millis(), |timestamp| timestamp + 1);
+                    let client = client.clone();
+                    let sender = sender.clone();
+                    let log_group = view.log_group.clone();
+                    let filter = view.filter.clone();
+                    live = Some(tokio::spawn(async move {
+                        client.tail_log_events(&log_group, filter.as_deref(), StreamFilter::ALL, Some(from), PollInterval::default(), |event| {
+                            sender.send(event).map_err(|_| anyhow!("Browser closed"))
+                        }).await
+                    }));
+                    app.live = true;
+                }
+            },
+            None => {}
+        }
+    }
+
+    if let Some(handle) = live {
+        handle.abort();
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, app: &mut App, view: &View) {
+    let [main, bottom] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let [list_area, detail_area] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(main);
+
+    let items: Vec<ListItem> = app.visible.iter()
+        .map(|&i| ListItem::new(view.row(&app.events[i])))
+        .collect();
+    let title = format!(" {} ({} of {} events) ",
+        view.log_group,
+        format::format_count(app.visible.len()),
+        format::format_count(app.events.len())
+    );
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().reversed());
+    frame.render_stateful_widget(list, list_area, &mut app.list);
+
+    let detail = app.selected().map(|event| view.detail(event, app.hex)).unwrap_or_default();
+    frame.render_widget(
+        Paragraph::new(detail).wrap(Wrap { trim: false }).block(Block::bordered().title(" Event ")),
+        detail_area
+    );
+
+    let status = match app.input {
+        Input::Filter => Line::from(vec![Span::from("filter: ").yellow(), Span::from(app.filter.as_str())]),
+        Input::Search => Line::from(vec![Span::from("/").yellow(), Span::from(app.search.as_str())]),
+        Input::None => status_line(app),
+    };
+    if app.input != Input::None {
+        let x = bottom.x.saturating_add(status.width() as u16).min(bottom.right().saturating_sub(1));
+        frame.set_cursor_position((x, bottom.y));
+    }
+    frame.render_widget(Paragraph::new(status), bottom);
+}
+
+fn status_line(app: &App) -> Line<'_> {
+    let mut spans = Vec::new();
+    if app.live {
+        spans.push(Span::from("● live ").green().bold());
+    }
+    if !app.filter.is_empty() {
+        spans.push(Span::from(format!("filter: {} ", app.filter)).yellow());
+    }
+    match &app.status {
+        Some(status) => spans.push(Span::from(status.as_str()).red()),
+        None => spans.push(Span::from(
+            "q quit · ↑↓ move · f filter · / search · n/N next/prev · l live · x hex · Esc clear filter"
+        ).dark_gray()),
+    }
+    Line::from(spans)
+}
+
+impl View {
+    fn row(&self, event: &FilteredLogEvent) -> Line<'static> {
+        let timestamp = self.time_format.format(event.timestamp.unwrap_or(0), self.tz).unwrap_or_default();
+        let stream = event.log_stream_name.clone().unwrap_or_default();
+        let color = tui_color(format::stream_color(&stream));
+        let message = format::sanitize_for_terminal(&format::strip_ansi(event.message.as_deref().unwrap_or("")));
+        let first_line = message.lines().next().unwrap_or("").to_string();
+        Line::from(vec![
+            Span::from(timestamp).blue(),
+            Span::from(" "),
+            Span::styled(stream, Style::new().fg(color)),
+            Span::from(" "),
+            Span::from(first_line),
+        ])
+    }
+
+    /// Metadata lines, then the message: as a hex dump with `hex`,
+    /// pretty-printed when it holds a JSON object or array, as is otherwise.
+    fn detail(&self, event: &FilteredLogEvent, hex: bool) -> Text<'static> {
+        let timestamp = event.timestamp.unwrap_or(0);
+        let stream = event.log_stream_name.clone().unwrap_or_default();
+        let mut lines = vec![
+            Line::from(vec![
+                Span::from("Time:     ").bold(),
+                Span::from(self.time_format.format(timestamp, self.tz).unwrap_or_default()).blue(),
+            ]),
+            Line::from(vec![
+                Span::from("Stream:   ").bold(),
+                Span::styled(stream.clone(), Style::new().fg(tui_color(format::stream_color(&stream)))),
+            ]),
+        ];
+        if let Some(ingested) = event.ingestion_time {
+            lines.push(Line::from(vec![
+                Span::from("Ingested: ").bold(),
+                Span::from(format!("+{}", format::format_delay(ingested - timestamp))),
+            ]));
+        }
+        lines.push(Line::default());
+
+        if hex {
+            let bytes = event.message.as_deref().unwrap_or("").as_bytes();
+            lines.extend(format::hexdump(bytes).into_iter().map(Line::from));
+            return Text::from(lines);
+        }
+
+        let message = format::sanitize_for_terminal(&format::strip_ansi(event.message.as_deref().unwrap_or("").trim_end()));
+        let body = json_formatter::parse_json_payload(&message)
+            .filter(|value| value.is_object() || value.is_array())
+            .and_then(|value| serde_json::to_string_pretty(&value).ok())
+            .unwrap_or(message);
+        lines.extend(body.lines().map(|line| Line::from(line.to_string())));
+        Text::from(lines)
+    }
+}
+
+/// The ratatui equivalent of a `colored` color, so streams get the same
+/// colors as in `query` and `tail`.
+fn tui_color(color: colored::Color) -> Color {
+    match color {
+        colored::Color::Black => Color::Black,
+        colored::Color::Red => Color::Red,
+        colored::Color::Green => Color::Green,
+        colored::Color::Yellow => Color::Yellow,
+        colored::Color::Blue => Color::Blue,
+        colored::Color::Magenta => Color::Magenta,
+        colored::Color::Cyan => Color::Cyan,
+        colored::Color::White => Color::Gray,
+        colored::Color::BrightBlack => Color::DarkGray,
+        colored::Color::BrightRed => Color::LightRed,
+        colored::Color::BrightGreen => Color::LightGreen,
+        colored::Color::BrightYellow => Color::LightYellow,
+        colored::Color::BrightBlue => Color::LightBlue,
+        colored::Color::BrightMagenta => Color::LightMagenta,
+        colored::Color::BrightCyan => Color::LightCyan,
+        colored::Color::BrightWhite => Color::White,
+        colored::Color::TrueColor { r, g, b } => Color::Rgb(r, g, b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: i64, stream: &str, message: &str) -> FilteredLogEvent {
+        FilteredLogEvent::builder().timestamp(timestamp).log_stream_name(stream).message(message).build()
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            app.handle_key(key(KeyCode::Char(c)));
+        }
+    }
+
+    #[test]
+    fn test_filter_search_and_live_append() {
+        let mut app = App::new(vec![
+            event(1, "web", "GET /health 200"),
+            event(2, "worker", "ERROR job failed"),
+            event(3, "web", "GET /users 500"),
+        ]);
+        assert_eq!(app.selected_index(), Some(2));
+
+        app.handle_key(key(KeyCode::Char('f')));
+        type_text(&mut app, "web");
+        assert_eq!(app.visible, [0, 2]);
+        assert_eq!(app.selected_index(), Some(2));
+        app.handle_key(key(KeyCode::Enter));
+
+        // Events that don't match the filter are kept but not listed
+        app.push(event(4, "worker", "ERROR again"));
+        app.push(event(5, "web", "GET / 200"));
+        assert_eq!(app.visible, [0, 2, 4]);
+        assert_eq!(app.selected_index(), Some(4));
+
+        app.handle_key(key(KeyCode::Esc));
+        assert_eq!(app.visible.len(), 5);
+        app.handle_key(key(KeyCode::Char('/')));
+        type_text(&mut app, "error");
+        app.handle_key(key(KeyCode::Enter));
+        assert_eq!(app.selected_index(), Some(1));
+        app.handle_key(key(KeyCode::Char('n')));
+        assert_eq!(app.selected_index(), Some(3));
+        app.handle_key(key(KeyCode::Char('N')));
+        assert_eq!(app.selected_index(), Some(1));
+
+        assert_eq!(app.handle_key(key(KeyCode::Char('l'))), Some(Action::ToggleLive));
+        assert_eq!(app.handle_key(key(KeyCode::Char('q'))), Some(Action::Quit));
+    }
+}
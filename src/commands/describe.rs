@@ -0,0 +1,130 @@
+use anyhow::Result;
+use colored::Colorize;
+use chrono::{DateTime, Utc};
+use crate::aws::client::{CloudWatchClient, LogStreamInfo};
+use crate::utils::format::{self, visible_width};
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Streams count as active with --active when they received events this
+/// recently.
+const ACTIVE_WINDOW_MS: i64 = 3_600_000;
+
+pub async fn run(
+    client: CloudWatchClient,
+    log_group: String,
+    limit: Option<usize>,
+    active: bool,
+    quiet: bool,
+) -> Result<()> {
+    if let Some(request) = client.dry_run("DescribeLogStreams") {
+        request
+            .log_group(&log_group)
+            .param("orderBy", "LastEventTime")
+            .param("descending", true)
+            .param("limit", limit)
+            .param("activeWithin", active.then_some("1h"))
+            .print();
+        return Ok(());
+    }
+
+    let spinner = if quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap()
+    );
+    spinner.set_message("Loading log streams...");
+
+    let active_since = active.then(|| Utc::now().timestamp_millis() - ACTIVE_WINDOW_MS);
+    let streams = client.describe_log_streams(&log_group, limit, active_since).await?;
+
+    spinner.finish_and_clear();
+
+    if quiet {
+        for stream in &streams {
+            println!("{}", stream.name);
+        }
+        return Ok(());
+    }
+
+    println!("{} {}",
+        "Log group:".bright_blue().bold(),
+        log_group.bright_yellow()
+    );
+
+    if streams.is_empty() {
+        println!("{}", if active {
+            "No streams with events in the last hour".yellow()
+        } else {
+            "No log streams found".yellow()
+        });
+        return Ok(());
+    }
+
+    println!("{} {} streams:\n",
+        "Found".bright_green().bold(),
+        streams.len().to_string().bright_yellow().bold()
+    );
+
+    for line in render_table(&streams) {
+        println!("{}", line);
+    }
+
+    println!("\n{} Use {} to follow the most recent activity",
+        "Tip:".bright_magenta().bold(),
+        format!("cwl tail {} --follow", log_group).bright_white().italic()
+    );
+
+    Ok(())
+}
+
+fn format_time(ts: Option<i64>) -> String {
+    ts.and_then(DateTime::<Utc>::from_timestamp_millis)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn render_table(streams: &[LogStreamInfo]) -> Vec<String> {
+    let rows: Vec<[String; 4]> = streams.iter()
+        .map(|stream| [
+            stream.name.clone(),
+            format_time(stream.last_event_time),
+            stream.stored_bytes.map(format::format_bytes).unwrap_or_else(|| "-".to_string()),
+            format_time(stream.creation_time),
+        ])
+        .collect();
+
+    let headers = ["STREAM", "LAST EVENT", "STORED", "CREATED"];
+    let mut widths = headers.map(str::len);
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(visible_width(value));
+        }
+    }
+
+    let pad = |value: &str, width: usize| {
+        format!("{}{}", value, " ".repeat(width.saturating_sub(visible_width(value))))
+    };
+
+    let mut lines = Vec::new();
+    lines.push(headers.iter()
+        .zip(widths)
+        .map(|(h, w)| pad(&h.bright_cyan().bold().to_string(), w))
+        .collect::<Vec<_>>()
+        .join("  "));
+
+    for row in &rows {
+        lines.push(format!("{}  {}  {}  {}",
+            pad(&row[0].bright_white().to_string(), widths[0]),
+            pad(&row[1].bright_blue().to_string(), widths[1]),
+            pad(&row[2], widths[2]),
+            row[3].bright_black()
+        ));
+    }
+
+    lines
+}
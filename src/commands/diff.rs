@@ -0,0 +1,227 @@
+use anyhow::{Result, Context};
+use chrono_tz::Tz;
+use colored::Colorize;
+use crate::aws::client::{CloudWatchClient, StreamFilter};
+use crate::utils::format::{self, visible_width};
+use crate::utils::time;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+
+pub struct DiffOptions {
+    pub log_group: String,
+    /// `START..END`, see `time::parse_window`
+    pub window_a: String,
+    pub window_b: String,
+    pub filter: Option<String>,
+    /// Only show this many patterns, largest changes first
+    pub limit: Option<usize>,
+    /// Also list patterns whose count didn't change
+    pub all: bool,
+    pub tz: Tz,
+    pub quiet: bool,
+}
+
+/// A message shape with its event count in each window.
+#[derive(Debug, PartialEq)]
+struct PatternDiff {
+    pattern: String,
+    count_a: usize,
+    count_b: usize,
+}
+
+impl PatternDiff {
+    fn delta(&self) -> i64 {
+        self.count_b as i64 - self.count_a as i64
+    }
+}
+
+/// Compares the message shapes of two time windows, e.g. before and after a
+/// deploy, to surface new, vanished and changed kinds of messages.
+pub async fn run(client: CloudWatchClient, options: DiffOptions) -> Result<()> {
+    let DiffOptions { log_group, window_a, window_b, filter, limit, all, tz, quiet } = options;
+
+    let (start_a, end_a) = time::parse_window(&window_a).context("Invalid --window-a")?;
+    let (start_b, end_b) = time::parse_window(&window_b).context("Invalid --window-b")?;
+
+    if let Some(request) = client.dry_run("FilterLogEvents") {
+        request
+            .log_group(&log_group)
+            .time("windowAStart", Some(start_a), tz)
+            .time("windowAEnd", Some(end_a), tz)
+            .time("windowBStart", Some(start_b), tz)
+            .time("windowBEnd", Some(end_b), tz)
+            .param("filterPattern", filter)
+            .print();
+        return Ok(());
+    }
+
+    let spinner = if quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap()
+    );
+
+    spinner.set_message("Fetching window A...");
+    let events_a = client.get_log_events(&log_group, Some(start_a), Some(end_a), filter.as_deref(), StreamFilter::ALL, None, false).await?;
+    spinner.set_message("Fetching window B...");
+    let events_b = client.get_log_events(&log_group, Some(start_b), Some(end_b), filter.as_deref(), StreamFilter::ALL, None, false).await?;
+    spinner.finish_and_clear();
+
+    let messages_a: Vec<&str> = events_a.iter().filter_map(|e| e.message.as_deref()).collect();
+    let messages_b: Vec<&str> = events_b.iter().filter_map(|e| e.message.as_deref()).collect();
+    let mut diffs = diff_patterns(&messages_a, &messages_b);
+    if !all {
+        diffs.retain(|diff| diff.delta() != 0);
+    }
+    let total = diffs.len();
+    diffs.truncate(limit.unwrap_or(usize::MAX));
+
+    if quiet {
+        for diff in &diffs {
+            println!("{}\t{}\t{}\t{:+}", diff.pattern, diff.count_a, diff.count_b, diff.delta());
+        }
+        return Ok(());
+    }
+
+    let describe = |start, end| format!("{} → {}",
+        time::format_timestamp(start, tz, "%Y-%m-%d %H:%M:%S").unwrap_or_default(),
+        time::format_timestamp(end, tz, "%Y-%m-%d %H:%M:%S").unwrap_or_default()
+    );
+    println!("{} {}", "Log group:".bright_blue().bold(), log_group.bright_yellow());
+    println!("{} {} ({} events)", "Window A:".bright_blue().bold(), describe(start_a, end_a), events_a.len());
+    println!("{} {} ({} events)\n", "Window B:".bright_blue().bold(), describe(start_b, end_b), events_b.len());
+
+    if diffs.is_empty() {
+        println!("{}", "No differences in message patterns between the windows".yellow());
+        return Ok(());
+    }
+
+    for line in render_table(&diffs, format::terminal_width()) {
+        println!("{}", line);
+    }
+
+    if diffs.len() < total {
+        println!("\n{} Showing {} of {} patterns (--limit)",
+            "Note:".bright_magenta().bold(),
+            diffs.len(),
+            total
+        );
+    }
+
+    Ok(())
+}
+
+/// Counts each normalized message shape in both windows. Patterns are
+/// ordered by the size of their change, largest first.
+fn diff_patterns(messages_a: &[&str], messages_b: &[&str]) -> Vec<PatternDiff> {
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for message in messages_a {
+        counts.entry(format::normalize_message(message)).or_default().0 += 1;
+    }
+    for message in messages_b {
+        counts.entry(format::normalize_message(message)).or_default().1 += 1;
+    }
+
+    let mut diffs: Vec<PatternDiff> = counts.into_iter()
+        .map(|(pattern, (count_a, count_b))| PatternDiff { pattern, count_a, count_b })
+        .collect();
+    diffs.sort_by(|a, b| {
+        b.delta().abs().cmp(&a.delta().abs())
+            .then_with(|| (b.count_a + b.count_b).cmp(&(a.count_a + a.count_b)))
+            .then_with(|| a.pattern.cmp(&b.pattern))
+    });
+    diffs
+}
+
+fn render_table(diffs: &[PatternDiff], width: usize) -> Vec<String> {
+    let rows: Vec<[String; 4]> = diffs.iter()
+        .map(|diff| [
+            diff.pattern.clone(),
+            diff.count_a.to_string(),
+            diff.count_b.to_string(),
+            match (diff.count_a, diff.count_b) {
+                (0, _) => "new".to_string(),
+                (_, 0) => "gone".to_string(),
+                _ => format!("{:+}", diff.delta()),
+            },
+        ])
+        .collect();
+
+    let headers = ["PATTERN", "A", "B", "DELTA"];
+    let mut widths = headers.map(str::len);
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row).skip(1) {
+            *width = (*width).max(visible_width(value));
+        }
+    }
+    let counts_width = widths[1] + widths[2] + widths[3] + 6;
+    widths[0] = rows.iter()
+        .map(|row| visible_width(&row[0]))
+        .max()
+        .unwrap_or(0)
+        .clamp(headers[0].len(), width.saturating_sub(counts_width).max(20));
+
+    let pad = |value: &str, width: usize| {
+        format!("{}{}", value, " ".repeat(width.saturating_sub(visible_width(value))))
+    };
+    let pad_left = |value: &str, width: usize| {
+        format!("{}{}", " ".repeat(width.saturating_sub(visible_width(value))), value)
+    };
+
+    let mut lines = vec![format!("{}  {}  {}  {}",
+        pad(&headers[0].bright_cyan().bold().to_string(), widths[0]),
+        pad_left(&headers[1].bright_cyan().bold().to_string(), widths[1]),
+        pad_left(&headers[2].bright_cyan().bold().to_string(), widths[2]),
+        pad_left(&headers[3].bright_cyan().bold().to_string(), widths[3])
+    )];
+
+    for (diff, row) in diffs.iter().zip(&rows) {
+        let pattern = truncate(&row[0], widths[0]);
+        let delta = pad_left(&row[3], widths[3]);
+        let delta = match (diff.count_a, diff.count_b) {
+            (0, _) => delta.bright_red().bold(),
+            (_, 0) => delta.bright_green().bold(),
+            _ if diff.delta() > 0 => delta.yellow(),
+            _ if diff.delta() < 0 => delta.green(),
+            _ => delta.bright_black(),
+        };
+        lines.push(format!("{}  {}  {}  {}",
+            pad(&pattern, widths[0]),
+            pad_left(&row[1], widths[1]),
+            pad_left(&row[2], widths[2]),
+            delta
+        ));
+    }
+
+    lines
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_patterns_groups_shapes_and_orders_by_change() {
+        let before = ["GET /users/1 200", "GET /users/2 200", "cache warm"];
+        let after = ["GET /users/3 200", "DB timeout after 3000ms", "DB timeout after 2950ms", "cache warm"];
+
+        let diffs = diff_patterns(&before, &after);
+        assert_eq!(diffs[0], PatternDiff { pattern: "DB timeout after <num>ms".to_string(), count_a: 0, count_b: 2 });
+        assert_eq!(diffs[1], PatternDiff { pattern: "GET /users/<num> <num>".to_string(), count_a: 2, count_b: 1 });
+        assert_eq!(diffs[2].delta(), 0);
+    }
+}
@@ -0,0 +1,307 @@
+use anyhow::{Result, Context, bail};
+use colored::Colorize;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use crate::aws::client::{CloudWatchClient, StreamFilter};
+use crate::error::CwlError;
+use crate::utils::{format, time};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub struct ExportOptions {
+    pub log_group: String,
+    pub path: PathBuf,
+    pub since: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub default_since: Option<String>,
+    pub filter: Option<String>,
+    /// Write one file per time bucket of this length instead of one file
+    pub split_by: Option<String>,
+    pub gzip: bool,
+    pub compression_level: u32,
+    /// Time zone of the times shown by `--dry-run`; exported lines are UTC
+    pub tz: Tz,
+    pub quiet: bool,
+}
+
+/// Destination for exported lines, optionally gzip-compressed.
+enum ExportWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl ExportWriter {
+    fn create(path: &Path, gzip: bool, level: u32) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create export file: {}", path.display()))?;
+        let writer = BufWriter::new(file);
+
+        Ok(if gzip {
+            ExportWriter::Gzip(GzEncoder::new(writer, Compression::new(level)))
+        } else {
+            ExportWriter::Plain(writer)
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        match self {
+            ExportWriter::Plain(w) => writeln!(w, "{}", line),
+            ExportWriter::Gzip(w) => writeln!(w, "{}", line),
+        }
+    }
+
+    /// Flushes buffered data and, for gzip, writes the trailer. Skipping this
+    /// leaves a truncated archive.
+    fn finish(self) -> std::io::Result<()> {
+        let mut writer = match self {
+            ExportWriter::Plain(w) => w,
+            ExportWriter::Gzip(w) => w.finish()?,
+        };
+        writer.flush()
+    }
+}
+
+pub async fn run(client: CloudWatchClient, options: ExportOptions) -> Result<()> {
+    let ExportOptions {
+        log_group,
+        path,
+        since,
+        start,
+        end,
+        default_since,
+        filter,
+        split_by,
+        gzip,
+        compression_level,
+        tz,
+        quiet,
+    } = options;
+
+    let gzip = gzip || path.extension().is_some_and(|ext| ext == "gz");
+    let (start_time, end_time) = time::parse_time_range(since, start, end, default_since.as_deref())?;
+    let split_ms = split_by.as_deref().map(parse_split).transpose()?;
+
+    if let Some(request) = client.dry_run("FilterLogEvents") {
+        request
+            .log_group(&log_group)
+            .time("startTime", start_time, tz)
+            .time("endTime", end_time, tz)
+            .param("filterPattern", filter)
+            .param("path", path.display().to_string())
+            .param("gzip", gzip)
+            .param("splitBy", split_by)
+            .print();
+        return Ok(());
+    }
+
+    if !quiet {
+        println!("{} {} {} {}",
+            "Exporting logs from:".bright_blue().bold(),
+            log_group.bright_yellow(),
+            "to".bright_blue().bold(),
+            path.display().to_string().bright_yellow()
+        );
+    }
+
+    let open = |bucket: Option<i64>| -> Result<(ExportWriter, ExportedFile)> {
+        let file_path = match (split_ms, bucket) {
+            (Some(split_ms), Some(bucket)) => split_path(&path, &bucket_label(bucket, split_ms)),
+            _ => path.clone(),
+        };
+        Ok((ExportWriter::create(&file_path, gzip, compression_level)?, ExportedFile::new(file_path)))
+    };
+    // Split exports open a file per bucket as events reach it
+    let mut current = match split_ms {
+        Some(_) => None,
+        None => Some(open(None)?),
+    };
+    let mut files = Vec::new();
+    let mut bucket = None;
+
+    let spinner = if quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap()
+    );
+    spinner.set_message("Fetching log events...");
+
+    let events = client.get_log_events(
+        &log_group,
+        start_time,
+        end_time,
+        filter.as_deref(),
+        StreamFilter::ALL,
+        None,
+        true,
+    ).await?;
+
+    spinner.set_message("Writing export...");
+
+    for event in &events {
+        let Some(ref message) = event.message else {
+            continue;
+        };
+
+        if let Some(split_ms) = split_ms {
+            // Events are sorted, so each bucket is written in one go
+            let event_bucket = event.timestamp.map(|ts| ts - ts.rem_euclid(split_ms)).or(bucket).unwrap_or(0);
+            if bucket != Some(event_bucket) {
+                if let Some((writer, file)) = current.take() {
+                    files.push(file.finish(writer)?);
+                }
+                bucket = Some(event_bucket);
+            }
+        }
+
+        let timestamp = event.timestamp
+            .and_then(DateTime::<Utc>::from_timestamp_millis)
+            .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+            .unwrap_or_else(|| "Unknown time".to_string());
+        let stream = event.log_stream_name.as_deref().unwrap_or("unknown");
+
+        let line = format!("[{}] [{}] {}", timestamp, stream, message.trim_end());
+        let (writer, file) = match current {
+            Some(ref mut current) => current,
+            None => current.insert(open(bucket)?),
+        };
+        writer.write_line(&line)
+            .with_context(|| format!("Failed to write to {}", file.path.display()))?;
+
+        file.events += 1;
+        file.uncompressed_bytes += line.len() + 1;
+    }
+
+    if let Some((writer, file)) = current {
+        files.push(file.finish(writer)?);
+    }
+    spinner.finish_and_clear();
+
+    if quiet {
+        return Ok(());
+    }
+
+    match files.as_slice() {
+        [] => println!("\n{}", "No log events found; no files written".yellow()),
+        [file] if split_ms.is_none() => {
+            if gzip {
+                println!("\n{} Exported {} events ({} uncompressed, {} compressed)",
+                    "✓".bright_green().bold(),
+                    file.events.to_string().bright_yellow(),
+                    format::format_bytes(file.uncompressed_bytes as i64),
+                    format::format_bytes(file.written_bytes).bright_yellow()
+                );
+            } else {
+                println!("\n{} Exported {} events ({})",
+                    "✓".bright_green().bold(),
+                    file.events.to_string().bright_yellow(),
+                    format::format_bytes(file.written_bytes).bright_yellow()
+                );
+            }
+        }
+        _ => {
+            println!("\n{} Exported {} events to {} files",
+                "✓".bright_green().bold(),
+                files.iter().map(|file| file.events).sum::<usize>().to_string().bright_yellow(),
+                files.len().to_string().bright_yellow()
+            );
+            for file in &files {
+                println!("  {} ({} events, {})",
+                    file.path.display(),
+                    format::format_count(file.events),
+                    format::format_bytes(file.written_bytes).bright_yellow()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A file written by the export, with what went into it.
+struct ExportedFile {
+    path: PathBuf,
+    events: usize,
+    uncompressed_bytes: usize,
+    /// Size on disk, known once the file is finished
+    written_bytes: i64,
+}
+
+impl ExportedFile {
+    fn new(path: PathBuf) -> Self {
+        Self { path, events: 0, uncompressed_bytes: 0, written_bytes: 0 }
+    }
+
+    fn finish(mut self, writer: ExportWriter) -> Result<Self> {
+        writer.finish()
+            .with_context(|| format!("Failed to finish writing {}", self.path.display()))?;
+        self.written_bytes = std::fs::metadata(&self.path)
+            .map(|m| m.len() as i64)
+            .unwrap_or(0);
+        Ok(self)
+    }
+}
+
+/// Parses `--split-by`, which has to be a whole number of seconds so each
+/// bucket gets a distinct file name.
+fn parse_split(split_by: &str) -> Result<i64> {
+    let millis = time::parse_duration(split_by).context("Invalid --split-by")?.num_milliseconds();
+    if millis < 1000 || millis % 1000 != 0 {
+        bail!(CwlError::InvalidArgument(format!("--split-by must be a whole number of seconds, got '{}'", split_by)));
+    }
+    Ok(millis)
+}
+
+/// Names a bucket by its UTC start, only as precisely as the bucket size
+/// needs: `2024-01-01` for days, `2024-01-01T13` for hours and so on.
+fn bucket_label(bucket: i64, split_ms: i64) -> String {
+    let format = match split_ms {
+        ms if ms % 86_400_000 == 0 => "%Y-%m-%d",
+        ms if ms % 3_600_000 == 0 => "%Y-%m-%dT%H",
+        ms if ms % 60_000 == 0 => "%Y-%m-%dT%H%M",
+        _ => "%Y-%m-%dT%H%M%S",
+    };
+    DateTime::<Utc>::from_timestamp_millis(bucket)
+        .map(|dt| dt.format(format).to_string())
+        .unwrap_or_else(|| bucket.to_string())
+}
+
+/// Inserts the bucket label before the extensions: `logs.ndjson.gz` becomes
+/// `logs-2024-01-01.ndjson.gz`.
+fn split_path(path: &Path, label: &str) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let (stem, extensions) = match name.get(1..).and_then(|rest| rest.find('.')) {
+        Some(dot) => name.split_at(dot + 1),
+        None => (name.as_str(), ""),
+    };
+    path.with_file_name(format!("{}-{}{}", stem, label, extensions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_files_are_named_by_bucket() {
+        let day = parse_split("1d").unwrap();
+        let timestamp: i64 = 1_704_153_600_000 + 5 * 3_600_000; // 2024-01-02 05:00 UTC
+        let bucket = timestamp - timestamp.rem_euclid(day);
+        assert_eq!(bucket_label(bucket, day), "2024-01-02");
+        assert_eq!(bucket_label(timestamp, parse_split("6h").unwrap()), "2024-01-02T05");
+        assert_eq!(bucket_label(timestamp, parse_split("90s").unwrap()), "2024-01-02T050000");
+        assert!(parse_split("1500ms").is_err());
+
+        assert_eq!(split_path(Path::new("out/logs.ndjson.gz"), "2024-01-02"), Path::new("out/logs-2024-01-02.ndjson.gz"));
+        assert_eq!(split_path(Path::new("logs"), "2024-01-02"), Path::new("logs-2024-01-02"));
+        assert_eq!(split_path(Path::new(".logs.txt"), "2024-01-02"), Path::new(".logs-2024-01-02.txt"));
+    }
+}
@@ -0,0 +1,313 @@
+use anyhow::{Result, Context, bail};
+use colored::Colorize;
+use crate::error::CwlError;
+use crate::utils::{format, json_formatter};
+use crate::utils::filter::Grep;
+use crate::utils::output::{self, OutputFormat};
+use crate::utils::tee::TeeWriter;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, IsTerminal};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+
+#[derive(Default)]
+pub struct FormatOptions {
+    /// File to read; stdin when `None`. `.gz` files are decompressed.
+    pub path: Option<PathBuf>,
+    pub grep: Option<String>,
+    pub invert_match: bool,
+    pub regex_options: format::RegexOptions,
+    pub highlight_style: format::HighlightStyle,
+    pub formatted: bool,
+    pub format_hint: json_formatter::FormatHint,
+    /// Levels of nested JSON split into their own table columns (default
+    /// `DEFAULT_FLATTEN_DEPTH`)
+    pub flatten_depth: Option<usize>,
+    pub lambda: bool,
+    /// Condense embedded metric format documents into metric columns
+    pub emf: bool,
+    pub pin: Vec<String>,
+    /// Drop table columns present in fewer than this percentage of rows
+    pub min_frequency: Option<f64>,
+    pub max_width: Option<usize>,
+    /// Per-column width caps (`--col-width`)
+    pub col_width: Vec<json_formatter::ColumnWidth>,
+    pub no_truncate: bool,
+    pub no_level_color: bool,
+    /// Keep multi-line messages on one line instead of indenting the rest
+    pub no_wrap: bool,
+    pub output: OutputFormat,
+    pub quiet: bool,
+}
+
+/// One input line: `[timestamp] [stream] message` as written by `export`
+/// (and `query --output-file`), or a bare message such as a JSON object.
+struct InputLine {
+    timestamp: String,
+    stream: String,
+    message: String,
+}
+
+impl InputLine {
+    fn parse(line: &str) -> Self {
+        let (timestamp, stream, message) = json_formatter::parse_log_line(line);
+        Self { timestamp, stream, message }
+    }
+
+    /// The line as an event for JSON and YAML output.
+    fn into_record(self) -> Value {
+        serde_json::json!({
+            "timestamp": self.timestamp,
+            "stream": self.stream,
+            "message": format::strip_ansi(&self.message),
+        })
+    }
+}
+
+/// Opens `path`, decompressing `.gz` files, or stdin when `None`.
+fn open_input(path: Option<&Path>) -> Result<Box<dyn BufRead>> {
+    let Some(path) = path else {
+        return Ok(Box::new(std::io::stdin().lock()));
+    };
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Reads the events of `reader`, skipping blank lines and joining
+/// multi-line messages; `source` names it in read errors.
+fn input_lines<'a>(reader: impl BufRead + 'a, source: &'a str) -> impl Iterator<Item = Result<InputLine>> + 'a {
+    let lines = reader.lines()
+        .map(move |line| line.with_context(|| format!("Failed to read {}", source)))
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()));
+    Events { lines: lines.peekable() }
+        .map(|line| line.map(|line| InputLine::parse(&line)))
+}
+
+/// Joins multi-line messages back together: lines that don't start with `[`
+/// continue the `[timestamp]` line before them, less the indentation `query`
+/// puts in front of continuation lines. Input without prefixes passes
+/// through line by line.
+struct Events<I: Iterator<Item = Result<String>>> {
+    lines: Peekable<I>,
+}
+
+impl<I: Iterator<Item = Result<String>>> Iterator for Events<I> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut event = match self.lines.next()? {
+            Ok(line) => line,
+            Err(err) => return Some(Err(err)),
+        };
+        if !event.starts_with('[') {
+            return Some(Ok(event));
+        }
+
+        let (_, _, message) = json_formatter::parse_log_line(&event);
+        let indent = event.trim_end().len() - message.len();
+        while let Some(Ok(line)) = self.lines.peek() {
+            if line.starts_with('[') {
+                break;
+            }
+            let continuation = match line.get(..indent) {
+                Some(lead) if lead.bytes().all(|b| b == b' ') => &line[indent..],
+                _ => line.as_str(),
+            };
+            event.push('\n');
+            event.push_str(continuation);
+            self.lines.next();
+        }
+        Some(Ok(event))
+    }
+}
+
+/// Renders log lines from a file or stdin like `query` renders fetched
+/// events, without calling AWS. Colored output streams line by line; table,
+/// CSV and JSON output read the whole input first.
+pub fn run(options: FormatOptions) -> Result<()> {
+    let FormatOptions {
+        path,
+        grep,
+        invert_match,
+        regex_options,
+        highlight_style,
+        formatted,
+        format_hint,
+        flatten_depth,
+        lambda,
+        emf,
+        pin,
+        min_frequency,
+        max_width,
+        col_width,
+        no_truncate,
+        no_level_color,
+        no_wrap,
+        output,
+        quiet,
+    } = options;
+
+    let tabular = formatted || output == OutputFormat::Csv;
+    if flatten_depth.is_some() && !tabular {
+        bail!(CwlError::InvalidArgument("--flatten-depth and --compact only apply to --formatted and CSV output".to_string()));
+    }
+    if lambda && !tabular {
+        bail!(CwlError::InvalidArgument("--lambda only applies to --formatted and CSV output".to_string()));
+    }
+    let flatten_depth = flatten_depth.unwrap_or(json_formatter::DEFAULT_FLATTEN_DEPTH);
+    let grep = grep.as_deref()
+        .map(|pattern| Grep::new(pattern, &regex_options, invert_match))
+        .transpose()?;
+    let source = path.as_ref().map_or_else(|| "stdin".to_string(), |path| path.display().to_string());
+
+    let lines = input_lines(open_input(path.as_deref())?, &source)
+        .filter(|line| match (line, &grep) {
+            (Ok(line), Some(grep)) => grep.matches(&line.message),
+            _ => true,
+        });
+
+    let mut out = TeeWriter::new(None, false)?;
+
+    if output.is_json() || output == OutputFormat::Yaml {
+        let records = lines
+            .map(|line| line.map(InputLine::into_record))
+            .collect::<Result<Vec<Value>>>()?;
+        let rendered = output::render_events(records, output)?;
+        if !rendered.is_empty() {
+            out.write_line(rendered.trim_end());
+        }
+        return Ok(());
+    }
+
+    if formatted || output == OutputFormat::Csv {
+        let log_lines = lines
+            .map(|line| line.map(|line| format!("[{}] [{}] {}", line.timestamp, line.stream, line.message)))
+            .collect::<Result<Vec<String>>>()?;
+
+        let mut table = json_formatter::analyze_json_logs(&log_lines, format_hint, flatten_depth, lambda, emf);
+        if let Some(min_frequency) = min_frequency {
+            let pruned = json_formatter::prune_sparse_columns(&mut table, min_frequency, &pin);
+            if pruned > 0 && !quiet && output != OutputFormat::Csv {
+                println!("{} {} sparse columns (in fewer than {}% of rows)\n",
+                    "Pruned".bright_black(),
+                    format::format_count(pruned).bright_yellow(),
+                    min_frequency
+                );
+            }
+        }
+        json_formatter::pin_columns(&mut table, &pin);
+        if output == OutputFormat::Csv {
+            let csv = json_formatter::render_csv(&table)?;
+            out.write_line(csv.trim_end_matches('\n'));
+        } else {
+            json_formatter::cap_columns(&mut table, &col_width);
+            if !no_truncate {
+                let width = max_width.or_else(|| std::io::stdout().is_terminal().then(format::terminal_width));
+                json_formatter::fit_columns(&mut table, width);
+            }
+            json_formatter::print_formatted_table(&table, quiet, !no_level_color, &mut out);
+        }
+        return Ok(());
+    }
+
+    let mut count = 0usize;
+    for line in lines {
+        let line = line?;
+        if output == OutputFormat::Raw {
+            out.write_line(&line.message);
+            count += 1;
+            continue;
+        }
+        let message = format::sanitize_for_terminal(&line.message);
+        let message = match grep {
+            Some(ref grep) => grep.highlight(&message, highlight_style),
+            None => message,
+        };
+
+        let prefix = match (line.timestamp.is_empty(), line.stream.is_empty()) {
+            (true, _) => String::new(),
+            (false, true) => format!("[{}] ", line.timestamp.bright_blue()),
+            (false, false) => format!("[{}] [{}] ", line.timestamp.bright_blue(), line.stream.color(format::stream_color(&line.stream))),
+        };
+        let message = format::fold_lines(&message, format::visible_width(&prefix), !no_wrap);
+        out.write_line(&format!("{}{}", prefix, message));
+        count += 1;
+    }
+
+    if !quiet && output != OutputFormat::Raw {
+        println!("\n{} {} lines formatted from {}",
+            "✓".bright_green().bold(),
+            format::format_count(count).bright_yellow(),
+            source
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    // As written by `export`: continuation lines are indented to line up
+    // with the message after the `[timestamp] [stream] ` prefix
+    const EXPORTED: &str = concat!(
+        "[2024-01-01 10:00:00.000] [web-1] {\"level\":\"ERROR\",\"msg\":\"boom\"}\n",
+        "\n",
+        "[2024-01-01 10:00:01.000] [web-2] Traceback (most recent call last):\n",
+        "                                    File \"app.py\", line 1\n",
+    );
+
+    #[test]
+    fn test_gz_input_is_split_into_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exported.log.gz");
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(EXPORTED.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let lines: Vec<InputLine> = input_lines(open_input(Some(&path)).unwrap(), "test")
+            .collect::<Result<_>>()
+            .unwrap();
+
+        let parsed: Vec<(&str, &str, &str)> = lines.iter()
+            .map(|line| (line.timestamp.as_str(), line.stream.as_str(), line.message.as_str()))
+            .collect();
+        assert_eq!(parsed, [
+            ("2024-01-01 10:00:00.000", "web-1", r#"{"level":"ERROR","msg":"boom"}"#),
+            ("2024-01-01 10:00:01.000", "web-2", "Traceback (most recent call last):\n  File \"app.py\", line 1"),
+        ]);
+    }
+
+    #[test]
+    fn test_json_and_csv_rendering() {
+        let lines: Vec<InputLine> = input_lines(EXPORTED.as_bytes(), "stdin").collect::<Result<_>>().unwrap();
+
+        let records: Vec<Value> = input_lines(EXPORTED.as_bytes(), "stdin")
+            .map(|line| line.map(InputLine::into_record))
+            .collect::<Result<_>>()
+            .unwrap();
+        let rendered = output::render_events(records, OutputFormat::Ndjson).unwrap();
+        let first: Value = serde_json::from_str(rendered.lines().next().unwrap()).unwrap();
+        assert_eq!(first["stream"], "web-1");
+        assert_eq!(first["parsed"]["level"], "ERROR");
+
+        let log_lines: Vec<String> = lines.iter()
+            .map(|line| format!("[{}] [{}] {}", line.timestamp, line.stream, line.message))
+            .collect();
+        let table = json_formatter::analyze_json_logs(&log_lines, json_formatter::FormatHint::Auto, 1, false, false);
+        let csv = json_formatter::render_csv(&table).unwrap();
+        let mut rows = csv.lines();
+        assert_eq!(rows.next(), Some("timestamp,log_group,level,msg"));
+        assert_eq!(rows.next(), Some("2024-01-01 10:00:00.000,web-1,ERROR,boom"));
+    }
+}
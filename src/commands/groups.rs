@@ -1,16 +1,200 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
 use colored::Colorize;
 use regex::Regex;
-use crate::aws::client::CloudWatchClient;
+use crate::aws::client::{CloudWatchClient, LogGroupInfo, MetricFilterInfo, INFREQUENT_ACCESS};
+use crate::error::CwlError;
+use crate::utils::{filter as filter_utils, format, time};
+use crate::utils::output::{self, OutputFormat};
+use crate::utils::progress::ProgressMode;
 use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
 
-pub async fn run(
-    client: CloudWatchClient,
-    filter: Option<String>,
-) -> Result<()> {
-    println!("{}", "Fetching log groups...".bright_blue().bold());
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupSort {
+    /// Alphabetically by name
+    Name,
+    /// Largest stored bytes first
+    Bytes,
+    /// Longest retention first (never-expiring groups lead)
+    Retention,
+    /// Most recently created first
+    Created,
+    /// Least recently active first (checks each group's streams)
+    LastEvent,
+}
 
-    let spinner = ProgressBar::new_spinner();
+/// How `--filter` is matched against group names. `--fuzzy` always wins;
+/// otherwise the filter is a regex, falling back to a literal substring when
+/// it is not a valid regex.
+enum GroupMatcher {
+    Regex(Regex),
+    Fuzzy(String),
+}
+
+impl GroupMatcher {
+    fn new(pattern: &str, fuzzy: bool) -> Self {
+        if fuzzy {
+            return GroupMatcher::Fuzzy(pattern.to_lowercase());
+        }
+
+        match Regex::new(pattern) {
+            Ok(regex) => GroupMatcher::Regex(regex),
+            Err(_) => {
+                eprintln!("{} '{}' is not a valid regex, matching it literally instead",
+                    "Warning:".yellow().bold(),
+                    pattern
+                );
+                GroupMatcher::Regex(Regex::new(&regex::escape(pattern)).unwrap())
+            }
+        }
+    }
+
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            GroupMatcher::Regex(regex) => regex.is_match(name),
+            GroupMatcher::Fuzzy(needle) => fuzzy_match(needle, &name.to_lowercase()),
+        }
+    }
+}
+
+/// Case-folded subsequence match: every character of `needle` must appear in
+/// `haystack` in order, so plain substrings always match.
+fn fuzzy_match(needle: &str, haystack: &str) -> bool {
+    let mut chars = haystack.chars();
+    needle.chars().all(|c| chars.any(|h| h == c))
+}
+
+pub struct GroupsOptions {
+    pub filter: Option<String>,
+    /// Accept an empty `filter` instead of rejecting it
+    pub allow_empty_filter: bool,
+    pub fuzzy: bool,
+    pub sort: GroupSort,
+    pub limit: Option<usize>,
+    /// Only keep groups with no events within this duration
+    pub inactive: Option<String>,
+    /// Only keep groups storing no data
+    pub empty: bool,
+    /// Print just the number of matching groups
+    pub count: bool,
+    /// Show each group's class, retention and size
+    pub details: bool,
+    /// List each group's metric filters under it
+    pub metric_filters: bool,
+    pub output: OutputFormat,
+    pub progress: ProgressMode,
+    pub quiet: bool,
+}
+
+/// A log group in `--output json`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct GroupRecord {
+    name: String,
+    retention_in_days: Option<i32>,
+    stored_bytes: Option<i64>,
+    /// RFC 3339, like event timestamps in `query --output json`
+    creation_time: Option<String>,
+    arn: Option<String>,
+    metric_filter_count: Option<i32>,
+    log_group_class: Option<String>,
+    /// Only with `--with-metric-filters`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metric_filters: Option<Vec<MetricFilterRecord>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct MetricFilterRecord {
+    name: String,
+    pattern: String,
+    metrics: Vec<String>,
+}
+
+impl From<&MetricFilterInfo> for MetricFilterRecord {
+    fn from(filter: &MetricFilterInfo) -> Self {
+        Self {
+            name: filter.name.clone(),
+            pattern: filter.pattern.clone(),
+            metrics: filter.metrics.clone(),
+        }
+    }
+}
+
+impl From<&LogGroupInfo> for GroupRecord {
+    fn from(group: &LogGroupInfo) -> Self {
+        Self {
+            name: group.name.clone(),
+            retention_in_days: group.retention_in_days,
+            stored_bytes: group.stored_bytes,
+            creation_time: group.creation_time
+                .and_then(DateTime::<Utc>::from_timestamp_millis)
+                .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+            arn: group.arn.clone(),
+            metric_filter_count: group.metric_filter_count,
+            log_group_class: group.log_group_class.clone(),
+            metric_filters: None,
+        }
+    }
+}
+
+pub async fn run(client: CloudWatchClient, options: GroupsOptions) -> Result<()> {
+    let GroupsOptions {
+        filter,
+        allow_empty_filter,
+        fuzzy,
+        sort,
+        limit,
+        inactive,
+        empty,
+        count,
+        details,
+        metric_filters,
+        output,
+        progress,
+        quiet,
+    } = options;
+    if matches!(output, OutputFormat::Csv | OutputFormat::Yaml) {
+        bail!(CwlError::InvalidArgument("groups only supports colored, raw, json and ndjson output".to_string()));
+    }
+    // The count and JSON are meant for scripts, so they are the only output
+    let json = output.is_json();
+    let quiet = quiet || count || json || output == OutputFormat::Raw;
+
+    if !allow_empty_filter {
+        filter_utils::check_not_empty("--filter", &filter, "log groups")?;
+    }
+
+    let inactive_since = inactive
+        .map(|duration| time::parse_duration(&duration))
+        .transpose()?
+        .map(|duration| Utc::now().timestamp_millis() - duration.num_milliseconds());
+
+    if let Some(request) = client.dry_run("DescribeLogGroups") {
+        let check_activity = inactive_since.is_some() || sort == GroupSort::LastEvent;
+        request
+            .param("filter", filter)
+            .param("fuzzy", fuzzy)
+            .param("empty", empty)
+            .param("count", count)
+            .param("details", details)
+            .time("inactiveSince", inactive_since, chrono_tz::Tz::UTC)
+            .param("followUp", check_activity.then_some("DescribeLogStreams (limit 1) per matching group"))
+            .param("metricFilters", metric_filters.then_some("DescribeMetricFilters per listed group with metric filters"))
+            .print();
+        return Ok(());
+    }
+
+    if !quiet {
+        println!("{}", "Fetching log groups...".bright_blue().bold());
+    }
+
+    // Without a spinner the header above is the only progress line
+    let spinner = if quiet || !progress.draws() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
     spinner.set_style(
         ProgressStyle::default_spinner()
             .template("{spinner:.green} {msg}")
@@ -18,40 +202,304 @@ pub async fn run(
     );
     spinner.set_message("Loading log groups...");
 
-    let groups = client.list_log_groups(None).await?;
+    let groups = client.describe_log_groups(None).await?;
 
     spinner.finish_and_clear();
 
-    let filtered_groups = if let Some(ref pattern) = filter {
-        let regex = Regex::new(pattern)?;
+    let mut filtered_groups = if let Some(ref pattern) = filter {
+        let matcher = GroupMatcher::new(pattern, fuzzy);
         groups.into_iter()
-            .filter(|g| regex.is_match(g))
+            .filter(|g| matcher.is_match(&g.name))
             .collect::<Vec<_>>()
     } else {
         groups
     };
 
-    if filtered_groups.is_empty() {
-        println!("{}", "No log groups found".yellow());
+    if empty {
+        filtered_groups.retain(|g| g.stored_bytes.unwrap_or(0) == 0);
+    }
+
+    let check_activity = inactive_since.is_some() || sort == GroupSort::LastEvent;
+    if check_activity && !filtered_groups.is_empty() {
+        let message = format!("Checking activity of {} groups...", filtered_groups.len());
+        let bar = progress.bar(quiet, &message, || ProgressBar::new(filtered_groups.len() as u64).with_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} {msg} [{bar:30.cyan/blue}] {pos}/{len}")
+                .unwrap()
+                .progress_chars("=> ")
+        ));
+
+        client.fill_last_event_times(&mut filtered_groups, |checked| bar.set_position(checked as u64)).await?;
+        bar.finish_and_clear();
+
+        if let Some(cutoff) = inactive_since {
+            filtered_groups.retain(|g| g.last_event_time.is_none_or(|t| t < cutoff));
+        }
+    }
+
+    if count {
+        println!("{}", filtered_groups.len());
         return Ok(());
     }
 
-    println!("{} {} log groups:\n",
-        "Found".bright_green().bold(),
-        filtered_groups.len().to_string().bright_yellow().bold()
-    );
+    if filtered_groups.is_empty() && !json {
+        if !quiet {
+            println!("{}", "No log groups found".yellow());
+        }
+        return Ok(());
+    }
+
+    sort_groups(&mut filtered_groups, sort);
+
+    let total = filtered_groups.len();
+    if let Some(limit) = limit {
+        filtered_groups.truncate(limit);
+    }
+
+    // Only for the groups shown, since it costs a call per group
+    let group_filters = if metric_filters && (json || !quiet) {
+        let message = format!("Describing metric filters of {} groups...", filtered_groups.len());
+        let bar = progress.bar(quiet, &message, || ProgressBar::new(filtered_groups.len() as u64).with_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} {msg} [{bar:30.cyan/blue}] {pos}/{len}")
+                .unwrap()
+                .progress_chars("=> ")
+        ));
+        let filters = client.describe_group_metric_filters(&filtered_groups, |done| bar.set_position(done as u64)).await?;
+        bar.finish_and_clear();
+        Some(filters)
+    } else {
+        None
+    };
+
+    if json {
+        let records: Vec<GroupRecord> = filtered_groups.iter()
+            .enumerate()
+            .map(|(i, group)| GroupRecord {
+                metric_filters: group_filters.as_ref().map(|filters| filters[i].iter().map(MetricFilterRecord::from).collect()),
+                ..GroupRecord::from(group)
+            })
+            .collect();
+        let rendered = output::render_json(&records, output)?;
+        if !rendered.is_empty() {
+            println!("{}", rendered);
+        }
+        return Ok(());
+    }
+
+    if quiet {
+        for group in &filtered_groups {
+            println!("{}", group.name);
+        }
+        return Ok(());
+    }
 
-    for group in &filtered_groups {
-        println!("  {} {}",
-            "→".bright_cyan(),
-            group.bright_white()
+    if filtered_groups.len() < total {
+        println!("{} {} log groups (showing {} of {}):\n",
+            "Found".bright_green().bold(),
+            format::format_count(total).bright_yellow().bold(),
+            format::format_count(filtered_groups.len()).bright_yellow(),
+            format::format_count(total).bright_yellow()
+        );
+    } else {
+        println!("{} {} log groups:\n",
+            "Found".bright_green().bold(),
+            format::format_count(total).bright_yellow().bold()
         );
     }
 
+    for (i, group) in filtered_groups.iter().enumerate() {
+        let detail = if details { Some(group_details(group)) } else { sort_detail(group, sort) };
+        let detail = match detail {
+            Some(detail) if check_activity && (details || sort != GroupSort::LastEvent) => {
+                Some(format!("{}, {}", detail, activity_detail(group)))
+            }
+            None if check_activity => Some(activity_detail(group)),
+            detail => detail,
+        };
+        match detail {
+            Some(detail) => println!("  {} {} {}",
+                "→".bright_cyan(),
+                group.name.bright_white(),
+                format!("({})", detail).bright_black()
+            ),
+            None => println!("  {} {}",
+                "→".bright_cyan(),
+                group.name.bright_white()
+            ),
+        }
+        if let Some(ref group_filters) = group_filters {
+            for line in metric_filter_lines(&group_filters[i]) {
+                println!("      {}", line);
+            }
+        }
+    }
+
     println!("\n{} Use {} to tail a specific log group",
         "Tip:".bright_magenta().bold(),
         "cwl tail <log-group>".bright_white().italic()
     );
 
     Ok(())
-}
\ No newline at end of file
+}
+
+fn sort_groups(groups: &mut [LogGroupInfo], sort: GroupSort) {
+    match sort {
+        GroupSort::Name => groups.sort_by(|a, b| a.name.cmp(&b.name)),
+        GroupSort::Bytes => groups.sort_by(|a, b| b.stored_bytes.cmp(&a.stored_bytes)
+            .then_with(|| a.name.cmp(&b.name))),
+        GroupSort::Retention => groups.sort_by(|a, b| {
+            let key = |g: &LogGroupInfo| g.retention_in_days.unwrap_or(i32::MAX);
+            key(b).cmp(&key(a)).then_with(|| a.name.cmp(&b.name))
+        }),
+        GroupSort::Created => groups.sort_by(|a, b| b.creation_time.cmp(&a.creation_time)
+            .then_with(|| a.name.cmp(&b.name))),
+        GroupSort::LastEvent => groups.sort_by(|a, b| a.last_event_time.cmp(&b.last_event_time)
+            .then_with(|| a.name.cmp(&b.name))),
+    }
+}
+
+fn sort_detail(group: &LogGroupInfo, sort: GroupSort) -> Option<String> {
+    match sort {
+        GroupSort::Name => None,
+        GroupSort::Bytes => Some(format::format_bytes(group.stored_bytes.unwrap_or(0))),
+        GroupSort::Retention => Some(match group.retention_in_days {
+            Some(days) => format!("{}d retention", days),
+            None => "never expires".to_string(),
+        }),
+        GroupSort::Created => group.creation_time
+            .and_then(DateTime::<Utc>::from_timestamp_millis)
+            .map(|dt| format!("created {}", dt.format("%Y-%m-%d %H:%M:%S"))),
+        GroupSort::LastEvent => Some(activity_detail(group)),
+    }
+}
+
+/// One line per metric filter: its name, pattern and the metrics it feeds,
+/// with names padded so the patterns line up.
+fn metric_filter_lines(filters: &[MetricFilterInfo]) -> Vec<String> {
+    if filters.is_empty() {
+        return vec!["no metric filters".bright_black().to_string()];
+    }
+    let name_width = filters.iter().map(|filter| filter.name.chars().count()).max().unwrap_or(0);
+    filters.iter()
+        .map(|filter| {
+            let pattern = match filter.pattern.trim() {
+                "" => "(every event)".to_string(),
+                pattern => pattern.to_string(),
+            };
+            format!("{} {}  {} {}",
+                "▸".bright_black(),
+                format!("{:<name_width$}", filter.name).bright_white(),
+                pattern.yellow(),
+                format!("→ {}", filter.metrics.join(", ")).bright_cyan()
+            )
+        })
+        .collect()
+}
+
+/// The `--details` summary: class, retention and stored size.
+fn group_details(group: &LogGroupInfo) -> String {
+    let class = match group.log_group_class.as_deref() {
+        Some(INFREQUENT_ACCESS) => "Infrequent Access",
+        Some("DELIVERY") => "Delivery",
+        Some(class) if class != "STANDARD" => class,
+        _ => "Standard",
+    };
+    let retention = match group.retention_in_days {
+        Some(days) => format!("{}d retention", days),
+        None => "never expires".to_string(),
+    };
+    format!("{}, {}, {}", class, retention, format::format_bytes(group.stored_bytes.unwrap_or(0)))
+}
+
+fn activity_detail(group: &LogGroupInfo) -> String {
+    group.last_event_time
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+        .map(|dt| format!("last event {}", dt.format("%Y-%m-%d %H:%M:%S")))
+        .unwrap_or_else(|| "no events".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        let matcher = GroupMatcher::new("LAMBDA/my", true);
+        assert!(matcher.is_match("/aws/lambda/my-func"));
+        assert!(GroupMatcher::new("lmbmyfn", true).is_match("/aws/lambda/my-func"));
+        assert!(!GroupMatcher::new("ecs", true).is_match("/aws/lambda/my-func"));
+    }
+
+    #[test]
+    fn test_invalid_regex_falls_back_to_literal() {
+        let matcher = GroupMatcher::new("/aws/lambda/my(func", false);
+        assert!(matcher.is_match("/aws/lambda/my(func"));
+        assert!(!matcher.is_match("/aws/lambda/myfunc"));
+    }
+
+    #[test]
+    fn test_sort_by_last_event_puts_idle_groups_first() {
+        let group = |name: &str, last_event_time| LogGroupInfo {
+            name: name.to_string(),
+            creation_time: None,
+            retention_in_days: None,
+            stored_bytes: None,
+            arn: None,
+            metric_filter_count: None,
+            log_group_class: None,
+            last_event_time,
+        };
+        let mut groups = vec![group("busy", Some(300)), group("never", None), group("stale", Some(100))];
+
+        sort_groups(&mut groups, GroupSort::LastEvent);
+        let names: Vec<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["never", "stale", "busy"]);
+    }
+
+    #[test]
+    fn test_json_records_round_trip() {
+        let group = LogGroupInfo {
+            name: "/aws/lambda/api".to_string(),
+            creation_time: Some(1_704_103_200_000),
+            retention_in_days: None,
+            stored_bytes: Some(2048),
+            arn: Some("arn:aws:logs:us-east-1:123456789012:log-group:/aws/lambda/api:*".to_string()),
+            metric_filter_count: Some(1),
+            log_group_class: Some(INFREQUENT_ACCESS.to_string()),
+            last_event_time: None,
+        };
+        let json = serde_json::to_string_pretty(&[GroupRecord::from(&group)]).unwrap();
+        assert!(json.contains("\"retention_in_days\": null"));
+        assert!(!json.contains('\x1b'));
+
+        let records: Vec<GroupRecord> = serde_json::from_str(&json).unwrap();
+        assert_eq!(records[0].name, "/aws/lambda/api");
+        assert_eq!(records[0].creation_time.as_deref(), Some("2024-01-01T10:00:00.000Z"));
+        assert_eq!(records[0].metric_filter_count, Some(1));
+        assert_eq!(records, [GroupRecord::from(&group)]);
+        assert_eq!(group_details(&group), "Infrequent Access, never expires, 2.0 KB");
+    }
+
+    #[test]
+    fn test_metric_filter_lines_align_patterns() {
+        let filters = [
+            MetricFilterInfo {
+                name: "errors".to_string(),
+                pattern: "{ $.level = \"ERROR\" }".to_string(),
+                metrics: vec!["MyApp/Errors".to_string()],
+            },
+            MetricFilterInfo {
+                name: "all-requests".to_string(),
+                pattern: String::new(),
+                metrics: vec!["MyApp/Requests".to_string(), "MyApp/Hits".to_string()],
+            },
+        ];
+        let lines: Vec<String> = metric_filter_lines(&filters).iter().map(|line| format::strip_ansi(line)).collect();
+        assert_eq!(lines, [
+            "▸ errors        { $.level = \"ERROR\" } → MyApp/Errors",
+            "▸ all-requests  (every event) → MyApp/Requests, MyApp/Hits",
+        ]);
+        assert_eq!(format::strip_ansi(&metric_filter_lines(&[])[0]), "no metric filters");
+    }
+}
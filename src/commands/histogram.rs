@@ -0,0 +1,210 @@
+use anyhow::{Result, bail};
+use colored::Colorize;
+use chrono::Utc;
+use chrono_tz::Tz;
+use crate::aws::client::{CloudWatchClient, StreamFilter};
+use crate::error::CwlError;
+use crate::utils::{format, time};
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// The most buckets a histogram may have; beyond this the output is useless
+/// and the counts alone could exhaust memory.
+const MAX_BUCKETS: i64 = 10_000;
+
+pub struct HistogramOptions {
+    pub log_group: String,
+    pub interval: String,
+    pub since: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub default_since: Option<String>,
+    pub filter: Option<String>,
+    pub tz: Tz,
+    pub quiet: bool,
+}
+
+pub async fn run(client: CloudWatchClient, options: HistogramOptions) -> Result<()> {
+    let HistogramOptions {
+        log_group,
+        interval,
+        since,
+        start,
+        end,
+        default_since,
+        filter,
+        tz,
+        quiet,
+    } = options;
+
+    let interval_ms = time::parse_duration(&interval)?.num_milliseconds();
+    if interval_ms <= 0 {
+        bail!("Interval must be greater than zero");
+    }
+
+    let (start_time, end_time) = time::parse_time_range(since, start, end, default_since.as_deref())?;
+    let last = end_time.unwrap_or_else(|| Utc::now().timestamp_millis());
+    if let Some(start_time) = start_time {
+        check_bucket_count(start_time, last, interval_ms, &interval)?;
+    }
+
+    if let Some(request) = client.dry_run("FilterLogEvents") {
+        request
+            .log_group(&log_group)
+            .time("startTime", start_time, tz)
+            .time("endTime", end_time, tz)
+            .param("filterPattern", filter)
+            .param("interval", interval)
+            .print();
+        return Ok(());
+    }
+
+    if !quiet {
+        println!("{} {} {}",
+            "Event histogram for:".bright_blue().bold(),
+            log_group.bright_yellow(),
+            format!("({} buckets)", interval).bright_black()
+        );
+    }
+
+    let spinner = if quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap()
+    );
+    spinner.set_message("Fetching log events...");
+
+    let events = client.get_log_events(
+        &log_group,
+        start_time,
+        end_time,
+        filter.as_deref(),
+        StreamFilter::ALL,
+        None,
+        false,
+    ).await?;
+
+    spinner.finish_and_clear();
+
+    let timestamps: Vec<i64> = events.iter().filter_map(|e| e.timestamp).collect();
+    let Some(first) = start_time.or_else(|| timestamps.iter().min().copied()) else {
+        if !quiet {
+            println!("{}", "No log events found matching criteria".yellow());
+        }
+        return Ok(());
+    };
+    check_bucket_count(first, last, interval_ms, &interval)?;
+
+    let buckets = bucket_counts(&timestamps, first, last, interval_ms);
+    let peak = buckets.iter().map(|(_, count)| *count).max().unwrap_or(0);
+
+    let label_format = if interval_ms < 60_000 {
+        "%Y-%m-%d %H:%M:%S"
+    } else {
+        "%Y-%m-%d %H:%M"
+    };
+    let labels: Vec<String> = buckets.iter()
+        .map(|(bucket_start, _)| time::format_timestamp(*bucket_start, tz, label_format).unwrap_or_default())
+        .collect();
+    let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let count_width = peak.to_string().len();
+    let bar_space = format::terminal_width()
+        .saturating_sub(label_width + count_width + 5)
+        .max(10);
+
+    for ((_, count), label) in buckets.iter().zip(&labels) {
+        let bar_len = (count * bar_space).checked_div(peak).unwrap_or(0);
+        let bar = "█".repeat(bar_len);
+
+        if *count == peak && peak > 0 {
+            println!("{} │ {} {}",
+                format!("{:<1$}", label, label_width).bright_blue(),
+                bar.bright_red().bold(),
+                count.to_string().bright_red().bold()
+            );
+        } else {
+            println!("{} │ {} {}",
+                format!("{:<1$}", label, label_width).bright_blue(),
+                bar.bright_green(),
+                count
+            );
+        }
+    }
+
+    if !quiet {
+        println!("\n{} {} events in {} buckets, peak {} per {}",
+            "✓".bright_green().bold(),
+            timestamps.len().to_string().bright_yellow(),
+            buckets.len().to_string().bright_yellow(),
+            peak.to_string().bright_red(),
+            interval
+        );
+    }
+
+    Ok(())
+}
+
+/// Rejects ranges that would need more than `MAX_BUCKETS` buckets.
+fn check_bucket_count(start: i64, end: i64, interval_ms: i64, interval: &str) -> Result<()> {
+    let buckets = (end.max(start) - start) / interval_ms + 1;
+    if buckets > MAX_BUCKETS {
+        bail!(CwlError::InvalidArgument(format!(
+            "An interval of {} splits this time range into {} buckets (at most {} are allowed); use a larger --interval or a shorter range",
+            interval,
+            format::format_count(buckets as usize),
+            format::format_count(MAX_BUCKETS as usize)
+        )));
+    }
+    Ok(())
+}
+
+/// Counts events per `interval_ms` bucket between `start` and `end`, aligning
+/// buckets to multiples of the interval and keeping empty buckets so gaps are
+/// visible.
+pub fn bucket_counts(timestamps: &[i64], start: i64, end: i64, interval_ms: i64) -> Vec<(i64, usize)> {
+    let first_bucket = start.div_euclid(interval_ms) * interval_ms;
+    let last_bucket = end.max(start).div_euclid(interval_ms) * interval_ms;
+    let bucket_count = ((last_bucket - first_bucket) / interval_ms + 1) as usize;
+
+    let mut counts = vec![0usize; bucket_count];
+    for ts in timestamps {
+        if *ts < first_bucket || *ts > end.max(start) {
+            continue;
+        }
+        let index = ((ts - first_bucket) / interval_ms) as usize;
+        if let Some(count) = counts.get_mut(index) {
+            *count += 1;
+        }
+    }
+
+    counts.into_iter()
+        .enumerate()
+        .map(|(i, count)| (first_bucket + i as i64 * interval_ms, count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_counts_keeps_empty_buckets() {
+        let timestamps = vec![0, 10_000, 59_999, 180_000, 185_000];
+        let buckets = bucket_counts(&timestamps, 0, 200_000, 60_000);
+
+        assert_eq!(buckets, vec![(0, 3), (60_000, 0), (120_000, 0), (180_000, 2)]);
+    }
+
+    #[test]
+    fn test_check_bucket_count_rejects_huge_ranges() {
+        assert!(check_bucket_count(0, 3_600_000, 60_000, "1m").is_ok());
+
+        let err = check_bucket_count(0, 1_700_000_000_000, 1_000, "1s").unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CwlError::InvalidArgument(_))));
+        assert!(err.to_string().contains("--interval"), "{}", err);
+    }
+}
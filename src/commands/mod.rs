@@ -1,3 +1,15 @@
 pub mod tail;
 pub mod query;
-pub mod groups;
\ No newline at end of file
+pub mod groups;
+pub mod histogram;
+pub mod export;
+pub mod describe;
+pub mod streams;
+pub mod retention;
+pub mod format;
+pub mod put;
+pub mod diff;
+pub mod trace;
+pub mod schema;
+#[cfg(feature = "tui")]
+pub mod browse;
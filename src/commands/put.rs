@@ -0,0 +1,71 @@
+use anyhow::{Result, Context, bail};
+use chrono::Utc;
+use chrono_tz::Tz;
+use colored::Colorize;
+use crate::aws::arn::group_name;
+use crate::aws::client::CloudWatchClient;
+use crate::error::CwlError;
+use crate::utils::time;
+use std::io::Read;
+
+pub struct PutOptions {
+    pub log_group: String,
+    pub stream: String,
+    /// The event message, or `-` to read it from stdin.
+    pub message: String,
+    /// Event time (anything `--start` accepts); now when `None`.
+    pub timestamp: Option<String>,
+    pub tz: Tz,
+    pub quiet: bool,
+}
+
+/// Writes a single event, e.g. to test metric filters or subscriptions.
+pub async fn run(client: CloudWatchClient, options: PutOptions) -> Result<()> {
+    let PutOptions { log_group, stream, message, timestamp, tz, quiet } = options;
+
+    let timestamp = match timestamp {
+        Some(timestamp) => time::parse_timestamp(&timestamp).context("Invalid --timestamp")?,
+        None => Utc::now().timestamp_millis(),
+    };
+
+    let message = match message.as_str() {
+        "-" => read_stdin_message()?,
+        _ => message,
+    };
+    if message.is_empty() {
+        bail!(CwlError::InvalidArgument("Refusing to put an empty message".to_string()));
+    }
+
+    if let Some(request) = client.dry_run("PutLogEvents") {
+        request
+            .param("logGroupName", group_name(&log_group))
+            .param("logStreamName", stream)
+            .time("timestamp", Some(timestamp), tz)
+            .param("message", message)
+            .print();
+        return Ok(());
+    }
+
+    client.put_log_event(&log_group, &stream, &message, timestamp).await?;
+
+    if !quiet {
+        println!("{} Put event to {} {}",
+            "✓".bright_green().bold(),
+            format!("{}/{}", log_group, stream).bright_white(),
+            time::format_timestamp(timestamp, tz, "%Y-%m-%d %H:%M:%S%.3f")
+                .map(|time| format!("at {}", time))
+                .unwrap_or_default()
+                .bright_black()
+        );
+    }
+
+    Ok(())
+}
+
+/// Reads the message from stdin, dropping the trailing newline that `echo`
+/// and friends add.
+fn read_stdin_message() -> Result<String> {
+    let mut message = String::new();
+    std::io::stdin().read_to_string(&mut message).context("Failed to read message from stdin")?;
+    Ok(message.trim_end_matches(['\n', '\r']).to_string())
+}
@@ -1,106 +1,719 @@
-use anyhow::Result;
+use anyhow::{Result, Context, bail};
 use colored::Colorize;
-use regex::Regex;
 use chrono::{DateTime, Utc};
-use crate::aws::client::CloudWatchClient;
+use chrono_tz::Tz;
+use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
+use crate::aws::arn::group_name;
+use crate::aws::backend::LogsBackend;
+use crate::aws::client::{LogGroupInfo, StreamFilter};
+use crate::commands::tail;
+use crate::config::DEFAULT_WARN_DAYS;
+use crate::error::CwlError;
 use crate::utils::{format, time, json_formatter};
+use crate::utils::checkpoint::{self, Checkpoint, CheckpointQuery, ResumePoint, Saved};
+use crate::utils::dedup::Deduper;
+use crate::utils::filter::{self as filter_utils, FilterSpec, Grep, JsonSelect};
+use crate::utils::output::{self, OutputFormat};
+use crate::utils::pager::Pager;
+use crate::utils::progress::ProgressMode;
+use crate::utils::sample::{Sampler, Sampling};
+use crate::utils::tee::TeeWriter;
+use crate::utils::time::TimeFormat;
 use indicatif::{ProgressBar, ProgressStyle};
 use serde_json::Value;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
 
-pub async fn run(
-    client: CloudWatchClient,
-    log_group: String,
-    since: Option<String>,
-    start: Option<String>,
-    end: Option<String>,
-    filter: Option<String>,
-    limit: usize,
-    formatted: bool,
-) -> Result<()> {
-    println!("{} {}",
-        "Querying logs from:".bright_blue().bold(),
-        log_group.bright_yellow()
-    );
+/// CloudWatch prices data scanned per GB, counted in binary gigabytes.
+const BYTES_PER_GB: f64 = 1_073_741_824.0;
+
+#[derive(Default)]
+pub struct QueryOptions {
+    pub log_group: String,
+    pub group_pattern: bool,
+    pub since: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub default_since: Option<String>,
+    pub filters: Vec<String>,
+    /// `--where` conditions, compiled into a JSON filter that replaces
+    /// `filters`
+    pub conditions: Vec<String>,
+    /// Accept empty `filters`/`grep` patterns instead of rejecting them
+    pub allow_empty_filter: bool,
+    /// Client-side regex applied after `filters`
+    pub grep: Option<String>,
+    /// Keep the messages `grep` doesn't match
+    pub invert_match: bool,
+    /// Drop messages that aren't (or, with `NonJson`, are) JSON objects
+    pub json_select: Option<JsonSelect>,
+    /// Events shown before each match (`--before`/`--context`)
+    pub before: usize,
+    /// Events shown after each match (`--after`/`--context`)
+    pub after: usize,
+    pub regex_options: format::RegexOptions,
+    pub limit: usize,
+    /// Keep at most this many matches from each log stream
+    pub limit_per_stream: Option<usize>,
+    /// Randomly thin out matched events (`--sample`/`--sample-n`)
+    pub sampling: Option<Sampling>,
+    pub seed: Option<u64>,
+    pub concurrency: usize,
+    pub oldest: bool,
+    pub formatted: bool,
+    pub format_hint: json_formatter::FormatHint,
+    /// Levels of nested JSON split into their own table columns (default
+    /// `DEFAULT_FLATTEN_DEPTH`)
+    pub flatten_depth: Option<usize>,
+    /// Split Lambda START/END/REPORT lines into table columns
+    pub lambda: bool,
+    /// Condense embedded metric format documents into metric columns
+    pub emf: bool,
+    pub max_width: Option<usize>,
+    /// Per-column width caps (`--col-width`)
+    pub col_width: Vec<json_formatter::ColumnWidth>,
+    /// Table columns placed right after the timestamp and log group
+    pub pin: Vec<String>,
+    /// Drop table columns present in fewer than this percentage of rows
+    pub min_frequency: Option<f64>,
+    /// Also pin the fields that `filters` and `grep` refer to
+    pub fields_from_filter: bool,
+    /// JSON field whose time replaces the event time in table and CSV output
+    pub time_field: Option<String>,
+    /// Show each event's ingestion delay in colored output, and as a column
+    /// in CSV output
+    pub show_delay: bool,
+    /// Ingestion delays above this (e.g. `30s`) are drawn in red
+    pub lag_threshold: Option<String>,
+    pub no_truncate: bool,
+    /// Messages longer than this many bytes are cut in colored output (0 for
+    /// no limit)
+    pub max_line_length: usize,
+    /// Print each message's bytes as a hex dump instead of text
+    pub hexdump: bool,
+    /// Keep multi-line messages on one line instead of indenting the rest
+    pub no_wrap: bool,
+    /// Mark `--filter` and `--grep` matches in colored output
+    pub highlight: bool,
+    pub highlight_style: format::HighlightStyle,
+    pub no_level_color: bool,
+    pub fields: Vec<String>,
+    pub output_file: Option<PathBuf>,
+    pub dedup: bool,
+    pub dedup_strict: bool,
+    /// Confirm unbounded queries spanning more days than this (default
+    /// `DEFAULT_WARN_DAYS`)
+    pub warn_days: Option<u64>,
+    /// Print an estimate of the data scanned and what it costs
+    pub show_cost: bool,
+    /// Dollars per GB scanned for `show_cost`
+    pub cost_per_gb: f64,
+    pub yes: bool,
+    pub tz: Tz,
+    pub time_format: TimeFormat,
+    pub output: OutputFormat,
+    pub raw: bool,
+    pub strip_ansi: bool,
+    pub template: Option<String>,
+    /// Page colored output through `$PAGER` when stdout is a terminal
+    pub pager: bool,
+    /// Keep following new events once the results are printed
+    pub follow: bool,
+    /// Save fetched pages here so an interrupted query can be resumed
+    pub checkpoint: Option<PathBuf>,
+    /// Carry on from a checkpoint instead of starting over
+    pub resume_checkpoint: Option<PathBuf>,
+    pub progress: ProgressMode,
+    pub quiet: bool,
+}
+
+pub async fn run(client: impl LogsBackend, options: QueryOptions) -> Result<()> {
+    let QueryOptions {
+        log_group,
+        group_pattern,
+        since,
+        start,
+        end,
+        default_since,
+        filters,
+        conditions,
+        allow_empty_filter,
+        grep,
+        invert_match,
+        json_select,
+        before,
+        after,
+        regex_options,
+        limit,
+        limit_per_stream,
+        sampling,
+        seed,
+        concurrency,
+        oldest,
+        formatted,
+        format_hint,
+        flatten_depth,
+        lambda,
+        emf,
+        max_width,
+        col_width,
+        pin,
+        min_frequency,
+        fields_from_filter,
+        time_field,
+        show_delay,
+        lag_threshold,
+        no_truncate,
+        max_line_length,
+        hexdump,
+        no_wrap,
+        highlight,
+        highlight_style,
+        no_level_color,
+        fields,
+        output_file,
+        dedup,
+        dedup_strict,
+        warn_days,
+        show_cost,
+        cost_per_gb,
+        yes,
+        tz,
+        time_format,
+        output,
+        raw,
+        strip_ansi,
+        template,
+        pager,
+        follow,
+        checkpoint,
+        resume_checkpoint,
+        progress: progress_mode,
+        quiet,
+    } = options;
+
+    let filters = match conditions.is_empty() {
+        true => filters,
+        false => vec![filter_utils::where_pattern(&conditions)?],
+    };
+    if output == OutputFormat::Raw && formatted {
+        bail!(CwlError::InvalidArgument("--formatted can't be combined with --output raw".to_string()));
+    }
+    let raw = raw || output == OutputFormat::Raw;
+    let tabular = formatted || output == OutputFormat::Csv;
+    if flatten_depth.is_some() && !tabular {
+        bail!(CwlError::InvalidArgument("--flatten-depth and --compact only apply to --formatted and CSV output".to_string()));
+    }
+    if lambda && !tabular {
+        bail!(CwlError::InvalidArgument("--lambda only applies to --formatted and CSV output".to_string()));
+    }
+    let flatten_depth = flatten_depth.unwrap_or(json_formatter::DEFAULT_FLATTEN_DEPTH);
+
+    // Following continues like `tail --follow`, with the same filters and layout
+    let follow = follow.then(|| tail::TailOptions {
+        log_group: log_group.clone(),
+        follow: true,
+        filters: filters.clone(),
+        allow_empty_filter,
+        grep: grep.clone(),
+        invert_match,
+        streams: Vec::new(),
+        stream_prefix: None,
+        regex_options,
+        from: None,
+        lines: tail::DEFAULT_LINES,
+        resume: None,
+        poll_interval: None,
+        max_poll_interval: None,
+        live: false,
+        highlight,
+        highlight_style,
+        fields: fields.clone(),
+        output_file: output_file.clone(),
+        dedup,
+        dedup_strict,
+        stats: false,
+        group_by_stream: false,
+        max_rate: None,
+        max_line_length,
+        no_wrap,
+        tz,
+        time_format: time_format.clone(),
+        raw,
+        strip_ansi,
+        template: template.clone(),
+        progress: progress_mode,
+        quiet,
+    });
+    if follow.is_some() && (log_group.contains('*') || group_pattern) {
+        bail!(CwlError::InvalidArgument("--follow needs a single log group, not a pattern".to_string()));
+    }
+    if follow.is_some() && (formatted || !matches!(output, OutputFormat::Colored | OutputFormat::Raw)) {
+        bail!(CwlError::InvalidArgument("--follow only works with colored or raw output".to_string()));
+    }
+    let pager = pager && follow.is_none();
+
+    // Machine-readable output carries no headers, spinners or summaries
+    let quiet = quiet || raw || output != OutputFormat::Colored;
+    let template = template.as_deref().map(format::Template::parse).transpose()?;
+    if !allow_empty_filter {
+        filter_utils::check_not_empty("--filter", &filters, "events")?;
+        filter_utils::check_not_empty("--grep", &grep, "events")?;
+    }
+    // Context needs the unfiltered neighbours, so filters are applied locally
+    let with_context = before > 0 || after > 0;
+    let filter = if with_context {
+        FilterSpec::parse_local(&filters)?
+    } else {
+        FilterSpec::parse(&filters)?
+    };
+    let grep_matcher = grep.as_deref()
+        .map(|pattern| Grep::new(pattern, &regex_options, invert_match))
+        .transpose()?;
+    let match_locally = filter.is_local() || grep_matcher.is_some() || json_select.is_some() || limit_per_stream.is_some();
+    let lag_threshold = lag_threshold.as_deref()
+        .map(|threshold| time::parse_duration(threshold).context("Invalid --lag-threshold"))
+        .transpose()?
+        .map(|threshold| threshold.num_milliseconds());
 
-    let (start_time, end_time) = time::parse_time_range(since, start, end)?;
+    // A resumed query keeps its original range, however `--since` was given
+    let resumed = resume_checkpoint.as_deref().map(checkpoint::load).transpose()?;
+    let (start_time, end_time) = match resumed {
+        Some(ref saved) => (saved.query.start_time, saved.query.end_time),
+        None => time::parse_time_range(since, start, end, default_since.as_deref())?,
+    };
+    let event_limit = if limit == usize::MAX { None } else { Some(limit) };
 
-    if let Some(start_ts) = start_time {
-        let dt = DateTime::<Utc>::from_timestamp_millis(start_ts)
-            .unwrap_or_else(|| Utc::now());
+    let pattern = match log_group.contains('*') {
+        true => Some(log_group.clone()),
+        false => group_pattern.then(|| format!("{}*", log_group)),
+    };
+    // --show-cost prices every matching group, or just this one
+    let cost_pattern = pattern.clone().unwrap_or_else(|| group_name(&log_group));
+
+    let checkpoint_query = CheckpointQuery {
+        log_group: log_group.clone(),
+        start_time,
+        end_time,
+        filter_pattern: filter.server.clone(),
+    };
+    if (checkpoint.is_some() || resumed.is_some()) && pattern.is_some() {
+        bail!(CwlError::InvalidArgument("--checkpoint and --resume need a single log group, not a pattern".to_string()));
+    }
+    if let (Some(saved), Some(path)) = (&resumed, &resume_checkpoint) {
+        if saved.query != checkpoint_query {
+            bail!(CwlError::InvalidArgument(format!(
+                "{} was saved for {} with filter {:?}; resume it with the same log group and --filter",
+                path.display(),
+                saved.query.log_group,
+                saved.query.filter_pattern.as_deref().unwrap_or_default()
+            )));
+        }
+    }
+
+    if let Some(request) = client.dry_run("FilterLogEvents") {
+        let request = match pattern {
+            Some(ref pattern) => request.param("logGroupNamePattern", pattern.as_str()),
+            None => request.log_group(&log_group),
+        };
+        request
+            .time("startTime", start_time, tz)
+            .time("endTime", end_time, tz)
+            .param("filterPattern", filter.server.clone())
+            .param("localFilters", filter.is_local().then(|| filters.clone()))
+            .param("grep", grep.clone())
+            .param("invertMatch", invert_match.then_some(true))
+            .param("messages", match json_select {
+                Some(JsonSelect::Json) => Value::from("json only"),
+                Some(JsonSelect::NonJson) => Value::from("non-json only"),
+                None => Value::Null,
+            })
+            .param("limit", event_limit)
+            .param("limitPerStream", limit_per_stream)
+            .param("sample", match sampling {
+                Some(Sampling::Rate(rate)) => Value::from(rate),
+                Some(Sampling::Count(n)) => Value::from(format!("{} events", n)),
+                None => Value::Null,
+            })
+            .param("seed", seed)
+            .param("keep", if oldest { "oldest" } else { "newest" })
+            .param("concurrency", concurrency)
+            .param("follow", follow.is_some().then_some(true))
+            .param("checkpoint", checkpoint.as_ref().or(resume_checkpoint.as_ref()).map(|path| path.display().to_string()))
+            .param("resumeFrom", resumed.as_ref().map(|saved| format!("{} saved events", saved.events.len())))
+            .print();
+        return Ok(());
+    }
+
+    let mut out = TeeWriter::new(output_file.as_deref(), false)?;
+
+    if !quiet {
         println!("{} {}",
-            "Start time:".bright_blue().bold(),
-            dt.format("%Y-%m-%d %H:%M:%S").to_string().bright_yellow()
+            "Querying logs from:".bright_blue().bold(),
+            log_group.bright_yellow()
         );
     }
 
-    if let Some(end_ts) = end_time {
-        let dt = DateTime::<Utc>::from_timestamp_millis(end_ts)
-            .unwrap_or_else(|| Utc::now());
+    let log_groups = if let Some(ref pattern) = pattern {
+        let groups: Vec<String> = client.describe_matching_log_groups(pattern).await?
+            .into_iter()
+            .map(|group| group.name)
+            .collect();
+        if groups.is_empty() {
+            bail!("No log groups match '{}'", pattern);
+        }
+        if !quiet {
+            println!("{} {}",
+                "Matched log groups:".bright_blue().bold(),
+                format::format_count(groups.len()).bright_yellow()
+            );
+        }
+        Some(groups)
+    } else {
+        None
+    };
+
+    if !quiet {
+        if let Some(start_ts) = start_time {
+            println!("{} {}",
+                "Start time:".bright_blue().bold(),
+                time::format_timestamp(start_ts, tz, "%Y-%m-%d %H:%M:%S").unwrap_or_default().bright_yellow()
+            );
+        }
+
+        if let Some(end_ts) = end_time {
+            println!("{} {}",
+                "End time:".bright_blue().bold(),
+                time::format_timestamp(end_ts, tz, "%Y-%m-%d %H:%M:%S").unwrap_or_default().bright_yellow()
+            );
+        }
+
+        if !filters.is_empty() {
+            println!("{} {}{}",
+                "Filter pattern:".bright_blue().bold(),
+                filters.join(" OR ").bright_yellow(),
+                if filter.is_local() { " (matched locally)" } else { "" }
+            );
+        }
+
+        if let Some(ref grep) = grep {
+            println!("{} {}{}",
+                "Grep pattern:".bright_blue().bold(),
+                grep.bright_yellow(),
+                if invert_match { " (inverted)" } else { "" }
+            );
+        }
+
         println!("{} {}",
-            "End time:".bright_blue().bold(),
-            dt.format("%Y-%m-%d %H:%M:%S").to_string().bright_yellow()
+            "Max events:".bright_blue().bold(),
+            if limit == usize::MAX {
+                "unlimited (fetching all in time range)".bright_yellow().to_string()
+            } else {
+                format::format_count(limit).bright_yellow().to_string()
+            }
         );
     }
 
-    if let Some(ref pattern) = filter {
-        println!("{} {}",
-            "Filter pattern:".bright_blue().bold(),
-            pattern.bright_yellow()
+    if limit == usize::MAX && !yes && resumed.is_none() && !confirm_large_query(start_time, end_time, warn_days.unwrap_or(DEFAULT_WARN_DAYS))? {
+        println!("{}", "Query cancelled".yellow());
+        return Ok(());
+    }
+
+    // Locally matched filters have to see every event before the limit applies
+    let fetch_limit = event_limit.filter(|_| !match_locally);
+
+    // With a limit there is something to measure against, so draw a bar
+    let progress = progress_mode.bar(quiet, "Fetching log events...", || match fetch_limit {
+        Some(limit) => ProgressBar::new(limit as u64).with_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{bar:30.cyan/blue}] {percent}% {msg}")
+                .unwrap()
+                .progress_chars("=> ")
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.green} {msg}")
+                .unwrap()
+        ),
+    });
+    progress.enable_steady_tick(std::time::Duration::from_millis(100));
+
+    let pages = Cell::new(0);
+    let on_page = |fetched: usize| {
+        pages.set(pages.get() + 1);
+        progress.set_position(fetched.min(limit) as u64);
+        progress.set_message(format!("{} events fetched, {} pages", fetched, pages.get()));
+    };
+
+    // Whether a message passes the local filters and, if so, whether
+    // --json-only/--non-json-only keep it
+    let local_match = |message: &str| {
+        (filter.matches(message) && grep_matcher.as_ref().is_none_or(|grep| grep.matches(message)))
+            .then(|| json_select.is_none_or(|select| select.matches(message)))
+    };
+
+    // Without a limit, sampling would otherwise hold every event in the range,
+    // so matches are sampled page by page as they arrive instead
+    let page_sampling = sampling.filter(|_| {
+        event_limit.is_none() && limit_per_stream.is_none() && checkpoint.is_none() && resumed.is_none()
+    });
+    let mut skipped_json = 0;
+    let mut matched = None;
+
+    let mut events: Vec<(String, FilteredLogEvent)> = if let Some(sampling) = page_sampling {
+        let mut sampler = Sampler::new(sampling, seed);
+        let mut fetched = 0;
+        for group in log_groups.as_deref().unwrap_or(std::slice::from_ref(&log_group)) {
+            client.scan_log_events(group, start_time, end_time, filter.server.as_deref(), |page| {
+                fetched += page.len();
+                on_page(fetched);
+                for event in page {
+                    match local_match(event.message.as_deref().unwrap_or_default()) {
+                        Some(true) => sampler.push((group.clone(), event)),
+                        Some(false) => skipped_json += 1,
+                        None => {}
+                    }
+                }
+                Ok(())
+            }).await?;
+        }
+        matched = Some(sampler.seen());
+        let mut events = sampler.finish();
+        events.sort_by_key(|(_, event)| (event.timestamp, event.ingestion_time));
+        events
+    } else {
+        match log_groups {
+            Some(ref groups) => client.get_log_events_multi(
+                groups,
+                start_time,
+                end_time,
+                filter.server.as_deref(),
+                fetch_limit,
+                !oldest,
+                on_page,
+            ).await?,
+            None => {
+                let checkpoint = match (checkpoint, resume_checkpoint.zip(resumed)) {
+                    (Some(path), _) => Some((Checkpoint::create(&path, &checkpoint_query)?, None, path)),
+                    (None, Some((path, saved))) => Some((Checkpoint::reopen(&path, &saved)?, Some(saved), path)),
+                    (None, None) => None,
+                };
+                let events = match (checkpoint, fetch_limit) {
+                    (Some((mut checkpoint, saved, path)), _) => {
+                        let events = fetch_with_checkpoint(&client, &checkpoint_query, &mut checkpoint, saved, &on_page).await?;
+                        if client.timed_out().is_some() {
+                            progress.suspend(|| eprintln!("{} Progress kept in {}; continue with --resume {}",
+                                "Note:".bright_magenta().bold(),
+                                path.display(),
+                                path.display()
+                            ));
+                        } else {
+                            checkpoint.remove()?;
+                            if !quiet {
+                                progress.suspend(|| println!("{} {}", "Query complete, removed checkpoint".bright_black(), path.display()));
+                            }
+                        }
+                        events
+                    }
+                    (None, Some(limit)) if !oldest => client.get_newest_log_events(
+                        &log_group,
+                        start_time,
+                        end_time,
+                        filter.server.as_deref(),
+                        limit,
+                        concurrency,
+                        on_page,
+                    ).await?,
+                    (None, _) => client.get_log_events_parallel(
+                        &log_group,
+                        start_time,
+                        end_time,
+                        filter.server.as_deref(),
+                        fetch_limit,
+                        concurrency,
+                        on_page,
+                    ).await?,
+                };
+                events.into_iter().map(|event| (log_group.clone(), event)).collect()
+            }
+        }
+    };
+    let multi_group = log_groups.is_some();
+    let resume = follow.is_some().then(|| {
+        tail::Resume::after(events.iter().map(|(_, event)| event), end_time.unwrap_or_else(|| Utc::now().timestamp_millis()))
+    });
+
+    progress.finish_and_clear();
+
+    if let Some(timeout) = client.timed_out() {
+        eprintln!("{} Timed out after {}; showing the {} events fetched so far",
+            "Note:".bright_magenta().bold(),
+            format::format_delay(timeout.as_millis() as i64),
+            format::format_count(events.len())
         );
     }
 
-    println!("{} {}",
-        "Max events:".bright_blue().bold(),
-        if limit == usize::MAX {
-            "unlimited (fetching all in time range)".bright_yellow().to_string()
+    let mut context = None;
+    let mut capped = BTreeMap::new();
+    if match_locally && page_sampling.is_none() {
+        let mut matches: Vec<bool> = events.iter()
+            .map(|(_, event)| match local_match(event.message.as_deref().unwrap_or_default()) {
+                Some(selected) => {
+                    skipped_json += usize::from(!selected);
+                    selected
+                }
+                None => false,
+            })
+            .collect();
+        if let Some(cap) = limit_per_stream {
+            capped = cap_per_stream(&events, &mut matches, cap, oldest);
+        }
+        if let Some(limit) = event_limit {
+            let mut positions: Vec<usize> = (0..matches.len()).filter(|&i| matches[i]).collect();
+            if oldest {
+                positions.truncate(limit);
+            } else {
+                positions.drain(..positions.len().saturating_sub(limit));
+            }
+            matches = vec![false; matches.len()];
+            for i in positions {
+                matches[i] = true;
+            }
+        }
+
+        if with_context {
+            let lines = context_lines(&matches, before, after);
+            events = lines.iter().map(|line| events[line.index].clone()).collect();
+            context = Some(lines);
         } else {
-            limit.to_string().bright_yellow().to_string()
+            let mut matches = matches.into_iter();
+            events.retain(|_| matches.next().unwrap_or(false));
         }
-    );
+    }
 
-    let spinner = ProgressBar::new_spinner();
-    spinner.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg} {prefix}")
-            .unwrap()
-    );
-    spinner.set_message("Fetching log events...");
+    if !capped.is_empty() && !quiet {
+        println!("{} {} streams at {} events (--limit-per-stream)",
+            "Capped".bright_black(),
+            format::format_count(capped.len()).bright_yellow(),
+            format::format_count(limit_per_stream.unwrap_or_default())
+        );
+        for ((group, stream), (kept, dropped)) in &capped {
+            let stream = format!("[{}]", stream.color(format::stream_color(stream)));
+            let stream = if multi_group {
+                format!("[{}] {}", group.magenta(), stream)
+            } else {
+                stream
+            };
+            println!("  {} kept {}, dropped {}",
+                stream,
+                format::format_count(*kept),
+                format::format_count(*dropped).bright_yellow()
+            );
+        }
+    }
 
-    let events = client.get_log_events(
-        &log_group,
-        start_time,
-        end_time,
-        filter.as_deref(),
-        if limit == usize::MAX { None } else { Some(limit) },
-    ).await?;
+    if skipped_json > 0 && !quiet {
+        println!("{} {} {} lines",
+            "Skipped".bright_black(),
+            format::format_count(skipped_json).bright_yellow(),
+            if json_select == Some(JsonSelect::Json) { "non-JSON" } else { "JSON" }
+        );
+    }
 
-    spinner.finish_and_clear();
+    // Sampled events are picked from the matches, keeping their order
+    if let Some(sampling) = sampling.filter(|_| page_sampling.is_none()) {
+        let mut sampler = Sampler::new(sampling, seed);
+        events.into_iter().for_each(|event| sampler.push(event));
+        matched = Some(sampler.seen());
+        events = sampler.finish();
+    }
+
+    if output.is_json() || output == OutputFormat::Yaml {
+        let records: Vec<Value> = events.iter()
+            .enumerate()
+            .map(|(i, (group, event))| {
+                let mut record = serde_json::json!({
+                    "timestamp": event.timestamp
+                        .and_then(DateTime::<Utc>::from_timestamp_millis)
+                        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+                    "stream": event.log_stream_name,
+                    "message": event.message.as_deref().map(format::strip_ansi),
+                });
+                if multi_group {
+                    record["log_group"] = Value::String(group.clone());
+                }
+                // Neighbours shown by --context, as opposed to matches
+                if context.as_ref().is_some_and(|lines: &Vec<ContextLine>| !lines[i].is_match) {
+                    record["context"] = Value::Bool(true);
+                }
+                record
+            })
+            .collect();
+        let rendered = output::render_events(records, output)?;
+        if !rendered.is_empty() {
+            out.write_line(rendered.trim_end());
+        }
+        if show_cost {
+            print_cost(&client, &cost_pattern, start_time, end_time, cost_per_gb, quiet).await?;
+        }
+        return match client.timed_out() {
+            Some(timeout) => Err(CwlError::timeout(timeout).into()),
+            None => Ok(()),
+        };
+    }
 
     if events.is_empty() {
-        println!("{}", "No log events found matching criteria".yellow());
-        return Ok(());
+        if !quiet {
+            println!("{}", "No log events found matching criteria".yellow());
+        }
+        if show_cost {
+            print_cost(&client, &cost_pattern, start_time, end_time, cost_per_gb, quiet).await?;
+        }
+        return continue_following(client, follow, resume, out).await;
     }
 
-    println!("{} {} events\n",
-        "Found".bright_green().bold(),
-        events.len().to_string().bright_yellow().bold()
-    );
+    if !quiet {
+        match matched {
+            Some(matched) => println!("{} {} of {} matched events\n",
+                "Sampled".bright_green().bold(),
+                format::format_count(events.len()).bright_yellow().bold(),
+                format::format_count(matched).bright_yellow()
+            ),
+            None => println!("{} {} events\n",
+                "Found".bright_green().bold(),
+                format::format_count(events.len()).bright_yellow().bold()
+            ),
+        }
+    }
+
+    if pager && output == OutputFormat::Colored {
+        out = out.with_pager(Pager::spawn());
+    }
 
-    if formatted {
+    if formatted || output == OutputFormat::Csv {
         let mut log_lines = Vec::new();
+        let mut delays = Vec::new();
 
-        for event in &events {
+        let mut field_times = 0usize;
+        for (group, event) in &events {
             if let Some(ref message) = event.message {
-                let timestamp = event.timestamp.map(|ts| {
-                    DateTime::<Utc>::from_timestamp_millis(ts)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S.%3f").to_string())
-                        .unwrap_or_else(|| "Unknown time".to_string())
-                }).unwrap_or_else(|| "Unknown time".to_string());
+                // Events without a usable --time-field keep their CloudWatch time
+                let field_time = time_field.as_deref().and_then(|field| json_formatter::field_timestamp(message, field));
+                field_times += usize::from(field_time.is_some());
+                let timestamp = field_time.or(event.timestamp)
+                    .and_then(|ts| time_format.format(ts, tz))
+                    .unwrap_or_else(|| "Unknown time".to_string());
 
                 let stream_name = event.log_stream_name
-                    .as_ref()
-                    .map(|s| s.clone())
+                    .clone()
                     .unwrap_or_default();
+                let stream_name = if multi_group {
+                    format!("{}:{}", group, stream_name)
+                } else {
+                    stream_name
+                };
 
                 let mut parsed_message = message.clone();
 
@@ -110,48 +723,679 @@ pub async fn run(
 
                 let log_line = format!("[{}] [{}] {}", timestamp, stream_name, parsed_message);
                 log_lines.push(log_line);
+                delays.push(ingestion_delay(event));
+            }
+        }
+
+        if let Some(ref field) = time_field {
+            if field_times == 0 && !log_lines.is_empty() && !quiet {
+                println!("{} No event has a timestamp in {}; showing CloudWatch event times\n",
+                    "Note:".bright_magenta().bold(),
+                    field.bright_yellow()
+                );
             }
         }
 
-        let output = json_formatter::analyze_json_logs(&log_lines);
-        json_formatter::print_formatted_table(&output);
+        let mut table = json_formatter::analyze_json_logs(&log_lines, format_hint, flatten_depth, lambda, emf);
+        let mut pinned = pin;
+        if fields_from_filter {
+            pinned.extend(filters.iter().chain(&grep).flat_map(|pattern| filter_utils::referenced_fields(pattern)));
+        }
+        if let Some(min_frequency) = min_frequency {
+            let pruned = json_formatter::prune_sparse_columns(&mut table, min_frequency, &pinned);
+            if pruned > 0 && !quiet {
+                println!("{} {} sparse columns (in fewer than {}% of rows)\n",
+                    "Pruned".bright_black(),
+                    format::format_count(pruned).bright_yellow(),
+                    min_frequency
+                );
+            }
+        }
+        // CSV is read by other tools, so the computed column is opt-in there
+        if output != OutputFormat::Csv || show_delay {
+            json_formatter::add_ingestion_delays(&mut table, &delays, lag_threshold);
+        }
+        json_formatter::pin_columns(&mut table, &pinned);
+        if output == OutputFormat::Csv {
+            let csv = json_formatter::render_csv(&table)?;
+            out.write_line(csv.trim_end_matches('\n'));
+        } else {
+            json_formatter::cap_columns(&mut table, &col_width);
+            if !no_truncate {
+                let width = max_width.or_else(|| std::io::stdout().is_terminal().then(format::terminal_width));
+                json_formatter::fit_columns(&mut table, width);
+            }
+            json_formatter::print_formatted_table(&table, quiet, !no_level_color, &mut out);
+        }
     } else {
-        let regex_pattern = filter.as_ref()
-            .map(|f| Regex::new(&regex::escape(f)))
-            .transpose()?;
+        let regex_pattern = if highlight {
+            filter.highlight_pattern(grep_matcher.as_ref(), &regex_options)?
+        } else {
+            None
+        };
+        let mut deduper = dedup.then(|| Deduper::new(dedup_strict, false));
 
-        for event in &events {
-            if let Some(ref message) = event.message {
-                let timestamp = event.timestamp.map(|ts| {
-                    DateTime::<Utc>::from_timestamp_millis(ts)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
-                        .unwrap_or_else(|| "Unknown time".to_string())
-                }).unwrap_or_else(|| "Unknown time".to_string());
+        for (i, (group, event)) in events.iter().enumerate() {
+            let context_line = context.as_ref().map(|lines: &Vec<ContextLine>| &lines[i]);
+            if context_line.is_some_and(|line| line.gap_before) {
+                out.write_line(&"--".bright_black().to_string());
+            }
+            let is_match = context_line.is_none_or(|line| line.is_match);
 
-                let stream_name = event.log_stream_name
-                    .as_ref()
-                    .map(|s| format!("[{}]", s.cyan()))
-                    .unwrap_or_default();
+            if let Some(ref event_message) = event.message {
+                let message = if strip_ansi {
+                    format::strip_ansi(event_message)
+                } else {
+                    event_message.clone()
+                };
+
+                if raw {
+                    out.write_line(message.trim_end_matches(['\n', '\r']));
+                    continue;
+                }
 
-                let formatted_message = if regex_pattern.is_some() {
-                    format::highlight_matches(&message, regex_pattern.as_ref().unwrap())
+                let timestamp = event.timestamp
+                    .and_then(|ts| time_format.format(ts, tz))
+                    .unwrap_or_else(|| "Unknown time".to_string());
+
+                if hexdump {
+                    let stream = event.log_stream_name.as_deref().unwrap_or_default();
+                    out.write_line(&format!("[{}] [{}]", timestamp.bright_blue(), stream.color(format::stream_color(stream))));
+                    for line in format::hexdump(event_message.as_bytes()) {
+                        out.write_line(&format!("  {}", line));
+                    }
+                    continue;
+                }
+
+                let message = &format::sanitize_for_terminal(&message);
+
+                let line = match template {
+                    Some(ref template) => {
+                        let mut line = template.render(&format::TemplateContext {
+                            timestamp: &timestamp,
+                            stream: event.log_stream_name.as_deref().unwrap_or_default(),
+                            group,
+                            message,
+                        });
+                        // Cut after rendering, so {json:...} still sees valid JSON
+                        format::truncate_line(&mut line, max_line_length);
+                        format::fold_lines(&line, 0, !no_wrap)
+                    }
+                    None => {
+                        let stream_name = event.log_stream_name
+                            .as_ref()
+                            .map(|s| format!("[{}]", s.color(format::stream_color(s))))
+                            .unwrap_or_default();
+                        let stream_name = if multi_group {
+                            format!("[{}] {}", group.magenta(), stream_name)
+                        } else {
+                            stream_name
+                        };
+
+                        let mut body = if fields.is_empty() {
+                            message.clone()
+                        } else {
+                            format::project_fields(message, &fields).unwrap_or_else(|| message.clone())
+                        };
+                        format::truncate_line(&mut body, max_line_length);
+
+                        let formatted_message = match regex_pattern {
+                            Some(ref pattern) if is_match => format::highlight_matches(&body, pattern, highlight_style),
+                            _ => body,
+                        };
+
+                        let delay = match ingestion_delay(event) {
+                            Some(delay) if show_delay => {
+                                let text = format!("(+{})", format::format_delay(delay));
+                                if lag_threshold.is_some_and(|threshold| delay > threshold) {
+                                    format!(" {}", text.red().bold())
+                                } else {
+                                    format!(" {}", text.bright_black())
+                                }
+                            }
+                            _ => String::new(),
+                        };
+
+                        let prefix = format!("[{}]{} {}", timestamp.bright_blue(), delay, stream_name);
+                        let indent = format::visible_width(&prefix) + 1;
+                        format!("{} {}", prefix, format::fold_lines(&formatted_message, indent, !no_wrap))
+                    }
+                };
+                let line = if is_match {
+                    line
                 } else {
-                    message.clone()
+                    format::strip_ansi(&line).dimmed().to_string()
                 };
 
-                println!("[{}] {} {}",
-                    timestamp.bright_blue(),
-                    stream_name,
-                    formatted_message
-                );
+                match deduper {
+                    Some(ref mut deduper) => deduper.push(message, line, &mut out),
+                    None => out.write_line(&line),
+                }
             }
         }
+
+        if let Some(ref mut deduper) = deduper {
+            deduper.finish(&mut out);
+        }
     }
 
-    println!("\n{} {} total events displayed",
-        "✓".bright_green().bold(),
-        events.len().to_string().bright_yellow()
-    );
+    out.close_pager();
 
+    if !quiet {
+        match log_groups {
+            Some(ref groups) => println!("\n{} {} total events displayed from {} log groups",
+                "✓".bright_green().bold(),
+                format::format_count(events.len()).bright_yellow(),
+                format::format_count(groups.len()).bright_yellow()
+            ),
+            None => println!("\n{} {} total events displayed",
+                "✓".bright_green().bold(),
+                format::format_count(events.len()).bright_yellow()
+            ),
+        }
+    }
+
+    if show_cost {
+        print_cost(&client, &cost_pattern, start_time, end_time, cost_per_gb, quiet).await?;
+    } else if !quiet {
+        println!("{}", format!("~{} returned", format::format_bytes(client.bytes_returned() as i64)).bright_black());
+    }
+
+    if let Some(timeout) = client.timed_out() {
+        bail!(CwlError::timeout(timeout));
+    }
+
+    continue_following(client, follow, resume, out).await
+}
+
+/// Prints the `--show-cost` estimate for the groups matching `pattern`.
+/// Asked for explicitly, so it goes to stderr when stdout only carries
+/// events (`quiet`).
+async fn print_cost(client: &impl LogsBackend, pattern: &str, start_time: Option<i64>, end_time: Option<i64>, cost_per_gb: f64, quiet: bool) -> Result<()> {
+    let returned = client.bytes_returned();
+    let groups = client.describe_matching_log_groups(pattern).await?;
+    let scanned = estimate_scanned_bytes(&groups, start_time, end_time, Utc::now().timestamp_millis(), returned);
+    let cost = format!("{} ~{} returned, ~{} scanned (estimated from stored bytes), about ${:.4} at ${}/GB",
+        "Cost:".bright_magenta().bold(),
+        format::format_bytes(returned as i64),
+        format::format_bytes(scanned as i64).bright_yellow(),
+        scanned as f64 / BYTES_PER_GB * cost_per_gb,
+        cost_per_gb
+    );
+    if quiet {
+        eprintln!("{}", cost);
+    } else {
+        println!("{}", cost);
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// FilterLogEvents doesn't report how much data it scanned, so this assumes
+/// each group's stored bytes are spread evenly over the time it keeps events
+/// for and takes the share the queried range covers. Never less than
+/// `returned`, which was certainly read.
+fn estimate_scanned_bytes(groups: &[LogGroupInfo], start_time: Option<i64>, end_time: Option<i64>, now: i64, returned: u64) -> u64 {
+    let scanned: f64 = groups.iter()
+        .map(|group| {
+            let retained_from = group.retention_in_days.map(|days| now - i64::from(days) * 86_400_000);
+            let kept_from = [group.creation_time, retained_from].into_iter().flatten().max().unwrap_or(now);
+            let from = start_time.unwrap_or(kept_from).max(kept_from);
+            let to = end_time.unwrap_or(now).min(now);
+            let share = ((to - from).max(0) as f64 / (now - kept_from).max(1) as f64).min(1.0);
+            group.stored_bytes.unwrap_or(0).max(0) as f64 * share
+        })
+        .sum();
+    (scanned as u64).max(returned)
+}
+
+/// Fetches `query` page by page, appending each page to `checkpoint`, and
+/// returns the events `saved` by an earlier run followed by the new ones. A
+/// saved token older than `TOKEN_VALIDITY_MILLIS` has expired, so the fetch
+/// starts again from the newest saved event, skipping the ones already saved.
+async fn fetch_with_checkpoint(
+    client: &impl LogsBackend,
+    query: &CheckpointQuery,
+    checkpoint: &mut Checkpoint,
+    saved: Option<Saved>,
+    progress: impl Fn(usize),
+) -> Result<Vec<FilteredLogEvent>> {
+    let (mut events, resume_point) = match saved {
+        Some(saved) => {
+            let point = saved.resume_point(Utc::now().timestamp_millis());
+            (saved.events, point)
+        }
+        None => (Vec::new(), ResumePoint::From(query.start_time)),
+    };
+    let (start_time, next_token) = match resume_point {
+        ResumePoint::Done => return Ok(events),
+        ResumePoint::Token(token) => (query.start_time, Some(token)),
+        ResumePoint::From(start_time) => (start_time, None),
+    };
+
+    let seen: HashSet<String> = events.iter()
+        .filter(|event| event.timestamp.is_some() && event.timestamp == start_time)
+        .filter_map(|event| event.event_id.clone())
+        .collect();
+    let is_new = |event: &FilteredLogEvent| event.event_id.as_ref().is_none_or(|id| !seen.contains(id));
+
+    let mut fetched = events.len();
+    progress(fetched);
+    let new_events = client.get_log_events_from_token(
+        &query.log_group,
+        start_time,
+        query.end_time,
+        query.filter_pattern.as_deref(),
+        StreamFilter::ALL,
+        None,
+        next_token,
+        |page, next_token| {
+            let page: Vec<FilteredLogEvent> = page.iter().filter(|event| is_new(event)).cloned().collect();
+            checkpoint.save_page(&page, next_token, Utc::now().timestamp_millis())?;
+            fetched += page.len();
+            progress(fetched);
+            Ok(())
+        },
+    ).await?;
+
+    events.extend(new_events.into_iter().filter(is_new));
+    Ok(events)
+}
+
+/// Hands over to `tail --follow` where the results ended, so nothing is
+/// missed or shown twice.
+async fn continue_following(
+    client: impl LogsBackend,
+    follow: Option<tail::TailOptions>,
+    resume: Option<tail::Resume>,
+    out: TeeWriter,
+) -> Result<()> {
+    let Some(follow) = follow else {
+        return Ok(());
+    };
+
+    // Flush the output file before tail appends to it
+    drop(out);
+    tail::run(client, tail::TailOptions { resume, ..follow }).await
+}
+
+/// How long CloudWatch took to receive an event after it happened.
+fn ingestion_delay(event: &FilteredLogEvent) -> Option<i64> {
+    event.ingestion_time.zip(event.timestamp).map(|(ingested, happened)| ingested - happened)
+}
+
+/// An event shown by `--context`: a match or one of its neighbours.
+#[derive(Debug, PartialEq)]
+struct ContextLine {
+    index: usize,
+    is_match: bool,
+    /// Not adjacent to the previously shown event, so a `--` separator goes first
+    gap_before: bool,
+}
+
+/// Picks the events within `before`/`after` positions of a match, like
+/// `grep -C`, merging overlapping windows.
+fn context_lines(matches: &[bool], before: usize, after: usize) -> Vec<ContextLine> {
+    let mut lines: Vec<ContextLine> = Vec::new();
+
+    for (i, _) in matches.iter().enumerate().filter(|(_, &is_match)| is_match) {
+        let start = lines.last()
+            .map_or(0, |line| line.index + 1)
+            .max(i.saturating_sub(before));
+        let end = (i + after).min(matches.len() - 1);
+
+        for (index, &is_match) in matches.iter().enumerate().take(end + 1).skip(start) {
+            let gap_before = index == start && lines.last().is_some_and(|line| line.index + 1 != index);
+            lines.push(ContextLine { index, is_match, gap_before });
+        }
+    }
+
+    lines
+}
+
+/// Asks for confirmation before an unbounded query spanning more than
+/// `warn_days`. Non-interactive sessions, and queries without a start time
+/// whose span isn't known, are never prompted.
+fn confirm_large_query(start_time: Option<i64>, end_time: Option<i64>, warn_days: u64) -> Result<bool> {
+    let Some(start_time) = start_time else {
+        return Ok(true);
+    };
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Ok(true);
+    }
+
+    let span = end_time.unwrap_or_else(|| Utc::now().timestamp_millis()) - start_time;
+    if span <= warn_days as i64 * 86_400_000 {
+        return Ok(true);
+    }
+
+    eprint!("{} This query has no --limit and spans {} days. This may scan a large volume and incur cost. Continue? [y/N] ",
+        "Warning:".yellow().bold(),
+        span / 86_400_000
+    );
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Keeps at most `cap` matches from each log stream, the newest unless
+/// `oldest`, so one noisy stream can't crowd out the rest. Streams are told
+/// apart by log group as well as name. Returns the kept and dropped counts of
+/// each `(group, stream)` that went over.
+fn cap_per_stream(events: &[(String, FilteredLogEvent)], matches: &mut [bool], cap: usize, oldest: bool) -> BTreeMap<(String, String), (usize, usize)> {
+    let mut positions: Vec<usize> = (0..matches.len()).filter(|&i| matches[i]).collect();
+    if !oldest {
+        positions.reverse();
+    }
+
+    let mut counts: HashMap<(&str, &str), (usize, usize)> = HashMap::new();
+    for i in positions {
+        let (group, event) = &events[i];
+        let stream = event.log_stream_name.as_deref().unwrap_or_default();
+        let (kept, dropped) = counts.entry((group, stream)).or_default();
+        if *kept < cap {
+            *kept += 1;
+        } else {
+            *dropped += 1;
+            matches[i] = false;
+        }
+    }
+
+    counts.into_iter()
+        .filter(|(_, (_, dropped))| *dropped > 0)
+        .map(|((group, stream), counts)| ((group.to_string(), stream.to_string()), counts))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::backend::MockBackend;
+
+    #[test]
+    fn test_context_lines_merge_and_separate() {
+        let matches = [false, true, false, false, false, false, true, false, true, false];
+        let shown: Vec<(usize, bool, bool)> = context_lines(&matches, 1, 1).iter()
+            .map(|line| (line.index, line.is_match, line.gap_before))
+            .collect();
+
+        assert_eq!(shown, [
+            (0, false, false), (1, true, false), (2, false, false),
+            (5, false, true), (6, true, false), (7, false, false), (8, true, false), (9, false, false),
+        ]);
+    }
+
+    #[test]
+    fn test_cap_per_stream_keeps_newest_of_each_stream() {
+        let events: Vec<(String, FilteredLogEvent)> = [("app", "noisy"), ("app", "quiet"), ("app", "noisy"), ("app", "noisy"), ("app", "noisy"), ("worker", "noisy")].iter()
+            .map(|(group, stream)| (group.to_string(), FilteredLogEvent::builder().log_stream_name(*stream).build()))
+            .collect();
+        let mut matches = vec![true, true, true, false, true, true];
+
+        // The same stream name in another group is a different stream
+        let capped = cap_per_stream(&events, &mut matches, 2, false);
+        assert_eq!(matches, [false, true, true, false, true, true]);
+        assert_eq!(capped.into_iter().collect::<Vec<_>>(), [(("app".to_string(), "noisy".to_string()), (2, 1))]);
+
+        let mut matches = vec![true; 6];
+        assert!(cap_per_stream(&events, &mut matches, 4, true).is_empty());
+    }
+
+    #[test]
+    fn test_estimate_scanned_bytes_takes_share_of_stored_bytes() {
+        let day = 86_400_000;
+        let now = 30 * day;
+        let group = LogGroupInfo {
+            name: "app".to_string(),
+            creation_time: Some(0),
+            retention_in_days: Some(10),
+            stored_bytes: Some(10_000),
+            arn: None,
+            metric_filter_count: None,
+            log_group_class: None,
+            last_event_time: None,
+        };
+
+        // Events older than the 10 day retention are gone, so a day is a tenth
+        assert_eq!(estimate_scanned_bytes(std::slice::from_ref(&group), Some(now - day), None, now, 0), 1_000);
+        assert_eq!(estimate_scanned_bytes(std::slice::from_ref(&group), Some(0), None, now, 0), 10_000);
+        assert_eq!(estimate_scanned_bytes(std::slice::from_ref(&group), Some(now - day), None, now, 5_000), 5_000);
+        assert_eq!(estimate_scanned_bytes(&[], None, None, now, 42), 42);
+    }
+
+    #[tokio::test]
+    async fn test_missing_log_group_suggests_similar_names() {
+        let backend = MockBackend::default()
+            .with_event("/aws/lambda/orders", "a", 1_000, "x")
+            .with_event("/aws/lambda/users", "a", 1_000, "x");
+
+        let err = run(backend, QueryOptions {
+            log_group: "/aws/lambda/odrers".to_string(),
+            start: Some("0".to_string()),
+            limit: usize::MAX,
+            yes: true,
+            quiet: true,
+            ..Default::default()
+        }).await.unwrap_err();
+
+        assert_eq!(crate::error::exit_code(&err), crate::error::EXIT_NOT_FOUND);
+        assert_eq!(format!("{:#}", err), "Log group '/aws/lambda/odrers' not found in region us-east-1; \
+            did you mean '/aws/lambda/orders'? Search for it with `cwl groups -f odrers`");
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_events_are_printed_oldest_first() {
+        let backend = MockBackend::default()
+            .with_event("app", "web-2", 3_000, "third")
+            .with_event("app", "web-1", 1_000, "first")
+            .with_event("app", "web-2", 2_000, "second");
+        let output_file = tempfile::NamedTempFile::new().unwrap();
+
+        run(backend, QueryOptions {
+            log_group: "app".to_string(),
+            start: Some("0".to_string()),
+            limit: usize::MAX,
+            yes: true,
+            raw: true,
+            quiet: true,
+            output_file: Some(output_file.path().to_path_buf()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let written = std::fs::read_to_string(output_file.path()).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), ["first", "second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn test_local_filters_and_limit_against_mock_backend() {
+        let backend = MockBackend::default()
+            .with_event("app", "web-1", 1_000, "ERROR db down")
+            .with_event("app", "web-1", 2_000, "ERROR healthcheck failed")
+            .with_event("app", "web-2", 3_000, "INFO request timeout")
+            .with_event("app", "web-2", 4_000, "ERROR disk full")
+            .with_event("other", "x", 5_000, "ERROR elsewhere");
+        let output_file = tempfile::NamedTempFile::new().unwrap();
+
+        run(backend, QueryOptions {
+            log_group: "app".to_string(),
+            start: Some("0".to_string()),
+            filters: vec!["ERROR -healthcheck".to_string(), "timeout".to_string()],
+            limit: 2,
+            raw: true,
+            quiet: true,
+            output_file: Some(output_file.path().to_path_buf()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let written = std::fs::read_to_string(output_file.path()).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), ["INFO request timeout", "ERROR disk full"]);
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_output_writes_one_object_per_event() {
+        let backend = MockBackend::default()
+            .with_event("app", "web-1", 1_000, r#"{"level":"ERROR","msg":"db down"}"#)
+            .with_event("app", "web-1", 2_000, "\x1b[31mplain\x1b[0m");
+        let output_file = tempfile::NamedTempFile::new().unwrap();
+
+        run(backend, QueryOptions {
+            log_group: "app".to_string(),
+            start: Some("0".to_string()),
+            limit: usize::MAX,
+            output: OutputFormat::Ndjson,
+            output_file: Some(output_file.path().to_path_buf()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let written = std::fs::read_to_string(output_file.path()).unwrap();
+        let records: Vec<Value> = written.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["stream"], "web-1");
+        assert_eq!(records[0]["parsed"]["level"], "ERROR");
+        assert_eq!(records[1]["message"], "plain");
+    }
+
+    #[tokio::test]
+    async fn test_sample_n_without_limit_keeps_n_matches_in_order() {
+        let mut backend = MockBackend::default();
+        for i in 0..50 {
+            let level = if i % 2 == 0 { "ERROR" } else { "INFO" };
+            backend = backend.with_event("app", "web-1", 1_000 * (50 - i), &format!("{} {}", level, i));
+        }
+        let output_file = tempfile::NamedTempFile::new().unwrap();
+
+        run(backend, QueryOptions {
+            log_group: "app".to_string(),
+            start: Some("0".to_string()),
+            grep: Some("ERROR".to_string()),
+            limit: usize::MAX,
+            sampling: Some(Sampling::Count(5)),
+            seed: Some(1),
+            yes: true,
+            raw: true,
+            quiet: true,
+            output_file: Some(output_file.path().to_path_buf()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let written = std::fs::read_to_string(output_file.path()).unwrap();
+        let numbers: Vec<i64> = written.lines()
+            .map(|line| line.strip_prefix("ERROR ").unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(numbers.len(), 5);
+        // Oldest first, which is the reverse of the order they were added in
+        assert!(numbers.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[tokio::test]
+    async fn test_csv_has_ingestion_delay_column_only_with_show_delay() {
+        for show_delay in [false, true] {
+            let backend = MockBackend::default().with_event("app", "web-1", 1_000, r#"{"level":"INFO"}"#);
+            let output_file = tempfile::NamedTempFile::new().unwrap();
+
+            run(backend, QueryOptions {
+                log_group: "app".to_string(),
+                start: Some("0".to_string()),
+                limit: usize::MAX,
+                output: OutputFormat::Csv,
+                show_delay,
+                output_file: Some(output_file.path().to_path_buf()),
+                ..Default::default()
+            }).await.unwrap();
+
+            let written = std::fs::read_to_string(output_file.path()).unwrap();
+            let header = written.lines().next().unwrap();
+            assert_eq!(header.contains("ingestion_delay"), show_delay, "{}", header);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_output_marks_context_events() {
+        let backend = MockBackend::default()
+            .with_event("app", "web-1", 1_000, "starting")
+            .with_event("app", "web-1", 2_000, "ERROR db down")
+            .with_event("app", "web-1", 3_000, "retrying");
+        let output_file = tempfile::NamedTempFile::new().unwrap();
+
+        run(backend, QueryOptions {
+            log_group: "app".to_string(),
+            start: Some("0".to_string()),
+            filters: vec!["ERROR".to_string()],
+            before: 1,
+            limit: usize::MAX,
+            output: OutputFormat::Ndjson,
+            output_file: Some(output_file.path().to_path_buf()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let written = std::fs::read_to_string(output_file.path()).unwrap();
+        let records: Vec<Value> = written.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["context"], true);
+        assert!(records[1].get("context").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_follow_picks_up_without_repeating_events() {
+        let backend = MockBackend::default()
+            .with_event("app", "web-1", 1_000, "first")
+            .with_event("app", "web-1", 2_000, "second")
+            .with_event("app", "web-2", 2_000, "second, other stream");
+        let output_file = tempfile::NamedTempFile::new().unwrap();
+
+        // The mock replays everything from the hand-off point once and returns
+        run(backend, QueryOptions {
+            log_group: "app".to_string(),
+            start: Some("0".to_string()),
+            limit: usize::MAX,
+            yes: true,
+            raw: true,
+            quiet: true,
+            follow: true,
+            output_file: Some(output_file.path().to_path_buf()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let written = std::fs::read_to_string(output_file.path()).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), ["first", "second", "second, other stream"]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_token_expired_skips_saved_events() {
+        let backend = MockBackend::default()
+            .with_event("app", "web-1", 1_000, "first")
+            .with_event("app", "web-1", 2_000, "second")
+            .with_event("app", "web-2", 2_000, "second, other stream")
+            .with_event("app", "web-1", 3_000, "third");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.jsonl");
+        let output_file = tempfile::NamedTempFile::new().unwrap();
+
+        let query = CheckpointQuery {
+            log_group: "app".to_string(),
+            start_time: Some(0),
+            end_time: Some(10_000),
+            filter_pattern: None,
+        };
+        let saved: Vec<FilteredLogEvent> = backend.events[..2].iter().map(|(_, event)| event.clone()).collect();
+        let mut checkpoint = Checkpoint::create(&path, &query).unwrap();
+        checkpoint.save_page(&saved, Some("2"), 0).unwrap();
+        drop(checkpoint);
+
+        run(backend, QueryOptions {
+            log_group: "app".to_string(),
+            limit: usize::MAX,
+            resume_checkpoint: Some(path.clone()),
+            raw: true,
+            quiet: true,
+            output_file: Some(output_file.path().to_path_buf()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let written = std::fs::read_to_string(output_file.path()).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), ["first", "second", "second, other stream", "third"]);
+        assert!(!path.exists());
+    }
+}
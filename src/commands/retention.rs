@@ -0,0 +1,124 @@
+use anyhow::{Result, bail};
+use colored::Colorize;
+use crate::aws::arn::group_name;
+use crate::aws::client::{CloudWatchClient, LogGroupInfo};
+use crate::error::CwlError;
+use std::io::{IsTerminal, Write};
+
+/// Retention periods (in days) accepted by the PutRetentionPolicy API.
+pub const ALLOWED_RETENTION_DAYS: [i32; 22] = [
+    1, 3, 5, 7, 14, 30, 60, 90, 120, 150, 180, 365, 400, 545, 731, 1096, 1827, 2192, 2557, 2922,
+    3288, 3653,
+];
+
+pub async fn run(
+    client: CloudWatchClient,
+    log_group: Option<String>,
+    all_matching: Option<String>,
+    days: i32,
+    yes: bool,
+    quiet: bool,
+) -> Result<()> {
+    if !ALLOWED_RETENTION_DAYS.contains(&days) {
+        bail!(CwlError::InvalidArgument(format!(
+            "Invalid retention of {} days. Allowed values: {}",
+            days,
+            ALLOWED_RETENTION_DAYS.map(|d| d.to_string()).join(", ")
+        )));
+    }
+
+    let all_matching = all_matching.map(|pattern| {
+        if pattern.contains('*') {
+            pattern
+        } else {
+            format!("{}*", pattern)
+        }
+    });
+
+    if let Some(request) = client.dry_run("PutRetentionPolicy") {
+        let request = match all_matching {
+            Some(pattern) => request.param("logGroupNamePattern", pattern),
+            None => request.param("logGroupName", log_group),
+        };
+        request.param("retentionInDays", days).print();
+        return Ok(());
+    }
+
+    let groups = match (log_group, all_matching) {
+        (_, Some(pattern)) => {
+            let groups = client.describe_matching_log_groups(&pattern).await?;
+            if groups.is_empty() {
+                bail!("No log groups match '{}'", pattern);
+            }
+
+            if !yes && !confirm(&groups, days)? {
+                println!("{}", "Cancelled".yellow());
+                return Ok(());
+            }
+            groups
+        }
+        (Some(name), None) => {
+            let name = group_name(&name);
+            let group = client.describe_log_groups(Some(&name)).await?
+                .into_iter()
+                .find(|group| group.name == name)
+                .ok_or_else(|| CwlError::LogGroupNotFound(format!("Log group not found: {}", name)))?;
+            vec![group]
+        }
+        (None, None) => bail!(CwlError::InvalidArgument("Specify a log group or --all-matching".to_string())),
+    };
+
+    for group in &groups {
+        client.put_retention_policy(&group.name, days).await?;
+
+        if quiet {
+            println!("{}", group.name);
+        } else {
+            println!("  {} {} {} {} {}",
+                "→".bright_cyan(),
+                group.name.bright_white(),
+                describe_retention(group.retention_in_days).bright_black(),
+                "→".bright_black(),
+                describe_retention(Some(days)).bright_green()
+            );
+        }
+    }
+
+    if !quiet {
+        println!("\n{} Updated retention for {} log groups",
+            "✓".bright_green().bold(),
+            groups.len().to_string().bright_yellow()
+        );
+    }
+
+    Ok(())
+}
+
+fn describe_retention(days: Option<i32>) -> String {
+    match days {
+        Some(days) => format!("{}d", days),
+        None => "never expires".to_string(),
+    }
+}
+
+/// Lists the affected groups and asks before changing them. Without a
+/// terminal to ask on, the change is refused unless `--yes` was given.
+fn confirm(groups: &[LogGroupInfo], days: i32) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        bail!(CwlError::InvalidArgument(
+            "Refusing to change retention for multiple groups without --yes in a non-interactive session".to_string()
+        ));
+    }
+
+    eprintln!("Retention will be set to {} for {} log groups:", describe_retention(Some(days)), groups.len());
+    for group in groups {
+        eprintln!("  {} ({})", group.name, describe_retention(group.retention_in_days));
+    }
+    eprint!("{} Continue? [y/N] ", "Warning:".yellow().bold());
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
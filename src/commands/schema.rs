@@ -0,0 +1,186 @@
+use anyhow::{bail, Result};
+use chrono_tz::Tz;
+use colored::Colorize;
+use crate::aws::client::CloudWatchClient;
+use crate::error::CwlError;
+use crate::utils::{format, time};
+use crate::utils::json_formatter::{self, FieldSchema};
+use crate::utils::output::{self, OutputFormat};
+use crate::utils::progress::ProgressMode;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+/// Events sampled when `--sample-size` isn't given.
+pub const DEFAULT_SAMPLE_SIZE: usize = 1000;
+
+pub struct SchemaOptions {
+    pub log_group: String,
+    /// How many of the newest events to look at
+    pub sample_size: usize,
+    pub since: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub default_since: Option<String>,
+    pub filter: Option<String>,
+    pub flatten_depth: usize,
+    pub tz: Tz,
+    pub output: OutputFormat,
+    pub progress: ProgressMode,
+    pub quiet: bool,
+}
+
+/// A field in `--output json`.
+#[derive(Debug, Serialize)]
+struct FieldRecord<'a> {
+    field: &'a str,
+    types: Vec<&'static str>,
+    count: usize,
+    /// Share of the sampled JSON messages with the field, from 0 to 1
+    frequency: f64,
+    example: &'a str,
+    max_width: usize,
+}
+
+/// Samples recent events and lists the fields their JSON messages contain,
+/// with how often each appears, the types it holds and an example value.
+pub async fn run(client: CloudWatchClient, options: SchemaOptions) -> Result<()> {
+    let SchemaOptions {
+        log_group,
+        sample_size,
+        since,
+        start,
+        end,
+        default_since,
+        filter,
+        flatten_depth,
+        tz,
+        output,
+        progress,
+        quiet,
+    } = options;
+    if matches!(output, OutputFormat::Csv | OutputFormat::Yaml) {
+        bail!(CwlError::InvalidArgument("schema only supports colored, raw, json and ndjson output".to_string()));
+    }
+    let json = output.is_json();
+    let quiet = quiet || json || output == OutputFormat::Raw;
+
+    let (start_time, end_time) = time::parse_time_range(since, start, end, default_since.as_deref())?;
+
+    if let Some(request) = client.dry_run("FilterLogEvents") {
+        request
+            .log_group(&log_group)
+            .time("startTime", start_time, tz)
+            .time("endTime", end_time, tz)
+            .param("filterPattern", filter)
+            .param("sampleSize", sample_size)
+            .param("order", "newest first")
+            .print();
+        return Ok(());
+    }
+
+    if !quiet {
+        println!("{} {}",
+            "Inferring schema of:".bright_blue().bold(),
+            log_group.bright_yellow()
+        );
+    }
+
+    let spinner = progress.bar(quiet, "Sampling log events...", || ProgressBar::new_spinner().with_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap()
+    ));
+    let events = client.get_newest_log_events(
+        &log_group,
+        start_time,
+        end_time,
+        filter.as_deref(),
+        sample_size,
+        1,
+        |count| spinner.set_message(format!("Sampling log events... {}", format::format_count(count))),
+    ).await?;
+    spinner.finish_and_clear();
+
+    let (fields, json_messages) = json_formatter::infer_schema(
+        events.iter().map(|event| event.message.as_deref().unwrap_or_default()),
+        flatten_depth,
+    );
+
+    if json {
+        let records: Vec<FieldRecord> = fields.iter()
+            .map(|field| FieldRecord {
+                field: &field.name,
+                types: field.types.iter().copied().collect(),
+                count: field.frequency,
+                frequency: field.frequency as f64 / json_messages.max(1) as f64,
+                example: &field.example,
+                max_width: field.max_width,
+            })
+            .collect();
+        let rendered = output::render_json(&records, output)?;
+        if !rendered.is_empty() {
+            println!("{}", rendered);
+        }
+        return Ok(());
+    }
+
+    if quiet {
+        for field in &fields {
+            println!("{}\t{}\t{}", field.name, type_list(field), field.frequency);
+        }
+        return Ok(());
+    }
+
+    if fields.is_empty() {
+        println!("{}", format!("No JSON messages among {} sampled events", format::format_count(events.len())).yellow());
+        return Ok(());
+    }
+
+    print_fields(&fields, json_messages);
+
+    println!("\n{} {} fields in {} of {} sampled events (the rest aren't JSON objects)",
+        "✓".bright_green().bold(),
+        format::format_count(fields.len()).bright_yellow(),
+        format::format_count(json_messages).bright_yellow(),
+        format::format_count(events.len())
+    );
+
+    Ok(())
+}
+
+fn type_list(field: &FieldSchema) -> String {
+    field.types.iter().copied().collect::<Vec<_>>().join("|")
+}
+
+fn print_fields(fields: &[FieldSchema], json_messages: usize) {
+    let seen: Vec<String> = fields.iter()
+        .map(|field| format!("{:.1}%", field.frequency as f64 * 100.0 / json_messages as f64))
+        .collect();
+    let types: Vec<String> = fields.iter().map(type_list).collect();
+    let name_width = fields.iter().map(|field| field.name.chars().count()).max().unwrap_or(0).max(5);
+    let type_width = types.iter().map(String::len).max().unwrap_or(0).max(4);
+    let seen_width = seen.iter().map(String::len).max().unwrap_or(0).max(4);
+    let example_width = format::terminal_width()
+        .saturating_sub(name_width + type_width + seen_width + 6)
+        .max(10);
+
+    // Padded before coloring, since escape codes would count towards the width
+    println!("{}  {}  {}  {}",
+        format!("{:<name_width$}", "FIELD").bright_cyan().bold(),
+        format!("{:<type_width$}", "TYPE").bright_cyan().bold(),
+        format!("{:>seen_width$}", "SEEN").bright_cyan().bold(),
+        "EXAMPLE".bright_cyan().bold()
+    );
+    for ((field, types), seen) in fields.iter().zip(&types).zip(&seen) {
+        let mut example = format::sanitize_for_terminal(&field.example).replace('\n', format::NEWLINE_MARKER);
+        if example.chars().count() > example_width {
+            example = example.chars().take(example_width - 1).collect::<String>() + "…";
+        }
+        println!("{}  {}  {:>seen_width$}  {}",
+            format!("{:<name_width$}", field.name).bright_yellow(),
+            format!("{:<type_width$}", types).bright_magenta(),
+            seen,
+            example.bright_black()
+        );
+    }
+}
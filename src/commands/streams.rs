@@ -0,0 +1,164 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use colored::Colorize;
+use crate::aws::client::{CloudWatchClient, LogStreamInfo};
+use crate::utils::{format, time};
+use crate::utils::output::{self, OutputFormat};
+use crate::utils::progress::ProgressMode;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+pub struct StreamsOptions {
+    pub log_group: String,
+    /// Only streams whose names start with this
+    pub prefix: Option<String>,
+    /// Most recently active first instead of by name
+    pub order_by_last_event: bool,
+    pub limit: Option<usize>,
+    pub tz: Tz,
+    pub output: OutputFormat,
+    pub progress: ProgressMode,
+    pub quiet: bool,
+}
+
+/// A log stream in `--output json`.
+#[derive(Debug, Serialize)]
+struct StreamRecord<'a> {
+    name: &'a str,
+    /// RFC 3339, like the times in `groups --output json`
+    creation_time: Option<String>,
+    last_event_time: Option<String>,
+}
+
+impl<'a> From<&'a LogStreamInfo> for StreamRecord<'a> {
+    fn from(stream: &'a LogStreamInfo) -> Self {
+        let rfc3339 = |ts: Option<i64>| ts
+            .and_then(DateTime::<Utc>::from_timestamp_millis)
+            .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true));
+        Self {
+            name: &stream.name,
+            creation_time: rfc3339(stream.creation_time),
+            last_event_time: rfc3339(stream.last_event_time),
+        }
+    }
+}
+
+/// Lists the streams of a log group with their creation and last event
+/// times.
+pub async fn run(client: CloudWatchClient, options: StreamsOptions) -> Result<()> {
+    let StreamsOptions {
+        log_group,
+        prefix,
+        order_by_last_event,
+        limit,
+        tz,
+        output,
+        progress,
+        quiet,
+    } = options;
+    let json = output.is_json();
+    let quiet = quiet || json || output == OutputFormat::Raw;
+
+    if let Some(request) = client.dry_run("DescribeLogStreams") {
+        request
+            .log_group(&log_group)
+            .param("logStreamNamePrefix", prefix.clone())
+            .param("orderBy", if order_by_last_event && prefix.is_none() { "LastEventTime" } else { "LogStreamName" })
+            .param("sortedLocally", (order_by_last_event && prefix.is_some()).then_some("by last event time"))
+            .param("limit", limit)
+            .print();
+        return Ok(());
+    }
+
+    let spinner = progress.bar(quiet, "Loading log streams...", || ProgressBar::new_spinner().with_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap()
+    ));
+    let streams = client.list_log_streams(&log_group, prefix.as_deref(), order_by_last_event, limit).await?;
+    spinner.finish_and_clear();
+
+    if json {
+        let records: Vec<StreamRecord> = streams.iter().map(StreamRecord::from).collect();
+        let rendered = output::render_json(&records, output)?;
+        if !rendered.is_empty() {
+            println!("{}", rendered);
+        }
+        return Ok(());
+    }
+
+    if quiet {
+        for stream in &streams {
+            println!("{}", stream.name);
+        }
+        return Ok(());
+    }
+
+    println!("{} {}",
+        "Log group:".bright_blue().bold(),
+        log_group.bright_yellow()
+    );
+
+    if streams.is_empty() {
+        println!("{}", match prefix {
+            Some(prefix) => format!("No log streams starting with {}", prefix).yellow(),
+            None => "No log streams found".yellow(),
+        });
+        return Ok(());
+    }
+
+    let format_time = |ts: Option<i64>| ts
+        .and_then(|ts| time::format_timestamp(ts, tz, "%Y-%m-%d %H:%M:%S"))
+        .unwrap_or_else(|| "-".to_string());
+    let rows: Vec<[String; 3]> = streams.iter()
+        .map(|stream| [stream.name.clone(), format_time(stream.creation_time), format_time(stream.last_event_time)])
+        .collect();
+    let name_width = rows.iter().map(|row| row[0].chars().count()).max().unwrap_or(0).max(6);
+    let time_width = rows.iter().map(|row| row[1].len()).max().unwrap_or(0).max(10);
+
+    // Padded before coloring, since escape codes would count towards the width
+    println!("{}  {}  {}",
+        format!("{:<name_width$}", "STREAM").bright_cyan().bold(),
+        format!("{:<time_width$}", "CREATED").bright_cyan().bold(),
+        "LAST EVENT".bright_cyan().bold()
+    );
+    for [name, created, last_event] in &rows {
+        println!("{}  {}  {}",
+            format!("{:<name_width$}", name).color(format::stream_color(name)),
+            format!("{:<time_width$}", created).bright_black(),
+            last_event.bright_blue()
+        );
+    }
+
+    // Paging stopped at the limit, so the full count isn't known
+    let more = if limit == Some(streams.len()) { " (--limit reached, there may be more)" } else { "" };
+    println!("\n{} {} streams{}",
+        "✓".bright_green().bold(),
+        format::format_count(streams.len()).bright_yellow(),
+        more.bright_black()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_record_times_are_rfc3339() {
+        let stream = LogStreamInfo {
+            name: "web/1".to_string(),
+            creation_time: Some(1_704_067_200_000),
+            last_event_time: None,
+            stored_bytes: None,
+        };
+        let json = serde_json::to_value(StreamRecord::from(&stream)).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "name": "web/1",
+            "creation_time": "2024-01-01T00:00:00.000Z",
+            "last_event_time": null,
+        }));
+    }
+}
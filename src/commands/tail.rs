@@ -1,114 +1,769 @@
-use anyhow::Result;
+use anyhow::{Result, Context, bail};
+use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
 use colored::Colorize;
 use regex::Regex;
-use chrono::{DateTime, Utc};
-use crate::aws::client::CloudWatchClient;
-use crate::utils::format;
+use chrono_tz::Tz;
+use crate::aws::backend::LogsBackend;
+use crate::aws::client::{PollInterval, StreamFilter};
+use crate::error::CwlError;
+use crate::utils::{format, time};
+use crate::utils::dedup::Deduper;
+use crate::utils::filter::{self as filter_utils, FilterSpec, Grep};
+use crate::utils::progress::ProgressMode;
+use crate::utils::tee::TeeWriter;
+use crate::utils::time::TimeFormat;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-
-pub async fn run(
-    client: CloudWatchClient,
-    log_group: String,
-    follow: bool,
-    filter: Option<String>,
-    highlight: bool,
-) -> Result<()> {
-    println!("{} {}",
-        "Tailing logs from:".bright_blue().bold(),
-        log_group.bright_yellow()
-    );
-
-    if let Some(ref pattern) = filter {
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How long a stream has to go quiet before `--group-by-stream` prints its
+/// buffered events in follow mode.
+const GROUP_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// The longest `--group-by-stream` holds a stream's events back, so a stream
+/// that never goes quiet is still printed.
+const GROUP_MAX_HOLD: Duration = Duration::from_secs(10);
+
+/// Events shown without `--follow`, unless `--lines` says otherwise.
+pub const DEFAULT_LINES: usize = 100;
+
+/// How far back a tail without `--follow` or `--since` looks.
+const DEFAULT_LOOKBACK_MILLIS: i64 = 5 * 60 * 1000;
+
+/// The most log stream names FilterLogEvents and StartLiveTail accept.
+const MAX_STREAM_NAMES: usize = 100;
+
+/// Where `query --follow` handed off: following starts at `timestamp`,
+/// skipping the events already shown from that millisecond.
+#[derive(Debug, Default)]
+pub struct Resume {
+    pub timestamp: i64,
+    pub seen: HashSet<String>,
+}
+
+impl Resume {
+    /// Picks up after the newest of `events`, or at `fallback` when there
+    /// were none.
+    pub fn after<'a>(events: impl IntoIterator<Item = &'a FilteredLogEvent>, fallback: i64) -> Self {
+        let mut resume = Resume { timestamp: fallback, seen: HashSet::new() };
+        let mut newest = None;
+        for event in events {
+            let Some(timestamp) = event.timestamp else { continue };
+            if newest.is_none_or(|newest| timestamp > newest) {
+                newest = Some(timestamp);
+                resume.seen.clear();
+            }
+            if newest == Some(timestamp) {
+                resume.seen.extend(event.event_id.clone());
+            }
+        }
+        if let Some(newest) = newest {
+            resume.timestamp = newest;
+        }
+        resume
+    }
+
+    fn is_new(&self, event: &FilteredLogEvent) -> bool {
+        event.event_id.as_ref().is_none_or(|id| !self.seen.contains(id))
+    }
+}
+
+#[derive(Default)]
+pub struct TailOptions {
+    pub log_group: String,
+    pub follow: bool,
+    pub filters: Vec<String>,
+    /// Accept empty `filters`/`grep` patterns instead of rejecting them
+    pub allow_empty_filter: bool,
+    /// Client-side regex applied after `filters`
+    pub grep: Option<String>,
+    /// Keep the messages `grep` doesn't match
+    pub invert_match: bool,
+    pub streams: Vec<String>,
+    /// Only streams whose names start with this; exclusive with `streams`
+    pub stream_prefix: Option<String>,
+    pub regex_options: format::RegexOptions,
+    pub from: Option<String>,
+    /// Most events shown without `follow`
+    pub lines: usize,
+    /// Follow from exactly here instead of `from`
+    pub resume: Option<Resume>,
+    pub poll_interval: Option<String>,
+    pub max_poll_interval: Option<String>,
+    /// Follow with StartLiveTail instead of polling
+    pub live: bool,
+    /// Mark `--filter` and `--grep` matches
+    pub highlight: bool,
+    pub highlight_style: format::HighlightStyle,
+    pub fields: Vec<String>,
+    pub output_file: Option<PathBuf>,
+    pub dedup: bool,
+    pub dedup_strict: bool,
+    pub stats: bool,
+    /// Print events grouped under a header per log stream
+    pub group_by_stream: bool,
+    /// In follow mode, print at most this many lines per second
+    pub max_rate: Option<usize>,
+    /// Messages longer than this many bytes are cut (0 for no limit)
+    pub max_line_length: usize,
+    /// Keep multi-line messages on one line instead of indenting the rest
+    pub no_wrap: bool,
+    pub tz: Tz,
+    pub time_format: TimeFormat,
+    pub raw: bool,
+    pub strip_ansi: bool,
+    pub template: Option<String>,
+    pub progress: ProgressMode,
+    pub quiet: bool,
+}
+
+pub async fn run(client: impl LogsBackend, options: TailOptions) -> Result<()> {
+    let TailOptions {
+        log_group,
+        follow,
+        filters,
+        allow_empty_filter,
+        grep,
+        invert_match,
+        streams,
+        stream_prefix,
+        regex_options,
+        from,
+        lines,
+        resume,
+        poll_interval,
+        max_poll_interval,
+        live,
+        highlight,
+        highlight_style,
+        fields,
+        output_file,
+        dedup,
+        dedup_strict,
+        stats,
+        group_by_stream,
+        max_rate,
+        max_line_length,
+        no_wrap,
+        tz,
+        time_format,
+        raw,
+        strip_ansi,
+        template,
+        progress,
+        quiet,
+    } = options;
+
+    let quiet = quiet || raw;
+    let template = template.as_deref().map(format::Template::parse).transpose()?;
+    if !allow_empty_filter {
+        filter_utils::check_not_empty("--filter", &filters, "events")?;
+        filter_utils::check_not_empty("--grep", &grep, "events")?;
+    }
+    let filter = FilterSpec::parse(&filters)?;
+    let stream_filter = StreamFilter { names: &streams, prefix: stream_prefix.as_deref() };
+    if streams.len() > MAX_STREAM_NAMES {
+        bail!(CwlError::InvalidArgument(format!(
+            "--stream can name at most {} streams, not {}", MAX_STREAM_NAMES, streams.len()
+        )));
+    }
+    let grep_matcher = grep.as_deref()
+        .map(|pattern| Grep::new(pattern, &regex_options, invert_match))
+        .transpose()?;
+    let keep = |event: &FilteredLogEvent| {
+        let message = event.message.as_deref().unwrap_or_default();
+        filter.matches(message)
+            && grep_matcher.as_ref().is_none_or(|grep| grep.matches(message))
+            && resume.as_ref().is_none_or(|resume| resume.is_new(event))
+    };
+
+    let (from_time, _) = match (&resume, from) {
+        (Some(resume), _) => (Some(resume.timestamp), None),
+        (None, Some(from)) => time::parse_time_range(Some(from), None, None, None)?,
+        (None, None) => (None, None),
+    };
+
+    let base = match poll_interval {
+        Some(ref interval) => parse_poll_interval(interval, "--poll-interval")?,
+        None => PollInterval::default().base,
+    };
+    let max = match max_poll_interval {
+        Some(ref interval) => parse_poll_interval(interval, "--max-poll-interval")?,
+        None => base,
+    };
+    let poll = PollInterval { base, max };
+
+    // One unfiltered stream can be followed by its forward token instead of
+    // searching the group on every poll
+    let single_stream = match streams.as_slice() {
+        [stream] if follow && !live && filters.is_empty() => Some(stream.as_str()),
+        _ => None,
+    };
+
+    let operation = match (live, single_stream) {
+        (true, _) => "StartLiveTail",
+        (false, Some(_)) => "GetLogEvents",
+        (false, None) => "FilterLogEvents",
+    };
+    if let Some(request) = client.dry_run(operation) {
+        let start_time = match follow {
+            true => from_time,
+            false => Some(from_time.unwrap_or_else(|| chrono::Utc::now().timestamp_millis() - DEFAULT_LOOKBACK_MILLIS)),
+        };
+        request
+            .log_group(&log_group)
+            .time("startTime", start_time, tz)
+            .param("filterPattern", filter.server.clone())
+            .param("localFilters", filter.is_local().then(|| filters.clone()))
+            .param("grep", grep.clone())
+            .param("invertMatch", invert_match.then_some(true))
+            .param("logStreamName", single_stream)
+            .param("startFromHead", single_stream.map(|_| true))
+            .param("logStreamNames", (!streams.is_empty() && single_stream.is_none()).then(|| streams.clone()))
+            .param("logStreamNamePrefix", stream_prefix.clone())
+            .param("follow", follow)
+            .param("limit", (!follow).then_some(lines))
+            .param("maxRate", max_rate.filter(|_| follow))
+            .param("pollInterval", (follow && !live).then(|| format!("{:?} up to {:?}", poll.base, poll.max.max(poll.base))))
+            .print();
+        return Ok(());
+    }
+
+    let mut out = TeeWriter::new(output_file.as_deref(), follow)?;
+    let mut deduper = dedup.then(|| Deduper::new(dedup_strict, follow));
+
+    if !quiet {
         println!("{} {}",
-            "Filter pattern:".bright_blue().bold(),
-            pattern.bright_yellow()
+            "Tailing logs from:".bright_blue().bold(),
+            log_group.bright_yellow()
         );
+
+        if !filters.is_empty() {
+            println!("{} {}{}",
+                "Filter pattern:".bright_blue().bold(),
+                filters.join(" OR ").bright_yellow(),
+                if filter.is_local() { " (matched locally)" } else { "" }
+            );
+        }
+
+        if let Some(ref grep) = grep {
+            println!("{} {}{}",
+                "Grep pattern:".bright_blue().bold(),
+                grep.bright_yellow(),
+                if invert_match { " (inverted)" } else { "" }
+            );
+        }
     }
 
-    let regex_pattern = filter.as_ref()
-        .map(|f| Regex::new(&regex::escape(f)))
-        .transpose()?;
+    // Also counts matches for --stats, so it's built even without highlighting
+    let regex_pattern = filter.highlight_pattern(grep_matcher.as_ref(), &regex_options)?;
+    let style = EventStyle {
+        log_group: &log_group,
+        highlight_pattern: regex_pattern.as_ref().filter(|_| highlight),
+        highlight_style,
+        fields: &fields,
+        tz,
+        time_format: &time_format,
+        max_line_length,
+        no_wrap,
+        raw,
+        strip_ansi,
+        template: template.as_ref(),
+    };
 
     if follow {
-        let spinner = ProgressBar::new_spinner();
-        spinner.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} {msg}")
-                .unwrap()
-        );
-        spinner.set_message("Waiting for logs...");
+        // With --stats the spinner stays up as a footer below the log lines
+        let spinner = if stats {
+            let spinner = progress.bar(quiet, "Counting events...", || ProgressBar::new_spinner().with_style(
+                ProgressStyle::default_spinner()
+                    .template("{spinner:.green} {pos} events seen ({per_sec}){msg}")
+                    .unwrap()
+            ));
+            // The message trails the counts, so it only holds the match count
+            spinner.set_message(if regex_pattern.is_some() { ", 0 matches" } else { "" });
+            spinner.enable_steady_tick(std::time::Duration::from_secs(1));
+            spinner
+        } else {
+            progress.bar(quiet, "Waiting for logs...", || ProgressBar::new_spinner().with_style(
+                ProgressStyle::default_spinner()
+                    .template("{spinner:.green} {msg}")
+                    .unwrap()
+            ))
+        };
 
-        let running = Arc::new(AtomicBool::new(true));
-        let r = running.clone();
+        // Shared with the timer that flushes quiet streams under --group-by-stream
+        let printer = RefCell::new(Printer::new(&mut out, &mut deduper, group_by_stream));
+        let show = |print: &mut dyn FnMut()| {
+            if stats {
+                spinner.suspend(print);
+            } else {
+                spinner.finish_and_clear();
+                print();
+            }
+        };
 
-        ctrlc::set_handler(move || {
-            r.store(false, Ordering::SeqCst);
-        })?;
+        let rate_limit = max_rate.map(|rate| RefCell::new(RateLimit::new(rate)));
 
-        client.tail_log_events(&log_group, filter.as_deref(), |event| {
-            if !running.load(Ordering::SeqCst) {
+        let mut matches = 0usize;
+        let on_event = |event: FilteredLogEvent| {
+            if !keep(&event) {
                 return Ok(());
             }
 
-            spinner.finish_and_clear();
-
-            if let Some(message) = event.message {
-                let timestamp = event.timestamp.map(|ts| {
-                    DateTime::<Utc>::from_timestamp_millis(ts)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
-                        .unwrap_or_else(|| "Unknown time".to_string())
-                }).unwrap_or_else(|| "Unknown time".to_string());
-
-                let formatted_message = if highlight && regex_pattern.is_some() {
-                    format::highlight_matches(&message, regex_pattern.as_ref().unwrap())
-                } else {
-                    message
-                };
-
-                println!("[{}] {}",
-                    timestamp.bright_blue(),
-                    formatted_message
-                );
+            if stats {
+                spinner.inc(1);
+                if let (Some(pattern), Some(message)) = (&regex_pattern, &event.message) {
+                    if pattern.is_match(message) {
+                        matches += 1;
+                        spinner.set_message(format!(", {} matches", matches));
+                    }
+                }
             }
 
+            if rate_limit.as_ref().is_some_and(|limit| !limit.borrow_mut().take()) {
+                return Ok(());
+            }
+
+            let mut event = Some(event);
+            show(&mut || {
+                if let Some(event) = event.take() {
+                    printer.borrow_mut().push(event, &style);
+                }
+            });
             Ok(())
-        }).await?;
+        };
+
+        let tail = async {
+            match single_stream {
+                _ if live => client.start_live_tail(&log_group, filter.server.as_deref(), stream_filter, on_event).await,
+                Some(stream) => client.tail_single_stream(&log_group, stream, from_time, poll, on_event).await,
+                None => client.tail_log_events(&log_group, filter.server.as_deref(), stream_filter, from_time, poll, on_event).await,
+            }
+        };
+
+        let flush_quiet_streams = async {
+            let mut ticker = tokio::time::interval(Duration::from_millis(500));
+            loop {
+                ticker.tick().await;
+                let mut ready = printer.borrow_mut().take_idle(Some(GROUP_DEBOUNCE));
+                if !ready.is_empty() {
+                    show(&mut || printer.borrow_mut().print_groups(std::mem::take(&mut ready), &style));
+                }
+            }
+        };
+
+        // Refills the --max-rate bucket each second and reports what was dropped
+        let refill_rate_limit = async {
+            let mut ticker = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                ticker.tick().await;
+                let suppressed = rate_limit.as_ref().map_or(0, |limit| limit.borrow_mut().refill());
+                if suppressed > 0 {
+                    show(&mut || printer.borrow_mut().write_notice(&suppressed_notice(suppressed)));
+                }
+            }
+        };
+
+        tokio::select! {
+            result = tail => result?,
+            _ = flush_quiet_streams, if group_by_stream => {},
+            _ = refill_rate_limit, if rate_limit.is_some() => {},
+            _ = tokio::signal::ctrl_c() => {},
+        }
+
+        spinner.finish_and_clear();
+        let mut printer = printer.into_inner();
+        printer.flush(&style);
+        let suppressed = rate_limit.map_or(0, |limit| limit.into_inner().refill());
+        if suppressed > 0 {
+            printer.write_notice(&suppressed_notice(suppressed));
+        }
     } else {
-        let events = client.get_log_events(
+        let mut events = client.get_log_events(
             &log_group,
-            Some(chrono::Utc::now().timestamp_millis() - 300000),
+            Some(from_time.unwrap_or_else(|| chrono::Utc::now().timestamp_millis() - DEFAULT_LOOKBACK_MILLIS)),
             None,
-            filter.as_deref(),
-            Some(100),
+            filter.server.as_deref(),
+            stream_filter,
+            (!filter.is_local() && grep_matcher.is_none()).then_some(lines),
+            true,
         ).await?;
+        events.retain(|event| keep(event));
+        events.truncate(lines);
 
         if events.is_empty() {
-            println!("{}", "No log events found".yellow());
+            if !quiet {
+                println!("{}", "No log events found".yellow());
+            }
         } else {
+            let mut printer = Printer::new(&mut out, &mut deduper, group_by_stream);
             for event in events {
-                if let Some(message) = event.message {
-                    let timestamp = event.timestamp.map(|ts| {
-                        DateTime::<Utc>::from_timestamp_millis(ts)
-                            .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
-                            .unwrap_or_else(|| "Unknown time".to_string())
-                    }).unwrap_or_else(|| "Unknown time".to_string());
-
-                    let formatted_message = if highlight && regex_pattern.is_some() {
-                        format::highlight_matches(&message, regex_pattern.as_ref().unwrap())
-                    } else {
-                        message
-                    };
-
-                    println!("[{}] {}",
-                        timestamp.bright_blue(),
-                        formatted_message
-                    );
-                }
+                printer.push(event, &style);
             }
+            printer.flush(&style);
         }
     }
 
+    if let Some(ref mut deduper) = deduper {
+        deduper.finish(&mut out);
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
+
+fn parse_poll_interval(interval: &str, flag: &str) -> Result<std::time::Duration> {
+    let duration = time::parse_duration(interval)
+        .with_context(|| format!("Invalid {}", flag))?;
+    if duration <= chrono::Duration::zero() {
+        bail!("{} must be greater than zero", flag);
+    }
+    Ok(duration.to_std()?)
+}
+
+/// Sends events to `print_event`, or with `--group-by-stream` buffers them per
+/// log stream and prints each stream's events together under a header.
+struct Printer<'a> {
+    out: &'a mut TeeWriter,
+    deduper: &'a mut Option<Deduper>,
+    groups: Option<StreamGroups>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(out: &'a mut TeeWriter, deduper: &'a mut Option<Deduper>, group_by_stream: bool) -> Self {
+        Self {
+            out,
+            deduper,
+            groups: group_by_stream.then(StreamGroups::default),
+        }
+    }
+
+    fn push(&mut self, event: FilteredLogEvent, style: &EventStyle) {
+        match self.groups {
+            Some(ref mut groups) => {
+                groups.push(event);
+                let ready = groups.take_idle(Some(GROUP_DEBOUNCE));
+                self.print_groups(ready, style);
+            }
+            None => print_event(&event, style, self.deduper, self.out),
+        }
+    }
+
+    /// Takes the buffers of streams quiet for `window` (or held for
+    /// `GROUP_MAX_HOLD`), or all of them.
+    fn take_idle(&mut self, window: Option<Duration>) -> Vec<(String, Vec<FilteredLogEvent>)> {
+        self.groups.as_mut().map(|groups| groups.take_idle(window)).unwrap_or_default()
+    }
+
+    fn print_groups(&mut self, ready: Vec<(String, Vec<FilteredLogEvent>)>, style: &EventStyle) {
+        for (stream, events) in ready {
+            let Some(ref mut groups) = self.groups else {
+                return;
+            };
+            if groups.current.as_deref() != Some(stream.as_str()) {
+                // A run of duplicates shouldn't carry over into another stream
+                if let Some(deduper) = self.deduper.as_mut() {
+                    deduper.finish(self.out);
+                }
+                if groups.current.is_some() {
+                    self.out.write_line("");
+                }
+                self.out.write_line(&format!("{} {} {}", "==>".bright_black(), stream.color(format::stream_color(&stream)).bold(), "<==".bright_black()));
+                groups.current = Some(stream);
+            }
+
+            for event in &events {
+                print_event(event, style, self.deduper, self.out);
+            }
+        }
+    }
+
+    fn write_notice(&mut self, notice: &str) {
+        self.out.write_line(notice);
+    }
+
+    /// Prints everything still buffered.
+    fn flush(&mut self, style: &EventStyle) {
+        let ready = self.take_idle(None);
+        self.print_groups(ready, style);
+    }
+}
+
+/// Token bucket for `--max-rate`: `rate` lines may be printed between
+/// refills, which happen once a second. Lines over the budget are dropped and
+/// counted.
+struct RateLimit {
+    rate: usize,
+    tokens: usize,
+    suppressed: usize,
+}
+
+impl RateLimit {
+    fn new(rate: usize) -> Self {
+        Self { rate, tokens: rate, suppressed: 0 }
+    }
+
+    /// Whether another line may be printed now.
+    fn take(&mut self) -> bool {
+        if self.tokens == 0 {
+            self.suppressed += 1;
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+
+    /// Refills the bucket, returning how many lines were dropped since the
+    /// last refill.
+    fn refill(&mut self) -> usize {
+        self.tokens = self.rate;
+        std::mem::take(&mut self.suppressed)
+    }
+}
+
+fn suppressed_notice(count: usize) -> String {
+    format!("… suppressed {} lines", count).yellow().to_string()
+}
+
+/// Events buffered per log stream, in the order the streams first showed up.
+#[derive(Default)]
+struct StreamGroups {
+    buffers: Vec<StreamBuffer>,
+    /// The stream whose header was printed last
+    current: Option<String>,
+}
+
+struct StreamBuffer {
+    stream: String,
+    events: Vec<FilteredLogEvent>,
+    /// When the oldest buffered event arrived
+    since: Instant,
+    last_seen: Instant,
+}
+
+impl StreamGroups {
+    fn push(&mut self, event: FilteredLogEvent) {
+        let stream = event.log_stream_name.clone().unwrap_or_default();
+        match self.buffers.iter_mut().find(|buffer| buffer.stream == stream) {
+            Some(buffer) => {
+                buffer.events.push(event);
+                buffer.last_seen = Instant::now();
+            }
+            None => self.buffers.push(StreamBuffer {
+                stream,
+                events: vec![event],
+                since: Instant::now(),
+                last_seen: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes the buffers of streams quiet for `window`, or held for
+    /// `GROUP_MAX_HOLD` however busy they are; all of them without a window.
+    fn take_idle(&mut self, window: Option<Duration>) -> Vec<(String, Vec<FilteredLogEvent>)> {
+        let (ready, held): (Vec<StreamBuffer>, _) = std::mem::take(&mut self.buffers)
+            .into_iter()
+            .partition(|buffer| window.is_none_or(|window| {
+                buffer.last_seen.elapsed() >= window || buffer.since.elapsed() >= GROUP_MAX_HOLD
+            }));
+        self.buffers = held;
+        ready.into_iter().map(|buffer| (buffer.stream, buffer.events)).collect()
+    }
+}
+
+/// How `print_event` renders each event.
+struct EventStyle<'a> {
+    log_group: &'a str,
+    highlight_pattern: Option<&'a Regex>,
+    highlight_style: format::HighlightStyle,
+    fields: &'a [String],
+    tz: Tz,
+    time_format: &'a TimeFormat,
+    max_line_length: usize,
+    no_wrap: bool,
+    raw: bool,
+    strip_ansi: bool,
+    template: Option<&'a format::Template>,
+}
+
+fn print_event(
+    event: &FilteredLogEvent,
+    style: &EventStyle,
+    deduper: &mut Option<Deduper>,
+    out: &mut TeeWriter,
+) {
+    let Some(ref message) = event.message else {
+        return;
+    };
+    let message = if style.strip_ansi {
+        format::strip_ansi(message)
+    } else {
+        message.clone()
+    };
+
+    if style.raw {
+        out.write_line(message.trim_end_matches(['\n', '\r']));
+        return;
+    }
+
+    let message = &format::sanitize_for_terminal(&message);
+
+    let timestamp = event.timestamp
+        .and_then(|ts| style.time_format.format(ts, style.tz))
+        .unwrap_or_else(|| "Unknown time".to_string());
+
+    let line = match style.template {
+        Some(template) => {
+            let mut line = template.render(&format::TemplateContext {
+                timestamp: &timestamp,
+                stream: event.log_stream_name.as_deref().unwrap_or_default(),
+                group: style.log_group,
+                message,
+            });
+            // Cut after rendering, so {json:...} still sees valid JSON
+            format::truncate_line(&mut line, style.max_line_length);
+            format::fold_lines(&line, 0, !style.no_wrap)
+        }
+        None => {
+            let mut body = if style.fields.is_empty() {
+                message.clone()
+            } else {
+                format::project_fields(message, style.fields).unwrap_or_else(|| message.clone())
+            };
+            format::truncate_line(&mut body, style.max_line_length);
+
+            let formatted_message = match style.highlight_pattern {
+                Some(pattern) => format::highlight_matches(&body, pattern, style.highlight_style),
+                None => body,
+            };
+
+            // Continuation lines start under the message, past `[timestamp] `
+            let indent = timestamp.chars().count() + 3;
+            format!("[{}] {}",
+                timestamp.bright_blue(),
+                format::fold_lines(&formatted_message, indent, !style.no_wrap)
+            )
+        }
+    };
+
+    match deduper {
+        Some(deduper) => deduper.push(message, line, out),
+        None => out.write_line(&line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::backend::MockBackend;
+
+    fn event(stream: &str, message: &str) -> FilteredLogEvent {
+        FilteredLogEvent::builder().log_stream_name(stream).message(message).build()
+    }
+
+    #[test]
+    fn test_stream_groups_keep_first_seen_order() {
+        let mut groups = StreamGroups::default();
+        for (stream, message) in [("b", "1"), ("a", "2"), ("b", "3")] {
+            groups.push(event(stream, message));
+        }
+
+        assert!(groups.take_idle(Some(Duration::from_secs(60))).is_empty());
+
+        let ready = groups.take_idle(None);
+        let summary: Vec<(&str, Vec<&str>)> = ready.iter()
+            .map(|(stream, events)| (stream.as_str(), events.iter().filter_map(|e| e.message.as_deref()).collect()))
+            .collect();
+        assert_eq!(summary, vec![("b", vec!["1", "3"]), ("a", vec!["2"])]);
+        assert!(groups.take_idle(None).is_empty());
+    }
+
+    #[test]
+    fn test_stream_groups_release_busy_streams_after_max_hold() {
+        let mut groups = StreamGroups::default();
+        groups.push(event("busy", "1"));
+        groups.buffers[0].since = Instant::now() - GROUP_MAX_HOLD;
+
+        // Still receiving events, so never quiet for the debounce window
+        groups.push(event("busy", "2"));
+        groups.push(event("quiet", "3"));
+
+        let ready = groups.take_idle(Some(GROUP_DEBOUNCE));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].0, "busy");
+        assert_eq!(ready[0].1.len(), 2);
+        assert_eq!(groups.buffers.len(), 1);
+    }
+
+    #[test]
+    fn test_rate_limit_drops_and_counts_excess() {
+        let mut limit = RateLimit::new(2);
+        assert_eq!((0..5).filter(|_| limit.take()).count(), 2);
+        assert_eq!(limit.refill(), 3);
+        assert!(limit.take());
+        assert_eq!(limit.refill(), 0);
+    }
+
+    #[test]
+    fn test_resume_skips_events_already_shown() {
+        let event = |id: &str, timestamp| FilteredLogEvent::builder().event_id(id).timestamp(timestamp).build();
+        let shown = [event("a", 100), event("c", 300), event("b", 300)];
+
+        let resume = Resume::after(&shown, 999);
+        assert_eq!(resume.timestamp, 300);
+        assert!(!resume.is_new(&event("b", 300)));
+        assert!(resume.is_new(&event("d", 300)));
+
+        assert_eq!(Resume::after(&[], 999).timestamp, 999);
+    }
+
+    #[tokio::test]
+    async fn test_live_tail_passes_filters_to_session_and_greps_locally() {
+        let backend = MockBackend::default()
+            .with_event("app", "web-1", 1_000, "ERROR db down")
+            .with_event("app", "web-1", 2_000, "ERROR disk full")
+            .with_event("app", "web-2", 3_000, "INFO db ok")
+            .with_event("app", "worker", 4_000, "ERROR db timeout");
+        let live_tails = backend.live_tails.clone();
+        let output_file = tempfile::NamedTempFile::new().unwrap();
+
+        run(backend, TailOptions {
+            log_group: "app".to_string(),
+            follow: true,
+            live: true,
+            filters: vec!["ERROR".to_string()],
+            grep: Some("db".to_string()),
+            streams: vec!["web-1".to_string(), "web-2".to_string()],
+            raw: true,
+            output_file: Some(output_file.path().to_path_buf()),
+            ..Default::default()
+        }).await.unwrap();
+
+        let written = std::fs::read_to_string(output_file.path()).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), ["ERROR db down"]);
+        assert_eq!(*live_tails.lock().unwrap(), [(
+            Some("ERROR".to_string()),
+            vec!["web-1".to_string(), "web-2".to_string()],
+        )]);
+    }
+
+    #[tokio::test]
+    async fn test_tail_rejects_too_many_streams() {
+        for live in [false, true] {
+            let backend = MockBackend::default().with_event("app", "web-1", 1_000, "x");
+            let live_tails = backend.live_tails.clone();
+
+            let err = run(backend, TailOptions {
+                log_group: "app".to_string(),
+                follow: live,
+                live,
+                streams: (0..=MAX_STREAM_NAMES).map(|i| format!("web-{}", i)).collect(),
+                ..Default::default()
+            }).await.unwrap_err();
+
+            assert!(matches!(err.downcast_ref(), Some(CwlError::InvalidArgument(_))));
+            assert!(live_tails.lock().unwrap().is_empty());
+        }
+    }
+}
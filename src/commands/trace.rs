@@ -0,0 +1,258 @@
+use anyhow::Result;
+use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
+use chrono_tz::Tz;
+use colored::Colorize;
+use crate::aws::client::{CloudWatchClient, StreamFilter};
+use crate::utils::format;
+use crate::utils::progress::ProgressMode;
+use crate::utils::time::{self, TimeFormat};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::{HashMap, HashSet};
+
+/// The field traced when `--key` isn't given.
+pub const DEFAULT_KEY: &str = "request_id";
+
+pub struct TraceOptions {
+    pub log_group: String,
+    /// JSON field holding the request id: a dot path or JSON Pointer
+    pub key: String,
+    /// Print this request's events; without it, list the busiest ids
+    pub id: Option<String>,
+    pub since: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub default_since: Option<String>,
+    pub filter: Option<String>,
+    /// How many ids to list without `id`
+    pub limit: usize,
+    pub tz: Tz,
+    pub time_format: TimeFormat,
+    pub progress: ProgressMode,
+    pub quiet: bool,
+}
+
+/// The events sharing one value of the traced key.
+#[derive(Debug, PartialEq)]
+struct IdSummary {
+    id: String,
+    count: usize,
+    first: i64,
+    last: i64,
+}
+
+/// Puts the events of one request, scattered across streams, on a single
+/// timeline, or lists the request ids with the most events.
+pub async fn run(client: CloudWatchClient, options: TraceOptions) -> Result<()> {
+    let TraceOptions {
+        log_group,
+        key,
+        id,
+        since,
+        start,
+        end,
+        default_since,
+        filter,
+        limit,
+        tz,
+        time_format,
+        progress,
+        quiet,
+    } = options;
+
+    let (start_time, end_time) = time::parse_time_range(since, start, end, default_since.as_deref())?;
+    // Events holding the id also contain it as text, so the server can skip
+    // everything else before the key is checked locally
+    let filter = filter.or_else(|| id.as_deref().and_then(id_filter));
+
+    if let Some(request) = client.dry_run("FilterLogEvents") {
+        request
+            .log_group(&log_group)
+            .time("startTime", start_time, tz)
+            .time("endTime", end_time, tz)
+            .param("filterPattern", filter)
+            .param("key", key)
+            .param("id", id)
+            .print();
+        return Ok(());
+    }
+
+    if !quiet {
+        match id {
+            Some(ref id) => println!("{} {} {}",
+                "Tracing:".bright_blue().bold(),
+                format!("{}={}", key, id).bright_yellow(),
+                format!("in {}", log_group).bright_black()
+            ),
+            None => println!("{} {} {}",
+                "Request ids:".bright_blue().bold(),
+                key.bright_yellow(),
+                format!("in {}", log_group).bright_black()
+            ),
+        }
+    }
+
+    let spinner = progress.bar(quiet, "Fetching log events...", || ProgressBar::new_spinner().with_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap()
+    ));
+    let events = client.get_log_events(&log_group, start_time, end_time, filter.as_deref(), StreamFilter::ALL, None, true).await?;
+    spinner.finish_and_clear();
+
+    match id {
+        Some(id) => print_trace(&trace_events(&events, &key, &id), &key, &id, tz, &time_format, quiet),
+        None => print_top_ids(top_ids(&events, &key), &key, limit, tz, quiet),
+    }
+
+    Ok(())
+}
+
+/// A filter pattern matching the id as a phrase. Ids with quotes or
+/// backslashes are matched locally only.
+fn id_filter(id: &str) -> Option<String> {
+    (!id.is_empty() && !id.contains(['"', '\\'])).then(|| format!("\"{}\"", id))
+}
+
+fn key_value(event: &FilteredLogEvent, key: &str) -> Option<String> {
+    event.message.as_deref().and_then(|message| format::format_json_field(message, key))
+}
+
+fn trace_events<'a>(events: &'a [FilteredLogEvent], key: &str, id: &str) -> Vec<&'a FilteredLogEvent> {
+    events.iter()
+        .filter(|event| key_value(event, key).as_deref() == Some(id))
+        .collect()
+}
+
+/// Counts the events of each id, most events first.
+fn top_ids(events: &[FilteredLogEvent], key: &str) -> Vec<IdSummary> {
+    let mut summaries: HashMap<String, IdSummary> = HashMap::new();
+    for event in events {
+        let Some(id) = key_value(event, key) else {
+            continue;
+        };
+        let timestamp = event.timestamp.unwrap_or(0);
+        let summary = summaries.entry(id.clone())
+            .or_insert(IdSummary { id, count: 0, first: timestamp, last: timestamp });
+        summary.count += 1;
+        summary.first = summary.first.min(timestamp);
+        summary.last = summary.last.max(timestamp);
+    }
+
+    let mut summaries: Vec<IdSummary> = summaries.into_values().collect();
+    summaries.sort_by(|a, b| {
+        b.count.cmp(&a.count)
+            .then_with(|| a.first.cmp(&b.first))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    summaries
+}
+
+fn print_trace(events: &[&FilteredLogEvent], key: &str, id: &str, tz: Tz, time_format: &TimeFormat, quiet: bool) {
+    if events.is_empty() {
+        if !quiet {
+            println!("{}", format!("No events with {}={}", key, id).yellow());
+        }
+        return;
+    }
+
+    let first = events[0].timestamp.unwrap_or(0);
+    let last = events.last().and_then(|event| event.timestamp).unwrap_or(first);
+    let offset_width = format!("+{}", format::format_delay(last - first)).len();
+
+    for event in events {
+        let timestamp = event.timestamp.unwrap_or(0);
+        let stream = event.log_stream_name.as_deref().unwrap_or("");
+        let offset = format!("{:>1$}", format!("+{}", format::format_delay(timestamp - first)), offset_width);
+        let prefix = format!("[{}] {} [{}] ",
+            time_format.format(timestamp, tz).unwrap_or_default().bright_blue(),
+            offset.bright_black(),
+            stream.color(format::stream_color(stream))
+        );
+        let message = format::sanitize_for_terminal(event.message.as_deref().unwrap_or("").trim_end());
+        println!("{}{}", prefix, format::fold_lines(&message, format::visible_width(&prefix), true));
+    }
+
+    if !quiet {
+        let streams: HashSet<&str> = events.iter().filter_map(|event| event.log_stream_name.as_deref()).collect();
+        println!("\n{} {} events over {} across {} streams",
+            "✓".bright_green().bold(),
+            format::format_count(events.len()).bright_yellow(),
+            format::format_delay(last - first),
+            streams.len()
+        );
+    }
+}
+
+fn print_top_ids(summaries: Vec<IdSummary>, key: &str, limit: usize, tz: Tz, quiet: bool) {
+    if quiet {
+        for summary in summaries.iter().take(limit) {
+            println!("{}\t{}", summary.id, summary.count);
+        }
+        return;
+    }
+
+    if summaries.is_empty() {
+        println!("{}", format!("No events with a {} field", key).yellow());
+        return;
+    }
+
+    let shown = &summaries[..limit.min(summaries.len())];
+    let count_width = shown.iter().map(|s| format::format_count(s.count).len()).max().unwrap_or(0).max(6);
+    let span_width = shown.iter().map(|s| format::format_delay(s.last - s.first).len()).max().unwrap_or(0).max(4);
+    let first_seen: Vec<String> = shown.iter()
+        .map(|s| time::format_timestamp(s.first, tz, TimeFormat::DEFAULT).unwrap_or_default())
+        .collect();
+    let seen_width = first_seen.iter().map(String::len).max().unwrap_or(0).max(10);
+    // Padded before coloring, since escape codes would count towards the width
+    println!("{}  {}  {}  {}",
+        format!("{:>count_width$}", "EVENTS").bright_cyan().bold(),
+        format!("{:>span_width$}", "SPAN").bright_cyan().bold(),
+        format!("{:<seen_width$}", "FIRST SEEN").bright_cyan().bold(),
+        key.to_uppercase().bright_cyan().bold()
+    );
+    for (summary, first_seen) in shown.iter().zip(first_seen) {
+        println!("{}  {:>span_width$}  {}  {}",
+            format!("{:>count_width$}", format::format_count(summary.count)).bright_yellow(),
+            format::format_delay(summary.last - summary.first),
+            format!("{:<seen_width$}", first_seen).bright_black(),
+            summary.id
+        );
+    }
+
+    println!("\n{} Showing {} of {} ids; pass --id to see one as a timeline",
+        "Note:".bright_magenta().bold(),
+        shown.len(),
+        format::format_count(summaries.len())
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: i64, message: &str) -> FilteredLogEvent {
+        FilteredLogEvent::builder().timestamp(timestamp).message(message).build()
+    }
+
+    #[test]
+    fn test_trace_groups_events_by_key() {
+        let events = [
+            event(1_000, r#"{"request_id":"a","msg":"start"}"#),
+            event(1_500, r#"{"request_id":"b","msg":"start"}"#),
+            event(2_000, "plain text mentioning a"),
+            event(3_000, r#"{"request_id":"a","msg":"done"}"#),
+            event(4_000, r#"{"ctx":{"request_id":"a"}}"#),
+        ];
+
+        let trace: Vec<i64> = trace_events(&events, DEFAULT_KEY, "a").iter().filter_map(|e| e.timestamp).collect();
+        assert_eq!(trace, [1_000, 3_000]);
+        assert_eq!(trace_events(&events, "ctx.request_id", "a").len(), 1);
+
+        assert_eq!(top_ids(&events, DEFAULT_KEY), [
+            IdSummary { id: "a".to_string(), count: 2, first: 1_000, last: 3_000 },
+            IdSummary { id: "b".to_string(), count: 1, first: 1_500, last: 1_500 },
+        ]);
+        assert_eq!(id_filter("a-1").as_deref(), Some("\"a-1\""));
+        assert_eq!(id_filter("say \"hi\""), None);
+    }
+}
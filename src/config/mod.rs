@@ -1,5 +1,8 @@
-use anyhow::Result;
+use anyhow::{Result, Context};
+use clap::ValueEnum;
+use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use crate::utils::output::OutputFormat;
 use std::collections::HashMap;
 use std::path::PathBuf;
 
@@ -10,16 +13,50 @@ pub struct Config {
     pub profiles: HashMap<String, ProfileConfig>,
     #[serde(default)]
     pub aliases: HashMap<String, String>,
+    #[serde(default)]
+    pub queries: HashMap<String, SavedQuery>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DefaultConfig {
-    #[serde(default = "default_region")]
-    pub region: String,
+    /// Region when neither `--region` nor `$AWS_REGION`/`$AWS_DEFAULT_REGION`
+    /// is set
+    #[serde(default)]
+    pub region: Option<String>,
+    /// AWS profile when neither `--profile` nor `$AWS_PROFILE` is set
+    #[serde(default)]
+    pub profile: Option<String>,
     #[serde(default = "default_output")]
     pub output: String,
-    #[serde(default = "default_max_events")]
-    pub max_events: usize,
+    /// Event limit for `query` without `--limit` and `tail` without `-n`
+    #[serde(default)]
+    pub max_events: Option<usize>,
+    #[serde(default)]
+    pub default_since: Option<String>,
+    #[serde(default = "default_warn_days")]
+    pub warn_days: u64,
+    /// Dollars per GB scanned, for `query --show-cost`
+    #[serde(default = "default_cost_per_gb")]
+    pub cost_per_gb: f64,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// strftime format or preset for event timestamps (`--time-format`)
+    #[serde(default)]
+    pub time_format: Option<String>,
+    /// How filter and grep matches are marked (`--highlight-color`)
+    #[serde(default)]
+    pub highlight_style: Option<String>,
+    #[serde(default)]
+    pub default_template: Option<String>,
+    #[serde(default)]
+    pub use_pager: bool,
+    /// Log group for `tail` and `query` when none is given and none of
+    /// `log_group_env` is set
+    #[serde(default)]
+    pub default_log_group: Option<String>,
+    /// Environment variables checked, in order, for the log group to use
+    #[serde(default = "default_log_group_env")]
+    pub log_group_env: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,45 +65,176 @@ pub struct ProfileConfig {
     pub region: Option<String>,
 }
 
-fn default_region() -> String {
-    "us-east-1".to_string()
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub log_group: String,
+    pub filter: Option<String>,
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub formatted: bool,
+    pub output: Option<OutputFormat>,
 }
 
+/// Keys understood in each table, used to warn about typos that serde would
+/// otherwise silently ignore. Keep in sync with the structs above.
+const CONFIG_KEYS: &[&str] = &["defaults", "profiles", "aliases", "queries"];
+const DEFAULTS_KEYS: &[&str] = &[
+    "region", "profile", "output", "max_events", "default_since", "warn_days", "timezone", "time_format", "default_template", "use_pager",
+    "default_log_group", "log_group_env", "highlight_style", "cost_per_gb",
+];
+const PROFILE_KEYS: &[&str] = &["assume_role", "region"];
+const QUERY_KEYS: &[&str] = &["log_group", "filter", "since", "limit", "formatted", "output"];
+
 fn default_output() -> String {
     "colored".to_string()
 }
 
-fn default_max_events() -> usize {
-    1000
+/// Days an unbounded query may span before `query` asks for confirmation.
+pub const DEFAULT_WARN_DAYS: u64 = 7;
+
+fn default_warn_days() -> u64 {
+    DEFAULT_WARN_DAYS
+}
+
+/// The Logs Insights price per GB scanned in us-east-1.
+fn default_cost_per_gb() -> f64 {
+    0.005
+}
+
+fn default_log_group_env() -> Vec<String> {
+    vec!["CWL_LOG_GROUP".to_string(), "AWS_LAMBDA_LOG_GROUP_NAME".to_string()]
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             defaults: DefaultConfig {
-                region: default_region(),
+                region: None,
+                profile: None,
                 output: default_output(),
-                max_events: default_max_events(),
+                max_events: None,
+                default_since: None,
+                warn_days: default_warn_days(),
+                cost_per_gb: default_cost_per_gb(),
+                timezone: None,
+                time_format: None,
+                highlight_style: None,
+                default_template: None,
+                use_pager: false,
+                default_log_group: None,
+                log_group_env: default_log_group_env(),
             },
             profiles: HashMap::new(),
             aliases: HashMap::new(),
+            queries: HashMap::new(),
         }
     }
 }
 
+impl DefaultConfig {
+    /// The event limit when `flag` (`--limit`, `tail -n`) isn't given:
+    /// `max_events` if configured, otherwise `fallback`.
+    pub fn event_limit(&self, flag: Option<usize>, fallback: usize) -> usize {
+        flag.or(self.max_events).unwrap_or(fallback)
+    }
+
+    /// The AWS profile to use: `flag` (`--profile`), then `$AWS_PROFILE`,
+    /// then `profile`.
+    pub fn resolve_profile(&self, flag: Option<&str>, env: impl Fn(&str) -> Option<String>) -> Option<String> {
+        flag.map(str::to_string)
+            .or_else(|| env("AWS_PROFILE").filter(|profile| !profile.trim().is_empty()))
+            .or_else(|| self.profile.clone())
+    }
+
+    /// The log group to use when `tail` or `query` isn't given one: the first
+    /// non-empty variable of `log_group_env`, then `default_log_group`.
+    pub fn fallback_log_group(&self, env: impl Fn(&str) -> Option<String>) -> Option<String> {
+        self.log_group_env.iter()
+            .filter_map(|name| env(name))
+            .find(|value| !value.trim().is_empty())
+            .or_else(|| self.default_log_group.clone())
+    }
+}
+
 impl Config {
+    /// The output mode: `flag` (`--output`), then the output saved with
+    /// `query` when running one, then `defaults.output`, then colored.
+    pub fn output(&self, flag: Option<OutputFormat>, query: Option<&str>) -> OutputFormat {
+        flag.or_else(|| query.and_then(|name| self.queries.get(name)).and_then(|saved| saved.output))
+            // defaults.output was validated when the config was loaded
+            .or_else(|| OutputFormat::from_str(&self.defaults.output, true).ok())
+            .unwrap_or_default()
+    }
+
     pub fn load() -> Result<Self> {
         let config_path = Self::config_path()?;
 
         if config_path.exists() {
             let contents = std::fs::read_to_string(&config_path)?;
-            let config: Config = toml::from_str(&contents)?;
+            let config: Config = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
+            config.validate()
+                .with_context(|| format!("Invalid config file: {}", config_path.display()))?;
+
+            if let Ok(value) = contents.parse::<toml::Value>() {
+                for warning in config_warnings(&value) {
+                    eprintln!("{} {}: {}", "Warning:".yellow().bold(), config_path.display(), warning);
+                }
+            }
+
             Ok(config)
         } else {
             Ok(Config::default())
         }
     }
 
+    fn validate(&self) -> Result<()> {
+        if self.defaults.max_events == Some(0) {
+            anyhow::bail!("defaults.max_events must be at least 1");
+        }
+
+        if !self.defaults.cost_per_gb.is_finite() || self.defaults.cost_per_gb < 0.0 {
+            anyhow::bail!("defaults.cost_per_gb must be a price of at least 0");
+        }
+
+        if let Some(ref since) = self.defaults.default_since {
+            crate::utils::time::parse_duration(since)
+                .context("defaults.default_since must be a duration like '6h' or '30m'")?;
+        }
+
+        if let Some(ref timezone) = self.defaults.timezone {
+            timezone.parse::<chrono_tz::Tz>()
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("defaults.timezone must be an IANA name like 'Europe/Berlin'")?;
+        }
+
+        if let Some(ref time_format) = self.defaults.time_format {
+            time_format.parse::<crate::utils::time::TimeFormat>()
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("defaults.time_format must be a strftime format or a preset like 'iso'")?;
+        }
+        if let Some(ref style) = self.defaults.highlight_style {
+            style.parse::<crate::utils::format::HighlightStyle>()
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("defaults.highlight_style must be color and style names like 'on_red+white'")?;
+        }
+
+        if let Some(ref template) = self.defaults.default_template {
+            crate::utils::format::Template::parse(template)
+                .context("defaults.default_template is not a valid template")?;
+        }
+
+        for (name, query) in &self.queries {
+            if let Some(ref since) = query.since {
+                crate::utils::time::parse_duration(since)
+                    .with_context(|| format!("queries.{}.since must be a duration like '6h' or '30m'", name))?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn save(&self) -> Result<()> {
         let config_path = Self::config_path()?;
 
@@ -89,4 +257,163 @@ impl Config {
         path.push("config.toml");
         Ok(path)
     }
-}
\ No newline at end of file
+}
+
+/// Lists likely mistakes in a parsed config file: unknown keys, an unknown
+/// output mode, or a region that doesn't look like an AWS region. These are
+/// only warnings; the config still loads.
+fn config_warnings(value: &toml::Value) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let unknown_keys = |table: Option<&toml::Value>, known: &[&str], path: &str, warnings: &mut Vec<String>| {
+        if let Some(table) = table.and_then(toml::Value::as_table) {
+            for key in table.keys().filter(|key| !known.contains(&key.as_str())) {
+                warnings.push(format!("unknown key '{}{}' (expected one of: {})", path, key, known.join(", ")));
+            }
+        }
+    };
+
+    unknown_keys(Some(value), CONFIG_KEYS, "", &mut warnings);
+    unknown_keys(value.get("defaults"), DEFAULTS_KEYS, "defaults.", &mut warnings);
+    for (section, known) in [("profiles", PROFILE_KEYS), ("queries", QUERY_KEYS)] {
+        if let Some(entries) = value.get(section).and_then(toml::Value::as_table) {
+            for (name, entry) in entries {
+                unknown_keys(Some(entry), known, &format!("{}.{}.", section, name), &mut warnings);
+            }
+        }
+    }
+
+    let defaults = value.get("defaults");
+    if let Some(output) = defaults.and_then(|d| d.get("output")).and_then(toml::Value::as_str) {
+        if OutputFormat::from_str(output, true).is_err() {
+            let modes: Vec<String> = OutputFormat::value_variants().iter()
+                .filter_map(|mode| mode.to_possible_value())
+                .map(|mode| mode.get_name().to_string())
+                .collect();
+            warnings.push(format!("defaults.output '{}' is not a known mode (expected one of: {})", output, modes.join(", ")));
+        }
+    }
+
+    let regions = defaults.and_then(|d| d.get("region")).into_iter()
+        .map(|region| ("defaults.region".to_string(), region))
+        .chain(value.get("profiles").and_then(toml::Value::as_table).into_iter()
+            .flatten()
+            .filter_map(|(name, profile)| profile.get("region").map(|region| (format!("profiles.{}.region", name), region))));
+    for (key, region) in regions {
+        if let Some(region) = region.as_str().filter(|region| !looks_like_region(region)) {
+            warnings.push(format!("{} '{}' doesn't look like an AWS region (e.g. us-east-1)", key, region));
+        }
+    }
+
+    warnings
+}
+
+/// Region names look like `us-east-1`, `ap-southeast-2` or `us-gov-west-1`.
+fn looks_like_region(region: &str) -> bool {
+    let parts: Vec<&str> = region.split('-').collect();
+    parts.len() >= 3
+        && parts[0].len() == 2
+        && parts.iter().all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()))
+        && parts.last().is_some_and(|last| last.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_warnings() {
+        let value: toml::Value = r#"
+            [defaults]
+            region = "us-est1"
+            output = "xml"
+            max_event = 10
+
+            [profiles.prod]
+            region = "eu-west-1"
+            asume_role = "arn:aws:iam::1:role/x"
+
+            [querys.errors]
+            log_group = "/aws/lambda/app"
+        "#.parse().unwrap();
+
+        let warnings = config_warnings(&value);
+        assert_eq!(warnings.len(), 5, "{:#?}", warnings);
+        assert!(warnings.iter().any(|w| w.contains("'querys'")));
+        assert!(warnings.iter().any(|w| w.contains("'defaults.max_event'")));
+        assert!(warnings.iter().any(|w| w.contains("'profiles.prod.asume_role'")));
+        assert!(warnings.iter().any(|w| w.contains("defaults.output 'xml'")));
+        assert!(warnings.iter().any(|w| w.contains("defaults.region 'us-est1'")));
+
+        assert!(looks_like_region("us-gov-west-1"));
+        assert!(looks_like_region("ap-southeast-2"));
+    }
+
+    #[test]
+    fn test_fallback_log_group_prefers_env_in_order() {
+        let mut defaults = Config::default().defaults;
+        let env = |name: &str| match name {
+            "AWS_LAMBDA_LOG_GROUP_NAME" => Some("/aws/lambda/app".to_string()),
+            "CWL_LOG_GROUP" => Some(" ".to_string()),
+            _ => None,
+        };
+        assert_eq!(defaults.fallback_log_group(env).as_deref(), Some("/aws/lambda/app"));
+
+        defaults.default_log_group = Some("/ecs/api".to_string());
+        assert_eq!(defaults.fallback_log_group(|_| None).as_deref(), Some("/ecs/api"));
+        defaults.log_group_env = vec!["AWS_LAMBDA_LOG_GROUP_NAME".to_string()];
+        assert_eq!(defaults.fallback_log_group(env).as_deref(), Some("/aws/lambda/app"));
+    }
+
+    #[test]
+    fn test_configured_max_events_changes_default_limit() {
+        let defaults = Config::default().defaults;
+        assert_eq!(defaults.event_limit(None, usize::MAX), usize::MAX);
+        assert_eq!(defaults.event_limit(Some(1000), usize::MAX), 1000);
+
+        let config: Config = toml::from_str("[defaults]\nmax_events = 250\n").unwrap();
+        assert_eq!(config.defaults.event_limit(None, usize::MAX), 250);
+        assert_eq!(config.defaults.event_limit(None, 20), 250);
+        assert_eq!(config.defaults.event_limit(Some(1000), usize::MAX), 1000);
+    }
+
+    #[test]
+    fn test_resolve_profile_precedence() {
+        let config: Config = toml::from_str("[defaults]\nprofile = \"dev\"\n").unwrap();
+        let defaults = config.defaults;
+        let env = |name: &str| (name == "AWS_PROFILE").then(|| "staging".to_string());
+
+        assert_eq!(defaults.region, None);
+        assert_eq!(defaults.resolve_profile(Some("prod"), env).as_deref(), Some("prod"));
+        assert_eq!(defaults.resolve_profile(None, env).as_deref(), Some("staging"));
+        assert_eq!(defaults.resolve_profile(None, |_| Some(String::new())).as_deref(), Some("dev"));
+        assert_eq!(Config::default().defaults.resolve_profile(None, |_| None), None);
+    }
+
+    #[test]
+    fn test_saved_query_output_round_trips() {
+        let mut config = Config::default();
+        config.defaults.output = "yaml".to_string();
+        config.queries.insert("errors".to_string(), SavedQuery {
+            log_group: "/aws/lambda/app".to_string(),
+            filter: Some("ERROR".to_string()),
+            since: Some("1h".to_string()),
+            limit: None,
+            formatted: false,
+            output: Some(OutputFormat::Ndjson),
+        });
+        config.queries.insert("plain".to_string(), SavedQuery { output: None, ..config.queries["errors"].clone() });
+
+        let contents = toml::to_string_pretty(&config).unwrap();
+        assert!(contents.contains("output = \"ndjson\""), "{}", contents);
+        let value: toml::Value = contents.parse().unwrap();
+        assert!(config_warnings(&value).is_empty());
+
+        let config: Config = toml::from_str(&contents).unwrap();
+        assert_eq!(config.queries["errors"].output, Some(OutputFormat::Ndjson));
+        assert_eq!(config.output(None, Some("errors")), OutputFormat::Ndjson);
+        assert_eq!(config.output(Some(OutputFormat::Csv), Some("errors")), OutputFormat::Csv);
+        assert_eq!(config.output(None, Some("plain")), OutputFormat::Yaml);
+        assert_eq!(config.output(None, None), OutputFormat::Yaml);
+    }
+}
@@ -0,0 +1,182 @@
+use aws_sdk_cloudwatchlogs::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use std::fmt;
+
+pub const EXIT_AUTH: u8 = 2;
+pub const EXIT_NOT_FOUND: u8 = 3;
+pub const EXIT_THROTTLED: u8 = 4;
+pub const EXIT_INVALID_ARGUMENT: u8 = 5;
+
+/// Error categories that map to distinct process exit codes so scripts can
+/// tell failures apart. Anything uncategorized exits with 1.
+#[derive(Debug)]
+pub enum CwlError {
+    /// Missing, expired or insufficient credentials
+    Auth(String),
+    /// The log group (or another resource) does not exist
+    LogGroupNotFound(String),
+    /// Throttled by the API or timed out
+    Throttled(String),
+    /// Invalid arguments, durations, timestamps or patterns
+    InvalidArgument(String),
+    /// Any other AWS error
+    Aws(String),
+}
+
+impl CwlError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CwlError::Auth(_) => EXIT_AUTH,
+            CwlError::LogGroupNotFound(_) => EXIT_NOT_FOUND,
+            CwlError::Throttled(_) => EXIT_THROTTLED,
+            CwlError::InvalidArgument(_) => EXIT_INVALID_ARGUMENT,
+            CwlError::Aws(_) => 1,
+        }
+    }
+
+    /// A command cut short by `--timeout`.
+    pub fn timeout(timeout: std::time::Duration) -> Self {
+        CwlError::Throttled(format!(
+            "Operation timed out after {}",
+            crate::utils::format::format_delay(timeout.as_millis() as i64)
+        ))
+    }
+
+    /// A log group that doesn't exist in `region`, suggesting the closest of
+    /// the `known` group names in case it was a typo.
+    pub fn log_group_not_found(log_group: &str, region: &str, known: &[String]) -> Self {
+        let partial = log_group.rsplit('/').find(|part| !part.is_empty()).unwrap_or(log_group);
+        let mut message = format!("Log group '{}' not found in region {}", log_group, region);
+        let closest = closest_names(log_group, known);
+        if !closest.is_empty() {
+            message.push_str(&format!("; did you mean {}?", closest.iter()
+                .map(|name| format!("'{}'", name))
+                .collect::<Vec<_>>()
+                .join(" or ")));
+        }
+        message.push_str(&format!(" Search for it with `cwl groups -f {}`", partial));
+        CwlError::LogGroupNotFound(message)
+    }
+
+    /// Categorizes an AWS error code such as `ThrottlingException`.
+    fn from_code(code: &str, message: String) -> Self {
+        match code {
+            "AccessDeniedException"
+            | "UnrecognizedClientException"
+            | "ExpiredTokenException"
+            | "InvalidSignatureException"
+            | "InvalidClientTokenId" => CwlError::Auth(message),
+            "ResourceNotFoundException" => CwlError::LogGroupNotFound(message),
+            "ThrottlingException"
+            | "LimitExceededException"
+            | "ServiceUnavailableException"
+            | "RequestTimeout" => CwlError::Throttled(message),
+            "InvalidParameterException" | "MalformedQueryException" => CwlError::InvalidArgument(message),
+            _ => CwlError::Aws(message),
+        }
+    }
+}
+
+impl fmt::Display for CwlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CwlError::Auth(message)
+            | CwlError::LogGroupNotFound(message)
+            | CwlError::Throttled(message)
+            | CwlError::InvalidArgument(message)
+            | CwlError::Aws(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for CwlError {}
+
+impl<E, R> From<SdkError<E, R>> for CwlError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: fmt::Debug,
+{
+    fn from(err: SdkError<E, R>) -> Self {
+        let message = DisplayErrorContext(&err).to_string();
+
+        if let Some(code) = err.code() {
+            return CwlError::from_code(code, message);
+        }
+
+        match err {
+            SdkError::TimeoutError(_) => CwlError::Throttled(message),
+            _ if message.contains("credentials") => CwlError::Auth(message),
+            _ => CwlError::Aws(message),
+        }
+    }
+}
+
+impl From<regex::Error> for CwlError {
+    fn from(err: regex::Error) -> Self {
+        CwlError::InvalidArgument(format!("Invalid pattern: {}", err))
+    }
+}
+
+impl From<chrono::ParseError> for CwlError {
+    fn from(err: chrono::ParseError) -> Self {
+        CwlError::InvalidArgument(format!("Invalid timestamp: {}", err))
+    }
+}
+
+impl From<std::num::ParseIntError> for CwlError {
+    fn from(err: std::num::ParseIntError) -> Self {
+        CwlError::InvalidArgument(format!("Invalid number: {}", err))
+    }
+}
+
+/// Up to three of `known`, closest first, within a few typos of `name`.
+/// How many depends on the length of its last segment, since groups tend to
+/// share long prefixes such as `/aws/lambda/`.
+fn closest_names<'a>(name: &str, known: &'a [String]) -> Vec<&'a str> {
+    let last_segment = name.rsplit('/').next().unwrap_or(name);
+    let max_distance = (last_segment.chars().count() / 3).clamp(1, 3);
+    let name = name.to_lowercase();
+    let mut candidates: Vec<(usize, &str)> = known.iter()
+        .map(|known| (crate::utils::format::edit_distance(&name, &known.to_lowercase()), known.as_str()))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect();
+    candidates.sort();
+    candidates.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+/// Exit code for an error, taken from the first `CwlError` (or parse error)
+/// in its context chain.
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<CwlError>() {
+            return err.exit_code();
+        }
+        if cause.is::<regex::Error>()
+            || cause.is::<chrono::ParseError>()
+            || cause.is::<std::num::ParseIntError>()
+        {
+            return EXIT_INVALID_ARGUMENT;
+        }
+    }
+
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_exit_code_follows_context_chain() {
+        let err = anyhow::Error::new(CwlError::from_code("ResourceNotFoundException", "missing".to_string()))
+            .context("Failed to get log events for group: /aws/lambda/nope");
+        assert_eq!(exit_code(&err), 3);
+
+        let err = "abc".parse::<i64>().context("Invalid start time format").unwrap_err();
+        assert_eq!(exit_code(&err), 5);
+
+        assert_eq!(exit_code(&anyhow::anyhow!("something else")), 1);
+        assert_eq!(CwlError::from_code("ThrottlingException", String::new()).exit_code(), 4);
+        assert_eq!(CwlError::from_code("ExpiredTokenException", String::new()).exit_code(), 2);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod aws;
 pub mod commands;
 pub mod config;
+pub mod error;
 pub mod utils;
\ No newline at end of file
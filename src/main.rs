@@ -1,22 +1,54 @@
-mod aws;
-mod commands;
-mod config;
-mod utils;
-
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use colored::Colorize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use cwl::{aws, commands, config, error, utils};
 
 #[derive(Parser)]
 #[command(name = "cwl")]
 #[command(about = "CloudWatch Logs CLI - A powerful tool for interacting with AWS CloudWatch Logs")]
 #[command(version)]
 struct Cli {
-    #[arg(short, long, global = true, help = "AWS profile to use")]
+    #[arg(short, long, global = true, help = "AWS profile to use (default: $AWS_PROFILE, then defaults.profile in the config)")]
     profile: Option<String>,
 
-    #[arg(short, long, global = true, help = "AWS region")]
+    #[arg(short, long, global = true, help = "AWS region (default: profile region, then $AWS_REGION, $AWS_DEFAULT_REGION, defaults.region in the config, the AWS config, us-east-1)")]
     region: Option<String>,
 
+    #[arg(long, global = true, value_name = "URL", help = "Send requests to this endpoint instead of AWS, e.g. http://localhost:4566 for LocalStack")]
+    endpoint_url: Option<String>,
+
+    #[arg(short, long, global = true, help = "Suppress headers and summaries, printing only log output")]
+    quiet: bool,
+
+    #[arg(long, global = true, value_name = "IANA_NAME", help = "Display timestamps in this timezone, e.g. Europe/Berlin, or 'local' (default: config, then $TZ, then UTC)")]
+    tz: Option<String>,
+
+    #[arg(long, global = true, conflicts_with_all = ["tz", "local"], help = "Display timestamps in UTC, ignoring the config and $TZ")]
+    utc: bool,
+
+    #[arg(long, global = true, conflicts_with = "tz", help = "Display timestamps in the system timezone")]
+    local: bool,
+
+    #[arg(long, global = true, value_name = "FORMAT", help = "Event timestamp format: a strftime string like '%H:%M:%S' or a preset (default, iso, rfc3339, epoch, short)")]
+    time_format: Option<utils::time::TimeFormat>,
+
+    #[arg(long, global = true, value_name = "STYLE", help = "How matches are highlighted: colors, on_<color> backgrounds and bold, italic, underline, dimmed or reversed, joined with '+' (default: config, then on_yellow+black)")]
+    highlight_color: Option<utils::format::HighlightStyle>,
+
+    #[arg(long, global = true, value_enum, help = "Output format (query, run, format, groups, schema and streams only; default: defaults.output in the config, then colored)")]
+    output: Option<utils::output::OutputFormat>,
+
+    #[arg(long, global = true, value_name = "DURATION", help = "Give up after this long (e.g. 30s, 5m), printing the events fetched so far (default: no timeout)")]
+    timeout: Option<String>,
+
+    #[arg(long, global = true, value_enum, default_value_t, help = "Draw spinners and progress bars: auto skips them when stdout isn't a terminal or $CI is set, printing plain status lines instead (query, tail and groups)")]
+    progress: utils::progress::ProgressMode,
+
+    #[arg(long, global = true, help = "Print the resolved AWS request (time range, filter, limit, region, profile) and exit without calling AWS")]
+    dry_run: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -25,25 +57,172 @@ struct Cli {
 enum Commands {
     #[command(about = "Stream logs in real-time")]
     Tail {
-        #[arg(help = "Log group name")]
-        log_group: String,
+        #[arg(help = "Log group name or ARN (default: $CWL_LOG_GROUP, $AWS_LAMBDA_LOG_GROUP_NAME or defaults.default_log_group)")]
+        log_group: Option<String>,
 
         #[arg(short, long, help = "Follow log stream")]
         follow: bool,
 
-        #[arg(short = 'f', long, help = "Filter pattern")]
-        filter: Option<String>,
+        #[arg(short = 'f', long, help = "Filter pattern (repeat to match any of several)")]
+        filter: Vec<String>,
+
+        #[arg(long, help = "Allow an empty --filter/--grep, which matches everything")]
+        allow_empty_filter: bool,
+
+        #[arg(long = "stream", visible_alias = "streams", value_delimiter = ',', value_name = "STREAM", help = "Only show these log streams (repeat or comma-separate); a single unfiltered stream is followed directly")]
+        streams: Vec<String>,
 
-        #[arg(long, help = "Highlight matches")]
+        #[arg(long, value_name = "PREFIX", conflicts_with = "streams", help = "Only show log streams whose names start with this")]
+        stream_prefix: Option<String>,
+
+        #[arg(long, value_name = "REGEX", help = "Only show messages matching this regex, checked locally after --filter")]
+        grep: Option<String>,
+
+        #[arg(short = 'v', long, requires = "grep", help = "Show messages --grep does NOT match instead")]
+        invert_match: bool,
+
+        #[arg(short = 'i', long, help = "Match --grep, and --filter highlighting, case-insensitively")]
+        ignore_case: bool,
+
+        #[arg(long, help = "Only highlight lines that --filter matches in full")]
+        whole_line: bool,
+
+        #[arg(long, visible_alias = "since", value_name = "SINCE", help = "Start from this far back (e.g., 10m, 1h); without --follow the default is 5m")]
+        from: Option<String>,
+
+        #[arg(short = 'n', long, value_name = "N", conflicts_with = "follow", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Show at most N events when not following (default: defaults.max_events in the config, then 100)")]
+        lines: Option<usize>,
+
+        #[arg(long, value_name = "DURATION", help = "How often to poll for new events in follow mode (e.g., 250ms, 5s; default: 1s)")]
+        poll_interval: Option<String>,
+
+        #[arg(long, value_name = "DURATION", help = "Back off toward this poll interval while no new events arrive (default: no backoff)")]
+        max_poll_interval: Option<String>,
+
+        #[arg(long, requires = "follow", conflicts_with_all = ["from", "poll_interval", "max_poll_interval"], help = "Follow through a StartLiveTail session, which pushes new events within about a second instead of polling for them")]
+        live: bool,
+
+        #[arg(long, overrides_with = "no_highlight", help = "Highlight --filter and --grep matches (default)")]
         highlight: bool,
+
+        #[arg(long, overrides_with = "highlight", help = "Don't highlight --filter and --grep matches")]
+        no_highlight: bool,
+
+        #[arg(long, value_delimiter = ',', help = "Show only these JSON fields as key=value pairs (e.g. level,msg)")]
+        fields: Vec<String>,
+
+        #[arg(long, help = "Also append output to this file (ANSI colors stripped)")]
+        output_file: Option<PathBuf>,
+
+        #[arg(long, help = "Collapse consecutive identical messages into one line with a count")]
+        dedup: bool,
+
+        #[arg(long, requires = "dedup", help = "With --dedup, treat messages differing only in whitespace as distinct")]
+        dedup_strict: bool,
+
+        #[arg(long, conflicts_with_all = ["fields", "dedup"], help = "Print only each message, verbatim, with no timestamp, stream or colors")]
+        raw: bool,
+
+        #[arg(long, help = "Remove ANSI color codes already present in messages (always done for json, csv and table output)")]
+        strip_ansi: bool,
+
+        #[arg(long, requires = "follow", help = "Show a live footer with event rate, total seen and filter matches")]
+        stats: bool,
+
+        #[arg(long, conflicts_with = "raw", help = "Print events grouped under a header per log stream instead of interleaved")]
+        group_by_stream: bool,
+
+        #[arg(long, value_name = "N", requires = "follow", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Print at most N lines per second, reporting how many were suppressed")]
+        max_rate: Option<usize>,
+
+        #[arg(long, value_name = "BYTES", default_value_t = utils::format::DEFAULT_MAX_LINE_LENGTH, help = "Cut messages longer than this before highlighting and printing them (0 for no limit)")]
+        max_line_length: usize,
+
+        #[arg(long, overrides_with = "no_wrap", help = "Print each line of a multi-line message on its own line, indented under the first (default)")]
+        wrap: bool,
+
+        #[arg(long, overrides_with = "wrap", help = "Keep multi-line messages on one line, with ⏎ where each newline was")]
+        no_wrap: bool,
+
+        #[arg(long, conflicts_with_all = ["raw", "fields"], help = "Render each event with a template, e.g. '{timestamp} {json:level} {message}'")]
+        template: Option<String>,
+
+        #[arg(long, help = "Reveal fields masked by a data protection policy (requires the logs:Unmask permission)")]
+        unmask: bool,
     },
 
     #[command(about = "Query historical logs")]
-    Query {
+    Query(Box<QueryArgs>),
+
+    #[command(about = "Format saved log lines (e.g. from export) from a file or stdin, without calling AWS")]
+    Format {
+        #[arg(help = "File of `[timestamp] [stream] message` or JSON-per-line logs, optionally .gz (default: stdin)")]
+        path: Option<PathBuf>,
+
+        #[arg(long, value_name = "REGEX", help = "Only show messages matching this regex")]
+        grep: Option<String>,
+
+        #[arg(short = 'v', long, requires = "grep", help = "Show messages --grep does NOT match instead")]
+        invert_match: bool,
+
+        #[arg(short = 'i', long, help = "Match --grep case-insensitively")]
+        ignore_case: bool,
+
+        #[arg(long, help = "Only match lines that --grep matches in full")]
+        whole_line: bool,
+
+        #[arg(long, help = "Format output as table with dynamic columns")]
+        formatted: bool,
+
+        #[arg(long, value_enum, default_value = "auto", requires = "formatted", help = "How to parse messages into table columns")]
+        format_hint: utils::json_formatter::FormatHint,
+
+        #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Split nested JSON into columns up to N levels deep (default: 3); deeper values stay as compact JSON")]
+        flatten_depth: Option<usize>,
+
+        #[arg(long, conflicts_with = "flatten_depth", help = "One table column per top-level field, with nested objects and arrays as compact JSON (same as --flatten-depth 1)")]
+        compact: bool,
+
+        #[arg(long, help = "Split Lambda START/END/REPORT lines into columns in table and CSV output")]
+        lambda: bool,
+
+        #[arg(long, help = "Show embedded metric format (EMF) logs as metric and dimension columns in table and CSV output")]
+        emf: bool,
+
+        #[arg(long, value_delimiter = ',', value_name = "FIELD", help = "Put these columns first in table and CSV output, after timestamp and log_group")]
+        pin: Vec<String>,
+
+        #[arg(long, value_name = "PCT", value_parser = utils::json_formatter::parse_percent, help = "Drop table and CSV columns present in fewer than PCT% of rows (e.g. 5)")]
+        min_frequency: Option<f64>,
+
+        #[arg(long, value_name = "N", requires = "formatted", help = "Fit the formatted table into N columns (default: terminal width)")]
+        max_width: Option<usize>,
+
+        #[arg(long, value_delimiter = ',', value_name = "N|FIELD=N", value_parser = utils::json_formatter::parse_column_width, requires = "formatted", help = "Cap formatted table columns at N characters, or one column with FIELD=N (e.g. 30,message=60)")]
+        col_width: Vec<utils::json_formatter::ColumnWidth>,
+
+        #[arg(long, requires = "formatted", conflicts_with = "max_width", help = "Never truncate formatted table values")]
+        no_truncate: bool,
+
+        #[arg(long, help = "Disable log level coloring in formatted table output")]
+        no_level_color: bool,
+
+        #[arg(long, overrides_with = "no_wrap", help = "Print each line of a multi-line message on its own line, indented under the first (default)")]
+        wrap: bool,
+
+        #[arg(long, overrides_with = "wrap", help = "Keep multi-line messages on one line, with ⏎ where each newline was")]
+        no_wrap: bool,
+    },
+
+    #[command(about = "Show an ASCII histogram of event counts over time")]
+    Histogram {
         #[arg(help = "Log group name")]
         log_group: String,
 
-        #[arg(long, help = "Time since (e.g., 1h, 30m, 1d)")]
+        #[arg(long, default_value = "5m", help = "Bucket size (e.g., 1m, 5m, 1h)")]
+        interval: String,
+
+        #[arg(long, conflicts_with_all = ["start", "end"], help = "Time since (e.g., 1h, 30m, 1d); can't be combined with --start/--end")]
         since: Option<String>,
 
         #[arg(long, help = "Start time (ISO 8601 or Unix timestamp)")]
@@ -54,41 +233,933 @@ enum Commands {
 
         #[arg(short = 'f', long, help = "Filter pattern")]
         filter: Option<String>,
+    },
+
+    #[command(about = "Compare message patterns between two time windows")]
+    Diff {
+        #[arg(help = "Log group name")]
+        log_group: String,
 
-        #[arg(long, help = "Maximum number of events (default: fetch all in time range)")]
+        #[arg(long, value_name = "START..END", help = "Baseline window (e.g. '3h ago..2h ago')")]
+        window_a: String,
+
+        #[arg(long, value_name = "START..END", help = "Window to compare against the baseline (e.g. '1h ago..', where an empty end means now)")]
+        window_b: String,
+
+        #[arg(short = 'f', long, help = "Filter pattern")]
+        filter: Option<String>,
+
+        #[arg(long, help = "Maximum number of patterns to show, largest changes first")]
+        limit: Option<usize>,
+
+        #[arg(long, help = "Also show patterns whose count didn't change")]
+        all: bool,
+    },
+
+    #[command(about = "Follow one request across streams by a shared JSON field, or list the busiest request ids")]
+    Trace {
+        #[arg(help = "Log group name")]
+        log_group: String,
+
+        #[arg(long, default_value = commands::trace::DEFAULT_KEY, help = "JSON field holding the request id, as a dot path (ctx.request_id) or JSON Pointer")]
+        key: String,
+
+        #[arg(long, help = "Print this request's events as one time-ordered trace; without it, list the request ids with the most events")]
+        id: Option<String>,
+
+        #[arg(long, conflicts_with_all = ["start", "end"], help = "Time since (e.g., 1h, 30m, 1d); can't be combined with --start/--end")]
+        since: Option<String>,
+
+        #[arg(long, help = "Start time (ISO 8601 or Unix timestamp)")]
+        start: Option<String>,
+
+        #[arg(long, help = "End time (ISO 8601 or Unix timestamp)")]
+        end: Option<String>,
+
+        #[arg(short = 'f', long, help = "Filter pattern (default with --id: the id as a quoted term)")]
+        filter: Option<String>,
+
+        #[arg(long, default_value_t = 10, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Number of request ids to list without --id")]
+        limit: usize,
+    },
+
+    #[command(visible_alias = "summary", about = "Infer the JSON fields of a log group's messages from a sample of recent events")]
+    Schema {
+        #[arg(help = "Log group name")]
+        log_group: String,
+
+        #[arg(long, value_name = "N", default_value_t = commands::schema::DEFAULT_SAMPLE_SIZE, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "How many of the newest events to sample")]
+        sample_size: usize,
+
+        #[arg(long, conflicts_with_all = ["start", "end"], help = "Time since (e.g., 1h, 30m, 1d); can't be combined with --start/--end")]
+        since: Option<String>,
+
+        #[arg(long, help = "Start time (ISO 8601 or Unix timestamp)")]
+        start: Option<String>,
+
+        #[arg(long, help = "End time (ISO 8601 or Unix timestamp)")]
+        end: Option<String>,
+
+        #[arg(short = 'f', long, help = "Filter pattern, to sample only some of the group's events")]
+        filter: Option<String>,
+
+        #[arg(long, value_name = "N", default_value_t = utils::json_formatter::DEFAULT_FLATTEN_DEPTH, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Report nested JSON fields up to N levels deep; deeper values are reported as objects or arrays")]
+        flatten_depth: usize,
+    },
+
+    #[command(about = "Browse a log group's events in a full-screen terminal UI (needs the tui feature)")]
+    Browse {
+        #[arg(help = "Log group name")]
+        log_group: String,
+
+        #[arg(long, conflicts_with_all = ["start", "end"], help = "Time since (e.g., 1h, 30m, 1d); can't be combined with --start/--end")]
+        since: Option<String>,
+
+        #[arg(long, help = "Start time (ISO 8601 or Unix timestamp)")]
+        start: Option<String>,
+
+        #[arg(long, help = "End time (ISO 8601 or Unix timestamp)")]
+        end: Option<String>,
+
+        #[arg(short = 'f', long, help = "Filter pattern, also applied to live events")]
+        filter: Option<String>,
+
+        #[arg(long, default_value_t = 10_000, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Load at most this many events, the newest")]
+        limit: usize,
+    },
+
+    #[command(about = "Export historical logs to a file")]
+    Export {
+        #[arg(help = "Log group name")]
+        log_group: String,
+
+        #[arg(help = "Output file path (compressed with gzip when it ends in .gz)")]
+        path: PathBuf,
+
+        #[arg(long, conflicts_with_all = ["start", "end"], help = "Time since (e.g., 1h, 30m, 1d); can't be combined with --start/--end")]
+        since: Option<String>,
+
+        #[arg(long, help = "Start time (ISO 8601 or Unix timestamp)")]
+        start: Option<String>,
+
+        #[arg(long, help = "End time (ISO 8601 or Unix timestamp)")]
+        end: Option<String>,
+
+        #[arg(short = 'f', long, help = "Filter pattern")]
+        filter: Option<String>,
+
+        #[arg(long, value_name = "DURATION", help = "Write one file per UTC time bucket (e.g. 1d, 1h), named like logs-2024-01-01.txt after the output path")]
+        split_by: Option<String>,
+
+        #[arg(long, help = "Compress the output with gzip regardless of the file extension")]
+        gzip: bool,
+
+        #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(0..=9), help = "Gzip compression level (0-9)")]
+        compression_level: u32,
+    },
+
+    #[command(about = "Run a saved query from the config file")]
+    Run {
+        #[arg(help = "Saved query name")]
+        name: String,
+
+        #[arg(long, help = "Override the saved time window (e.g., 1h, 30m, 1d)")]
+        since: Option<String>,
+
+        #[arg(short = 'f', long, help = "Override the saved filter pattern")]
+        filter: Option<String>,
+
+        #[arg(long, help = "Override the saved maximum number of events")]
         limit: Option<usize>,
 
         #[arg(long, help = "Format output as table with dynamic columns")]
         formatted: bool,
+
+        #[arg(short = 'y', long, help = "Skip the confirmation prompt for large unbounded queries")]
+        yes: bool,
+    },
+
+    #[command(about = "Show a log group's streams and when each last received events")]
+    Describe {
+        #[arg(help = "Log group name")]
+        log_group: String,
+
+        #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Maximum number of streams to show")]
+        limit: Option<usize>,
+
+        #[arg(long, help = "Only show streams with events in the last hour")]
+        active: bool,
+    },
+
+    #[command(about = "List the log streams of a log group with their creation and last event times")]
+    Streams {
+        #[arg(help = "Log group name")]
+        log_group: String,
+
+        #[arg(long, help = "Only list streams whose names start with this")]
+        prefix: Option<String>,
+
+        #[arg(long, help = "List the most recently active streams first instead of sorting by name")]
+        order_by_last_event: bool,
+
+        #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Maximum number of streams to show")]
+        limit: Option<usize>,
+    },
+
+    #[command(about = "Set the retention policy of one or more log groups")]
+    PutRetention {
+        #[arg(required_unless_present = "all_matching", help = "Log group name")]
+        log_group: Option<String>,
+
+        #[arg(long, value_name = "PATTERN", conflicts_with = "log_group", help = "Apply to every log group matching this prefix or * pattern")]
+        all_matching: Option<String>,
+
+        #[arg(long, help = "Retention in days (1, 3, 5, 7, 14, 30, 60, 90, 120, 150, 180, 365, 400, 545, 731, 1096, 1827, 2192, 2557, 2922, 3288 or 3653)")]
+        days: i32,
+
+        #[arg(short = 'y', long, help = "Skip the confirmation prompt for --all-matching")]
+        yes: bool,
+    },
+
+    #[command(about = "Write a single event to a log stream, creating the stream if needed")]
+    Put {
+        #[arg(help = "Log group name")]
+        log_group: String,
+
+        #[arg(help = "Log stream name")]
+        stream: String,
+
+        #[arg(help = "Event message, or - to read it from stdin")]
+        message: String,
+
+        #[arg(long, help = "Event time (e.g. '2024-01-15 10:30:00', '5m ago' or epoch millis); defaults to now")]
+        timestamp: Option<String>,
     },
 
     #[command(about = "List available log groups")]
     Groups {
-        #[arg(short = 'f', long, help = "Filter log groups by pattern")]
+        #[arg(short = 'f', long, help = "Filter log groups by regex (invalid regexes match literally)")]
         filter: Option<String>,
+
+        #[arg(long, help = "Allow an empty --filter, which matches everything")]
+        allow_empty_filter: bool,
+
+        #[arg(long, help = "Match --filter as a case-insensitive fuzzy pattern instead of a regex")]
+        fuzzy: bool,
+
+        #[arg(long, value_enum, default_value = "name", help = "Sort order for log groups")]
+        sort: commands::groups::GroupSort,
+
+        #[arg(long, help = "Maximum number of log groups to show")]
+        limit: Option<usize>,
+
+        #[arg(long, value_name = "DURATION", help = "Only show groups with no events within this duration (e.g. 90d)")]
+        inactive: Option<String>,
+
+        #[arg(long, help = "Only show groups that store no data")]
+        empty: bool,
+
+        #[arg(long, conflicts_with = "limit", help = "Print only the number of matching log groups")]
+        count: bool,
+
+        #[arg(long, help = "Show each group's class (Standard or Infrequent Access), retention and stored size")]
+        details: bool,
+
+        #[arg(long, conflicts_with = "count", help = "List each group's metric filters: name, filter pattern and target metric namespace/name")]
+        with_metric_filters: bool,
     },
 }
 
+#[derive(Args)]
+struct QueryArgs {
+    #[arg(help = "Log group name or ARN, or a pattern like /aws/lambda/prod-* to query every matching group (default: $CWL_LOG_GROUP, $AWS_LAMBDA_LOG_GROUP_NAME or defaults.default_log_group)")]
+    log_group: Option<String>,
+
+    #[arg(long, help = "Treat the log group argument as a prefix and query every matching group")]
+    group_pattern: bool,
+
+    #[arg(long, conflicts_with_all = ["start", "end"], help = "Time since (e.g., 1h, 30m, 1d); can't be combined with --start/--end")]
+    since: Option<String>,
+
+    #[arg(long, help = "Start time (ISO 8601 or Unix timestamp)")]
+    start: Option<String>,
+
+    #[arg(long, help = "End time (ISO 8601 or Unix timestamp)")]
+    end: Option<String>,
+
+    #[arg(short = 'f', long, help = "Filter pattern (repeat to match any of several)")]
+    filter: Vec<String>,
+
+    #[arg(long = "where", visible_alias = "filter-field", value_name = "CONDITION", conflicts_with = "filter", help = "Match a JSON field, e.g. level=ERROR or status>=500 (ops: = != > >= < <=; repeat to AND several); compiled into a JSON filter pattern")]
+    conditions: Vec<String>,
+
+    #[arg(long, help = "Allow an empty --filter/--grep, which matches everything")]
+    allow_empty_filter: bool,
+
+    #[arg(long, value_name = "REGEX", help = "Only show messages matching this regex, checked locally after --filter")]
+    grep: Option<String>,
+
+    #[arg(short = 'v', long, requires = "grep", help = "Show messages --grep does NOT match instead")]
+    invert_match: bool,
+
+    #[arg(long, conflicts_with = "non_json_only", help = "Drop messages that aren't JSON objects (stack traces, banners)")]
+    json_only: bool,
+
+    #[arg(long, help = "Only show messages that aren't JSON objects")]
+    non_json_only: bool,
+
+    #[arg(short = 'i', long, help = "Match --grep, and --filter highlighting, case-insensitively")]
+    ignore_case: bool,
+
+    #[arg(long, help = "Only highlight lines that --filter matches in full")]
+    whole_line: bool,
+
+    #[arg(short = 'C', long, value_name = "N", requires = "filter", help = "Show N events before and after each --filter match (fetches every event in the range)")]
+    context: Option<usize>,
+
+    #[arg(short = 'B', long, value_name = "N", requires = "filter", help = "Show N events before each --filter match")]
+    before: Option<usize>,
+
+    #[arg(short = 'A', long, value_name = "N", requires = "filter", help = "Show N events after each --filter match")]
+    after: Option<usize>,
+
+    #[arg(long, help = "Maximum number of events (default: defaults.max_events in the config, then fetch all in time range)")]
+    limit: Option<usize>,
+
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Keep at most N events from each log stream, so one noisy stream can't use up --limit")]
+    limit_per_stream: Option<usize>,
+
+    #[arg(long, value_name = "RATE", value_parser = utils::sample::parse_rate, conflicts_with_all = ["sample_n", "context", "before", "after"], help = "Keep each matched event with this probability (e.g. 0.01 for 1%)")]
+    sample: Option<f64>,
+
+    #[arg(long, value_name = "N", conflicts_with_all = ["context", "before", "after"], help = "Keep exactly N matched events, chosen uniformly at random")]
+    sample_n: Option<usize>,
+
+    #[arg(long, help = "Seed for --sample/--sample-n so the same events are picked every run")]
+    seed: Option<u64>,
+
+    #[arg(long, conflicts_with = "oldest", help = "With --limit, keep the most recent events (default)")]
+    newest: bool,
+
+    #[arg(long, help = "With --limit, keep the earliest events in the range instead of the most recent")]
+    oldest: bool,
+
+    #[arg(long, conflicts_with_all = ["end", "oldest", "formatted", "context", "before", "after", "sample", "sample_n", "pager", "json_only", "non_json_only", "group_pattern"], help = "After printing the results, keep following new events like tail --follow (until Ctrl-C)")]
+    follow: bool,
+
+    #[arg(long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=32), help = "Split the time range into this many chunks fetched concurrently (with --limit, scan this many windows back from the end at once)")]
+    concurrency: usize,
+
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["resume", "limit", "concurrency", "follow", "group_pattern"], help = "Save each fetched page to FILE so an interrupted query can be continued with --resume FILE")]
+    checkpoint: Option<PathBuf>,
+
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["since", "start", "end", "limit", "concurrency", "follow", "group_pattern"], help = "Continue a query interrupted while writing --checkpoint FILE, using its time range")]
+    resume: Option<PathBuf>,
+
+    #[arg(long, conflicts_with_all = ["fields", "dedup", "formatted"], help = "Print only each message, verbatim, with no timestamp, stream or colors")]
+    raw: bool,
+
+    #[arg(long, help = "Remove ANSI color codes already present in messages (always done for json, csv and table output)")]
+    strip_ansi: bool,
+
+    #[arg(long, help = "Reveal fields masked by a data protection policy (requires the logs:Unmask permission)")]
+    unmask: bool,
+
+    #[arg(long, conflicts_with_all = ["raw", "fields", "formatted"], help = "Render each event with a template, e.g. '{timestamp} {json:level} {message}'")]
+    template: Option<String>,
+
+    #[arg(short = 'P', long, help = "Page output through $PAGER (default: less -R) when stdout is a terminal")]
+    pager: bool,
+
+    #[arg(long, conflicts_with = "pager", help = "Don't page output, even if defaults.use_pager is set")]
+    no_pager: bool,
+
+    #[arg(long, help = "Format output as table with dynamic columns")]
+    formatted: bool,
+
+    #[arg(long, value_enum, default_value = "auto", requires = "formatted", help = "How to parse messages into table columns")]
+    format_hint: utils::json_formatter::FormatHint,
+
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Split nested JSON into columns up to N levels deep (default: 3); deeper values stay as compact JSON")]
+    flatten_depth: Option<usize>,
+
+    #[arg(long, conflicts_with = "flatten_depth", help = "One table column per top-level field, with nested objects and arrays as compact JSON (same as --flatten-depth 1)")]
+    compact: bool,
+
+    #[arg(long, help = "Split Lambda START/END/REPORT lines into columns (request_id, duration_ms, billed_ms, memory_mb, ...) in table and CSV output")]
+    lambda: bool,
+
+    #[arg(long, help = "Show embedded metric format (EMF) logs as namespace/metric = value unit plus their dimensions in table and CSV output, instead of flattening the _aws block")]
+    emf: bool,
+
+    #[arg(long, value_name = "N", requires = "formatted", help = "Fit the formatted table into N columns (default: terminal width)")]
+    max_width: Option<usize>,
+
+    #[arg(long, value_delimiter = ',', value_name = "N|FIELD=N", value_parser = utils::json_formatter::parse_column_width, requires = "formatted", help = "Cap formatted table columns at N characters, or one column with FIELD=N (e.g. 30,message=60)")]
+    col_width: Vec<utils::json_formatter::ColumnWidth>,
+
+    #[arg(long, value_delimiter = ',', value_name = "FIELD", help = "Put these columns first in table and CSV output, after timestamp and log_group")]
+    pin: Vec<String>,
+
+    #[arg(long, value_name = "PCT", value_parser = utils::json_formatter::parse_percent, help = "Drop table and CSV columns present in fewer than PCT% of rows (e.g. 5)")]
+    min_frequency: Option<f64>,
+
+    #[arg(long, help = "Put the fields --filter and --grep refer to (e.g. $.level, \"status\":, user=) first in table and CSV output")]
+    fields_from_filter: bool,
+
+    #[arg(long, help = "After the results, estimate the data scanned and its cost at defaults.cost_per_gb (from the log group's stored bytes, since FilterLogEvents doesn't report it)")]
+    show_cost: bool,
+
+    #[arg(long, value_name = "JSON_PATH", help = "Take the timestamp column of table and CSV output from this JSON field (e.g. @timestamp, meta.time), falling back to the event time where it's missing or unparseable")]
+    time_field: Option<String>,
+
+    #[arg(long, conflicts_with_all = ["raw", "template", "formatted"], help = "Show how long after each event CloudWatch ingested it, e.g. (+1.2s); with --output csv, add an ingestion_delay column")]
+    show_delay: bool,
+
+    #[arg(long, value_name = "DURATION", help = "Draw ingestion delays longer than this (e.g. 30s) in red")]
+    lag_threshold: Option<String>,
+
+    #[arg(long, requires = "formatted", conflicts_with = "max_width", help = "Never truncate formatted table values (e.g. for piping to `less -S`)")]
+    no_truncate: bool,
+
+    #[arg(long, value_name = "BYTES", default_value_t = utils::format::DEFAULT_MAX_LINE_LENGTH, help = "Cut messages longer than this before highlighting and printing them (0 for no limit)")]
+    max_line_length: usize,
+
+    #[arg(long, conflicts_with_all = ["raw", "template", "formatted", "fields", "dedup"], help = "Print each message's bytes as a hex dump (like hexdump -C) instead of text, e.g. to inspect binary payloads")]
+    hexdump: bool,
+
+    #[arg(long, overrides_with = "no_wrap", help = "Print each line of a multi-line message on its own line, indented under the first (default)")]
+    wrap: bool,
+
+    #[arg(long, overrides_with = "wrap", help = "Keep multi-line messages on one line, with ⏎ where each newline was")]
+    no_wrap: bool,
+
+    #[arg(long, overrides_with = "no_highlight", help = "Highlight --filter and --grep matches (default)")]
+    highlight: bool,
+
+    #[arg(long, overrides_with = "highlight", help = "Don't highlight --filter and --grep matches")]
+    no_highlight: bool,
+
+    #[arg(short = 'y', long, help = "Skip the confirmation prompt for large unbounded queries")]
+    yes: bool,
+
+    #[arg(long, value_name = "NAME", help = "Save this query to the config file under NAME (see `cwl run`)")]
+    save: Option<String>,
+
+    #[arg(long, help = "Disable log level coloring in formatted table output")]
+    no_level_color: bool,
+
+    #[arg(long, value_delimiter = ',', conflicts_with = "formatted", help = "Show only these JSON fields as key=value pairs (e.g. level,msg)")]
+    fields: Vec<String>,
+
+    #[arg(long, help = "Also append output to this file (ANSI colors stripped)")]
+    output_file: Option<PathBuf>,
+
+    #[arg(long, help = "Collapse consecutive identical messages into one line with a count")]
+    dedup: bool,
+
+    #[arg(long, requires = "dedup", help = "With --dedup, treat messages differing only in whitespace as distinct")]
+    dedup_strict: bool,
+}
+
+impl Commands {
+    /// Whether the command takes `--output` (or `defaults.output`) into
+    /// account; the rest always print colored text.
+    fn honors_output(&self) -> bool {
+        matches!(self, Commands::Query(_) | Commands::Run { .. } | Commands::Format { .. } | Commands::Groups { .. } | Commands::Schema { .. } | Commands::Streams { .. })
+    }
+
+    /// The log group argument of commands that target a single group.
+    fn log_group_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Commands::Histogram { log_group, .. }
+            | Commands::Diff { log_group, .. }
+            | Commands::Trace { log_group, .. }
+            | Commands::Schema { log_group, .. }
+            | Commands::Browse { log_group, .. }
+            | Commands::Export { log_group, .. }
+            | Commands::Describe { log_group, .. }
+            | Commands::Streams { log_group, .. }
+            | Commands::Put { log_group, .. } => Some(log_group),
+            Commands::Tail { log_group, .. }
+            | Commands::PutRetention { log_group, .. } => log_group.as_mut(),
+            Commands::Query(args) => args.log_group.as_mut(),
+            Commands::Format { .. } | Commands::Run { .. } | Commands::Groups { .. } => None,
+        }
+    }
+}
+
+/// The error for a `tail` or `query` with no log group on the command line,
+/// in the environment or in the config.
+fn missing_log_group(defaults: &config::DefaultConfig) -> error::CwlError {
+    error::CwlError::InvalidArgument(format!(
+        "No log group given: pass one, set {}, or set defaults.default_log_group in the config",
+        defaults.log_group_env.join(" or ")
+    ))
+}
+
+/// How long past `--timeout` a command may run before it is cut off, so
+/// fetches that stopped at the deadline can still print their partial
+/// results.
+const TIMEOUT_GRACE: std::time::Duration = std::time::Duration::from_secs(5);
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            return if err.use_stderr() {
+                ExitCode::from(error::EXIT_INVALID_ARGUMENT)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+    // Settled once the config is loaded, since defaults.output can ask for JSON
+    let mut json_errors = cli.output.is_some_and(utils::output::OutputFormat::is_json);
 
-    let aws_client = aws::client::CloudWatchClient::new(
-        cli.profile.as_deref(),
-        cli.region.as_deref(),
-    ).await?;
-
-    match cli.command {
-        Commands::Tail { log_group, follow, filter, highlight } => {
-            commands::tail::run(aws_client, log_group, follow, filter, highlight).await?;
-        },
-        Commands::Query { log_group, since, start, end, filter, limit, formatted } => {
-            commands::query::run(aws_client, log_group, since, start, end, filter, limit.unwrap_or(usize::MAX), formatted).await?;
-        },
-        Commands::Groups { filter } => {
-            commands::groups::run(aws_client, filter).await?;
-        },
+    match run(cli, &mut json_errors).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let code = error::exit_code(&err);
+            if json_errors {
+                eprintln!("{}", serde_json::json!({ "error": format!("{:#}", err), "code": code }));
+            } else {
+                eprintln!("{} {:#}", "Error:".bright_red().bold(), err);
+            }
+            ExitCode::from(code)
+        }
     }
+}
 
-    Ok(())
+async fn run(mut cli: Cli, json_errors: &mut bool) -> Result<()> {
+    let mut config = config::Config::load()?;
+    let tz_flag = match (cli.utc, cli.local) {
+        (true, _) => Some("UTC"),
+        (_, true) => Some("local"),
+        _ => cli.tz.as_deref(),
+    };
+    let tz = utils::time::resolve_timezone(tz_flag, config.defaults.timezone.as_deref())?;
+    // The config value was validated when it was loaded
+    let time_format = cli.time_format
+        .or_else(|| config.defaults.time_format.as_deref().and_then(|format| format.parse().ok()))
+        .unwrap_or_default();
+    let saved_query = match &cli.command {
+        Commands::Run { name, .. } => Some(name.as_str()),
+        _ => None,
+    };
+    let output = config.output(cli.output, saved_query);
+    // defaults.output only applies to the commands that take --output
+    *json_errors = output.is_json() && (cli.output.is_some() || cli.command.honors_output());
+    // Machine-readable output never carries color codes, even on a terminal
+    if output != utils::output::OutputFormat::Colored && cli.command.honors_output() {
+        colored::control::set_override(false);
+    }
+    let highlight_style = cli.highlight_color
+        .or_else(|| config.defaults.highlight_style.as_deref().and_then(|style| style.parse().ok()))
+        .unwrap_or_default();
+    let timeout = cli.timeout.as_deref()
+        .map(|timeout| utils::time::parse_duration(timeout).context("Invalid --timeout"))
+        .transpose()?
+        .map(|timeout| timeout.to_std().unwrap_or_default());
+
+    let profile = config.defaults.resolve_profile(cli.profile.as_deref(), |name| std::env::var(name).ok());
+    let profile_region = profile.as_ref()
+        .and_then(|profile| config.profiles.get(profile))
+        .and_then(|profile| profile.region.as_deref());
+    // Formatting local files needs no AWS client (or credentials)
+    if let Commands::Format { path, grep, invert_match, ignore_case, whole_line, formatted, format_hint, flatten_depth, compact, lambda, emf, pin, min_frequency, max_width, col_width, no_truncate, no_level_color, wrap: _, no_wrap } = cli.command {
+        return commands::format::run(commands::format::FormatOptions {
+            path,
+            grep,
+            invert_match,
+            regex_options: utils::format::RegexOptions { ignore_case, whole_line },
+            highlight_style,
+            formatted,
+            format_hint,
+            flatten_depth: if compact { Some(1) } else { flatten_depth },
+            lambda,
+            emf,
+            pin,
+            min_frequency,
+            max_width,
+            col_width,
+            no_truncate,
+            no_level_color,
+            no_wrap,
+            output,
+            quiet: cli.quiet,
+        });
+    }
+
+    // Inside Lambda or ECS the log group usually comes from the environment
+    let log_group = match &mut cli.command {
+        Commands::Tail { log_group, .. } => Some(log_group),
+        Commands::Query(args) => Some(&mut args.log_group),
+        _ => None,
+    };
+    if let Some(log_group) = log_group {
+        if log_group.is_none() {
+            let fallback = config.defaults.fallback_log_group(|name| std::env::var(name).ok());
+            if fallback.is_none() {
+                bail!(missing_log_group(&config.defaults));
+            }
+            *log_group = fallback;
+        }
+    }
+
+    // A log group ARN names its region, which takes precedence
+    let arn = cli.command.log_group_mut()
+        .and_then(|log_group| aws::arn::LogGroupArn::parse(log_group).map(|arn| (log_group, arn)))
+        .map(|(log_group, arn)| {
+            *log_group = arn.identifier();
+            arn
+        });
+    if let (Some(arn), Some(region)) = (&arn, &cli.region) {
+        if arn.region != *region {
+            eprintln!("{} Using region {} from the log group ARN instead of --region {}",
+                "Warning:".yellow().bold(),
+                arn.region,
+                region
+            );
+        }
+    }
+
+    let aws_client = aws::client::CloudWatchClient::new(
+        profile.as_deref(),
+        arn.as_ref().map(|arn| arn.region.as_str()).or(cli.region.as_deref()).or(profile_region),
+        config.defaults.region.as_deref(),
+        cli.endpoint_url.as_deref(),
+    ).await?
+        .with_dry_run(cli.dry_run, output)
+        .with_timeout(timeout);
+
+    let command = async move {
+        match cli.command {
+            Commands::Tail { log_group, follow, filter, allow_empty_filter, grep, invert_match, streams, stream_prefix, ignore_case, whole_line, from, lines, poll_interval, max_poll_interval, live, highlight: _, no_highlight, fields, output_file, dedup, dedup_strict, raw, strip_ansi, stats, group_by_stream, max_rate, max_line_length, wrap: _, no_wrap, template, unmask } => {
+                let log_group = log_group.ok_or_else(|| missing_log_group(&config.defaults))?;
+                // The configured template only replaces the default layout
+                let template = template.or_else(|| {
+                    (!raw && fields.is_empty()).then(|| config.defaults.default_template.clone()).flatten()
+                });
+
+                commands::tail::run(aws_client.with_unmask(unmask), commands::tail::TailOptions {
+                    log_group,
+                    follow,
+                    filters: filter,
+                    allow_empty_filter,
+                    grep,
+                    invert_match,
+                    streams,
+                    stream_prefix,
+                    regex_options: utils::format::RegexOptions { ignore_case, whole_line },
+                    from,
+                    lines: config.defaults.event_limit(lines, commands::tail::DEFAULT_LINES),
+                    resume: None,
+                    poll_interval,
+                    max_poll_interval,
+                    live,
+                    highlight: !no_highlight,
+                    highlight_style,
+                    fields,
+                    output_file,
+                    dedup,
+                    dedup_strict,
+                    stats,
+                    group_by_stream,
+                    max_rate,
+                    max_line_length,
+                    no_wrap,
+                    tz,
+                    time_format,
+                    raw,
+                    strip_ansi,
+                    template,
+                    progress: cli.progress,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            Commands::Query(args) => {
+                let QueryArgs { log_group, group_pattern, since, start, end, filter, conditions, allow_empty_filter, grep, invert_match, json_only, non_json_only, ignore_case, whole_line, context, before, after, limit, limit_per_stream, sample, sample_n, seed, newest: _, oldest, follow, concurrency, checkpoint, resume, raw, strip_ansi, template, unmask, pager, no_pager, formatted, format_hint, flatten_depth, compact, lambda, emf, max_width, col_width, pin, min_frequency, fields_from_filter, time_field, show_cost, show_delay, lag_threshold, no_truncate, max_line_length, hexdump, wrap: _, no_wrap, highlight: _, no_highlight, yes, save, no_level_color, fields, output_file, dedup, dedup_strict } = *args;
+                let log_group = log_group.ok_or_else(|| missing_log_group(&config.defaults))?;
+                if let Some(name) = save.filter(|_| !cli.dry_run) {
+                    if !allow_empty_filter {
+                        utils::filter::check_not_empty("--filter", &filter, "events")?;
+                    }
+                    let spec = utils::filter::FilterSpec::parse(&filter)?;
+                    if spec.is_local() {
+                        bail!(error::CwlError::InvalidArgument(
+                            "These --filter patterns are matched locally and can't be saved as one pattern".to_string()
+                        ));
+                    }
+                    config.queries.insert(name.clone(), config::SavedQuery {
+                        log_group: log_group.clone(),
+                        filter: spec.server,
+                        since: since.clone(),
+                        limit,
+                        formatted,
+                        output: cli.output,
+                    });
+                    config.save()?;
+                    eprintln!("Saved query '{}'", name);
+                }
+
+                let template = template.or_else(|| {
+                    (!raw && !formatted && fields.is_empty()).then(|| config.defaults.default_template.clone()).flatten()
+                });
+
+                commands::query::run(aws_client.with_unmask(unmask), commands::query::QueryOptions {
+                    log_group,
+                    group_pattern,
+                    since,
+                    start,
+                    end,
+                    default_since: config.defaults.default_since.clone(),
+                    filters: filter,
+                    conditions,
+                    allow_empty_filter,
+                    grep,
+                    invert_match,
+                    json_select: utils::filter::JsonSelect::from_flags(json_only, non_json_only),
+                    before: before.or(context).unwrap_or(0),
+                    after: after.or(context).unwrap_or(0),
+                    regex_options: utils::format::RegexOptions { ignore_case, whole_line },
+                    // A checkpointed query always runs to the end of its range
+                    limit: match checkpoint.is_some() || resume.is_some() {
+                        true => usize::MAX,
+                        false => config.defaults.event_limit(limit, usize::MAX),
+                    },
+                    limit_per_stream,
+                    sampling: sample.map(utils::sample::Sampling::Rate)
+                        .or(sample_n.map(utils::sample::Sampling::Count)),
+                    seed,
+                    concurrency,
+                    oldest,
+                    formatted,
+                    format_hint,
+                    flatten_depth: if compact { Some(1) } else { flatten_depth },
+                    lambda,
+                    emf,
+                    max_width,
+                    col_width,
+                    pin,
+                    min_frequency,
+                    fields_from_filter,
+                    time_field,
+                    show_delay,
+                    lag_threshold,
+                    no_truncate,
+                    max_line_length,
+                    hexdump,
+                    no_wrap,
+                    highlight: !no_highlight,
+                    highlight_style,
+                    no_level_color,
+                    fields,
+                    output_file,
+                    dedup,
+                    dedup_strict,
+                    warn_days: Some(config.defaults.warn_days),
+                    show_cost,
+                    cost_per_gb: config.defaults.cost_per_gb,
+                    yes,
+                    tz,
+                    time_format,
+                    output,
+                    raw,
+                    strip_ansi,
+                    template,
+                    pager: pager || (config.defaults.use_pager && !no_pager),
+                    follow,
+                    checkpoint,
+                    resume_checkpoint: resume,
+                    progress: cli.progress,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            // Handled above, before the client is created
+            Commands::Format { .. } => bail!("The format command doesn't take an AWS client"),
+            Commands::Histogram { log_group, interval, since, start, end, filter } => {
+                commands::histogram::run(aws_client, commands::histogram::HistogramOptions {
+                    log_group,
+                    interval,
+                    since,
+                    start,
+                    end,
+                    default_since: config.defaults.default_since.clone(),
+                    filter,
+                    tz,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            Commands::Trace { log_group, key, id, since, start, end, filter, limit } => {
+                commands::trace::run(aws_client, commands::trace::TraceOptions {
+                    log_group,
+                    key,
+                    id,
+                    since,
+                    start,
+                    end,
+                    default_since: config.defaults.default_since.clone(),
+                    filter,
+                    limit,
+                    tz,
+                    time_format,
+                    progress: cli.progress,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            Commands::Schema { log_group, sample_size, since, start, end, filter, flatten_depth } => {
+                commands::schema::run(aws_client, commands::schema::SchemaOptions {
+                    log_group,
+                    sample_size,
+                    since,
+                    start,
+                    end,
+                    default_since: config.defaults.default_since.clone(),
+                    filter,
+                    flatten_depth,
+                    tz,
+                    output,
+                    progress: cli.progress,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            #[cfg(feature = "tui")]
+            Commands::Browse { log_group, since, start, end, filter, limit } => {
+                commands::browse::run(aws_client, commands::browse::BrowseOptions {
+                    log_group,
+                    since,
+                    start,
+                    end,
+                    default_since: config.defaults.default_since.clone(),
+                    filter,
+                    limit,
+                    tz,
+                    time_format,
+                    progress: cli.progress,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            #[cfg(not(feature = "tui"))]
+            Commands::Browse { .. } => {
+                bail!(error::CwlError::InvalidArgument(
+                    "This build of cwl has no browse UI; rebuild it with `--features tui`".to_string()
+                ));
+            },
+            Commands::Diff { log_group, window_a, window_b, filter, limit, all } => {
+                commands::diff::run(aws_client, commands::diff::DiffOptions {
+                    log_group,
+                    window_a,
+                    window_b,
+                    filter,
+                    limit,
+                    all,
+                    tz,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            Commands::Export { log_group, path, since, start, end, filter, split_by, gzip, compression_level } => {
+                commands::export::run(aws_client, commands::export::ExportOptions {
+                    log_group,
+                    path,
+                    since,
+                    start,
+                    end,
+                    default_since: config.defaults.default_since.clone(),
+                    filter,
+                    split_by,
+                    gzip,
+                    compression_level,
+                    tz,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            Commands::Run { name, since, filter, limit, formatted, yes } => {
+                let saved = config.queries.get(&name)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("No saved query named '{}' in the config file", name))?;
+                let formatted = formatted || saved.formatted;
+
+                commands::query::run(aws_client, commands::query::QueryOptions {
+                    log_group: saved.log_group,
+                    since: since.or(saved.since),
+                    default_since: config.defaults.default_since.clone(),
+                    filters: filter.or(saved.filter).into_iter().collect(),
+                    limit: config.defaults.event_limit(limit.or(saved.limit), usize::MAX),
+                    formatted,
+                    flatten_depth: None,
+                    max_line_length: utils::format::DEFAULT_MAX_LINE_LENGTH,
+                    warn_days: Some(config.defaults.warn_days),
+                    yes,
+                    tz,
+                    time_format,
+                    highlight: true,
+                    highlight_style,
+                    output,
+                    template: config.defaults.default_template.clone().filter(|_| !formatted),
+                    pager: config.defaults.use_pager,
+                    progress: cli.progress,
+                    quiet: cli.quiet,
+                    ..Default::default()
+                }).await?;
+            },
+            Commands::Describe { log_group, limit, active } => {
+                commands::describe::run(aws_client, log_group, limit, active, cli.quiet).await?;
+            },
+            Commands::Streams { log_group, prefix, order_by_last_event, limit } => {
+                commands::streams::run(aws_client, commands::streams::StreamsOptions {
+                    log_group,
+                    prefix,
+                    order_by_last_event,
+                    limit,
+                    tz,
+                    output,
+                    progress: cli.progress,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            Commands::PutRetention { log_group, all_matching, days, yes } => {
+                commands::retention::run(aws_client, log_group, all_matching, days, yes, cli.quiet).await?;
+            },
+            Commands::Put { log_group, stream, message, timestamp } => {
+                commands::put::run(aws_client, commands::put::PutOptions {
+                    log_group,
+                    stream,
+                    message,
+                    timestamp,
+                    tz,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+            Commands::Groups { filter, allow_empty_filter, fuzzy, sort, limit, inactive, empty, count, details, with_metric_filters } => {
+                commands::groups::run(aws_client, commands::groups::GroupsOptions {
+                    filter,
+                    allow_empty_filter,
+                    fuzzy,
+                    sort,
+                    limit,
+                    inactive,
+                    empty,
+                    count,
+                    details,
+                    metric_filters: with_metric_filters,
+                    output,
+                    progress: cli.progress,
+                    quiet: cli.quiet,
+                }).await?;
+            },
+        }
+
+        Ok::<_, anyhow::Error>(())
+    };
+
+    // Fetches stop early at the deadline and print what they have; this is
+    // only a backstop for everything else, so it leaves them time to do that
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout + TIMEOUT_GRACE, command).await
+            .map_err(|_| error::CwlError::timeout(timeout))?,
+        None => command.await,
+    }
 }
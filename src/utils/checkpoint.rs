@@ -0,0 +1,249 @@
+use anyhow::{Result, Context, bail};
+use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
+use crate::error::CwlError;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How long CloudWatch accepts a `FilterLogEvents` `nextToken` (24 hours).
+/// Older checkpoints carry on from the newest saved event instead.
+pub const TOKEN_VALIDITY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// The query a checkpoint was taken for. Resuming reuses its time range, so
+/// relative times like `--since 2h` don't drift.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointQuery {
+    pub log_group: String,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub filter_pattern: Option<String>,
+}
+
+/// One line of a checkpoint file. The file is JSON lines: the query, then
+/// each fetched page as its events followed by a `page` record holding the
+/// token of the next page. Events after the last `page` record belong to a
+/// page that was cut off and are dropped on load.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Query(CheckpointQuery),
+    Event {
+        timestamp: Option<i64>,
+        ingestion_time: Option<i64>,
+        log_stream_name: Option<String>,
+        message: Option<String>,
+        event_id: Option<String>,
+    },
+    Page {
+        next_token: Option<String>,
+        saved_at: i64,
+    },
+}
+
+/// A checkpoint read back for `--resume`.
+pub struct Saved {
+    pub query: CheckpointQuery,
+    pub events: Vec<FilteredLogEvent>,
+    pub next_token: Option<String>,
+    /// When the last complete page was saved; `None` if none was
+    pub saved_at: Option<i64>,
+    /// Bytes up to the end of the last complete page
+    valid_len: u64,
+}
+
+/// Where a resumed fetch carries on.
+#[derive(Debug, PartialEq)]
+pub enum ResumePoint {
+    /// The last page was already fetched
+    Done,
+    /// The saved token is still valid
+    Token(String),
+    /// Fetch again from this time, skipping events already saved
+    From(Option<i64>),
+}
+
+impl Saved {
+    pub fn resume_point(&self, now: i64) -> ResumePoint {
+        match (&self.next_token, self.saved_at) {
+            (None, Some(_)) => ResumePoint::Done,
+            (Some(token), Some(saved_at)) if now - saved_at < TOKEN_VALIDITY_MILLIS => ResumePoint::Token(token.clone()),
+            // Events across streams aren't in time order, so the latest may not be the last
+            _ => ResumePoint::From(self.events.iter().filter_map(|event| event.timestamp).max().or(self.query.start_time)),
+        }
+    }
+}
+
+/// Reads a checkpoint written by `Checkpoint`.
+pub fn load(path: &Path) -> Result<Saved> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open checkpoint: {}", path.display()))?;
+
+    let mut query = None;
+    let mut events = Vec::new();
+    let mut page = Vec::new();
+    let mut next_token = None;
+    let mut saved_at = None;
+    let mut read = 0;
+    let mut valid_len = 0;
+
+    for line in BufReader::new(file).split(b'\n') {
+        let line = line.with_context(|| format!("Failed to read checkpoint: {}", path.display()))?;
+        read += line.len() as u64 + 1;
+        // A record cut off mid-write ends the usable part of the file
+        let Ok(record) = serde_json::from_slice::<Record>(&line) else {
+            break;
+        };
+        match record {
+            Record::Query(saved) if query.is_none() => {
+                query = Some(saved);
+                valid_len = read;
+            }
+            Record::Query(_) => break,
+            Record::Event { timestamp, ingestion_time, log_stream_name, message, event_id } => {
+                page.push(FilteredLogEvent::builder()
+                    .set_timestamp(timestamp)
+                    .set_ingestion_time(ingestion_time)
+                    .set_log_stream_name(log_stream_name)
+                    .set_message(message)
+                    .set_event_id(event_id)
+                    .build());
+            }
+            Record::Page { next_token: token, saved_at: at } => {
+                events.append(&mut page);
+                next_token = token;
+                saved_at = Some(at);
+                valid_len = read;
+            }
+        }
+    }
+
+    let Some(query) = query else {
+        bail!(CwlError::InvalidArgument(format!("{} is not a cwl checkpoint", path.display())));
+    };
+
+    Ok(Saved { query, events, next_token, saved_at, valid_len })
+}
+
+/// Appends each fetched page to a checkpoint file so an interrupted query can
+/// be resumed with `load`.
+pub struct Checkpoint {
+    file: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    /// Starts a new checkpoint. An existing one is never overwritten, since
+    /// it may hold hours of progress.
+    pub fn create(path: &Path, query: &CheckpointQuery) -> Result<Self> {
+        if path.metadata().is_ok_and(|metadata| metadata.len() > 0) {
+            bail!(CwlError::InvalidArgument(format!(
+                "Checkpoint {} already exists; continue it with --resume or delete it",
+                path.display()
+            )));
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create checkpoint: {}", path.display()))?;
+        let mut checkpoint = Self { file: BufWriter::new(file), path: path.to_path_buf() };
+        checkpoint.write(&Record::Query(query.clone()))?;
+        checkpoint.flush()?;
+        Ok(checkpoint)
+    }
+
+    /// Continues writing to the file `saved` was loaded from, dropping any
+    /// partly written page first.
+    pub fn reopen(path: &Path, saved: &Saved) -> Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open checkpoint: {}", path.display()))?;
+        file.set_len(saved.valid_len)
+            .with_context(|| format!("Failed to truncate checkpoint: {}", path.display()))?;
+        Ok(Self { file: BufWriter::new(file), path: path.to_path_buf() })
+    }
+
+    pub fn save_page(&mut self, events: &[FilteredLogEvent], next_token: Option<&str>, saved_at: i64) -> Result<()> {
+        for event in events {
+            self.write(&Record::Event {
+                timestamp: event.timestamp,
+                ingestion_time: event.ingestion_time,
+                log_stream_name: event.log_stream_name.clone(),
+                message: event.message.clone(),
+                event_id: event.event_id.clone(),
+            })?;
+        }
+        self.write(&Record::Page { next_token: next_token.map(str::to_string), saved_at })?;
+        self.flush()
+    }
+
+    /// Deletes the checkpoint once the query has completed.
+    pub fn remove(self) -> Result<()> {
+        let Self { file, path } = self;
+        drop(file);
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove checkpoint: {}", path.display()))
+    }
+
+    fn write(&mut self, record: &Record) -> Result<()> {
+        serde_json::to_writer(&mut self.file, record)?;
+        writeln!(self.file)
+            .with_context(|| format!("Failed to write checkpoint: {}", self.path.display()))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+            .with_context(|| format!("Failed to write checkpoint: {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip_drops_partial_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.jsonl");
+        let query = CheckpointQuery {
+            log_group: "app".to_string(),
+            start_time: Some(1_000),
+            end_time: Some(9_000),
+            filter_pattern: None,
+        };
+        let event = |id: &str, timestamp| FilteredLogEvent::builder()
+            .event_id(id)
+            .timestamp(timestamp)
+            .message(format!("event {}", id))
+            .build();
+
+        let mut checkpoint = Checkpoint::create(&path, &query).unwrap();
+        // Pages interleave streams, so events needn't be in time order
+        checkpoint.save_page(&[event("a", 2_000), event("b", 1_000)], Some("token-2"), 50_000).unwrap();
+        drop(checkpoint);
+        assert!(Checkpoint::create(&path, &query).is_err());
+
+        // A crash mid-page leaves events without their `page` record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        writeln!(file, r#"{{"type":"event","timestamp":3000,"ingestion_time":null,"log_stream_name":null,"message":"c","event_id":"c"}}"#).unwrap();
+        write!(file, r#"{{"type":"eve"#).unwrap();
+        drop(file);
+
+        let saved = load(&path).unwrap();
+        assert_eq!(saved.query, query);
+        assert_eq!(saved.events.len(), 2);
+        assert_eq!(saved.resume_point(60_000), ResumePoint::Token("token-2".to_string()));
+        assert_eq!(saved.resume_point(50_000 + TOKEN_VALIDITY_MILLIS), ResumePoint::From(Some(2_000)));
+
+        let mut checkpoint = Checkpoint::reopen(&path, &saved).unwrap();
+        checkpoint.save_page(&[event("c", 3_000)], None, 70_000).unwrap();
+        drop(checkpoint);
+
+        let saved = load(&path).unwrap();
+        let ids: Vec<&str> = saved.events.iter().filter_map(|event| event.event_id.as_deref()).collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+        assert_eq!(saved.resume_point(80_000), ResumePoint::Done);
+
+        Checkpoint::reopen(&path, &saved).unwrap().remove().unwrap();
+        assert!(!path.exists());
+    }
+}
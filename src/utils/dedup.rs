@@ -0,0 +1,117 @@
+use colored::Colorize;
+use crate::utils::tee::TeeWriter;
+
+/// Collapses runs of consecutive identical messages into a single line with a
+/// trailing `(xN)` count, like `uniq -c`.
+///
+/// In buffered mode a line is held back until the run ends. In live mode
+/// (follow) the first line of a run is printed immediately; repeats either
+/// rewrite that line in place when stdout is a terminal, or produce a
+/// summarized line once the run ends. Rewritten runs reach the output file
+/// once, as their final counted line.
+pub struct Deduper {
+    strict: bool,
+    live: bool,
+    in_place: bool,
+    last_key: Option<String>,
+    last_line: String,
+    count: usize,
+}
+
+impl Deduper {
+    pub fn new(strict: bool, live: bool) -> Self {
+        use std::io::IsTerminal;
+
+        Self {
+            strict,
+            live,
+            in_place: live && std::io::stdout().is_terminal(),
+            last_key: None,
+            last_line: String::new(),
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, message: &str, line: String, out: &mut TeeWriter) {
+        let key = dedup_key(message, self.strict);
+
+        if self.last_key.as_deref() == Some(key.as_str()) {
+            self.count += 1;
+            if self.in_place {
+                out.rewrite_last_line(&with_count(&self.last_line, self.count));
+            }
+            return;
+        }
+
+        self.finish(out);
+
+        if self.in_place {
+            out.print_line(&line);
+        } else if self.live {
+            out.write_line(&line);
+        }
+        self.last_key = Some(key);
+        self.last_line = line;
+        self.count = 1;
+    }
+
+    pub fn finish(&mut self, out: &mut TeeWriter) {
+        if self.last_key.take().is_none() {
+            return;
+        }
+
+        let line = if self.count > 1 {
+            with_count(&self.last_line, self.count)
+        } else {
+            std::mem::take(&mut self.last_line)
+        };
+
+        if self.in_place {
+            out.write_file_line(&line);
+        } else if !self.live || self.count > 1 {
+            out.write_line(&line);
+        }
+
+        self.count = 0;
+    }
+}
+
+fn with_count(line: &str, count: usize) -> String {
+    format!("{} {}", line, format!("(x{})", count).bright_black())
+}
+
+/// Messages that differ only in whitespace are considered identical unless
+/// `strict` is set.
+pub fn dedup_key(message: &str, strict: bool) -> String {
+    if strict {
+        message.to_string()
+    } else {
+        message.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_key_whitespace() {
+        assert_eq!(dedup_key("  retrying   request\n", false), dedup_key("retrying request", false));
+        assert_ne!(dedup_key("  retrying   request\n", true), dedup_key("retrying request", true));
+    }
+
+    #[test]
+    fn test_in_place_runs_reach_output_file_once() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut out = TeeWriter::new(Some(file.path()), true).unwrap();
+        let mut deduper = Deduper { in_place: true, ..Deduper::new(false, true) };
+
+        for message in ["retrying", "retrying", "retrying", "connected"] {
+            deduper.push(message, message.to_string(), &mut out);
+        }
+        deduper.finish(&mut out);
+
+        let written = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), ["retrying (x3)", "connected"]);
+    }
+}
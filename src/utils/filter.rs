@@ -0,0 +1,497 @@
+use regex::Regex;
+use std::sync::OnceLock;
+use crate::error::CwlError;
+use crate::utils::format::{self, HighlightStyle, RegexOptions};
+
+/// The kinds of CloudWatch filter pattern syntax a `--filter` can use.
+#[derive(Debug, Clone, PartialEq)]
+enum PatternKind {
+    /// Unstructured terms such as `ERROR`, `"connection reset"` or `ERROR -DEBUG`
+    Terms(Vec<Term>),
+    /// `{ $.level = "ERROR" }`
+    Json,
+    /// `[ip, user, ...]`
+    SpaceDelimited,
+    /// `%regex%`
+    Regex,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Required(String),
+    Optional(String),
+    Excluded(String),
+}
+
+/// Several `--filter` patterns OR-combined. Simple terms are sent as one
+/// `?"a" ?"b"` pattern and JSON patterns as one `{ (a) || (b) }` pattern.
+/// Term expressions that can't be OR-ed in CloudWatch syntax (e.g.
+/// `ERROR -DEBUG`) are matched locally instead, which fetches every event in
+/// the range. Other combinations are rejected.
+#[derive(Debug, Clone, Default)]
+pub struct FilterSpec {
+    /// Pattern sent to FilterLogEvents
+    pub server: Option<String>,
+    /// Term expressions an event must match at least one of, checked locally
+    local: Vec<Vec<Term>>,
+    /// The text highlighted in output
+    highlight: Vec<String>,
+}
+
+impl FilterSpec {
+    pub fn parse(filters: &[String]) -> Result<Self, CwlError> {
+        match filters {
+            [] => return Ok(Self::default()),
+            [filter] => {
+                return Ok(Self {
+                    server: Some(filter.clone()),
+                    local: Vec::new(),
+                    highlight: vec![filter.clone()],
+                });
+            }
+            _ => {}
+        }
+
+        let kinds: Vec<PatternKind> = filters.iter().map(|f| classify(f)).collect();
+
+        if kinds.iter().all(|kind| matches!(kind, PatternKind::Json)) {
+            let conditions: Vec<String> = filters.iter()
+                .map(|f| {
+                    let inner = f.trim().trim_start_matches('{').trim_end_matches('}').trim();
+                    format!("({})", inner)
+                })
+                .collect();
+            return Ok(Self {
+                server: Some(format!("{{ {} }}", conditions.join(" || "))),
+                local: Vec::new(),
+                highlight: Vec::new(),
+            });
+        }
+
+        let term_lists: Vec<Vec<Term>> = kinds.into_iter()
+            .map(|kind| match kind {
+                PatternKind::Terms(terms) => Ok(terms),
+                _ => Err(CwlError::InvalidArgument(
+                    "Repeated --filter patterns must all be terms or all be JSON patterns; \
+                     space-delimited ([...]) and regex (%...%) patterns can't be combined"
+                        .to_string(),
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let highlight = positive_terms(&term_lists);
+
+        let simple: Option<Vec<&str>> = term_lists.iter()
+            .map(|terms| match terms.as_slice() {
+                [Term::Required(text)] | [Term::Optional(text)] => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        Ok(match simple {
+            Some(texts) => Self {
+                server: Some(texts.iter().map(|text| format!("?\"{}\"", text)).collect::<Vec<_>>().join(" ")),
+                local: Vec::new(),
+                highlight,
+            },
+            None => Self {
+                server: None,
+                local: term_lists,
+                highlight,
+            },
+        })
+    }
+
+    /// Parses `filters` to be matched entirely locally, for when every event
+    /// has to be fetched anyway (e.g. to show context around matches). Only
+    /// term patterns can be evaluated locally.
+    pub fn parse_local(filters: &[String]) -> Result<Self, CwlError> {
+        let local: Vec<Vec<Term>> = filters.iter()
+            .map(|filter| match classify(filter) {
+                PatternKind::Terms(terms) => Ok(terms),
+                _ => Err(CwlError::InvalidArgument(format!(
+                    "Filter '{}' can't be matched locally; only term patterns work here",
+                    filter
+                ))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let highlight = match filters {
+            [filter] => vec![filter.clone()],
+            _ => positive_terms(&local),
+        };
+
+        Ok(Self {
+            server: None,
+            local,
+            highlight,
+        })
+    }
+
+    /// Whether some patterns are checked locally rather than by CloudWatch.
+    pub fn is_local(&self) -> bool {
+        !self.local.is_empty()
+    }
+
+    /// Applies the locally checked patterns. Always true when everything was
+    /// sent to CloudWatch.
+    pub fn matches(&self, message: &str) -> bool {
+        self.local.is_empty() || self.local.iter().any(|terms| terms_match(terms, message))
+    }
+
+    /// A regex highlighting any of the filtered terms and the `--grep`
+    /// pattern, if there are any. An inverted grep leaves nothing to
+    /// highlight.
+    pub fn highlight_pattern(&self, grep: Option<&Grep>, options: &RegexOptions) -> Result<Option<Regex>, regex::Error> {
+        if grep.is_some_and(Grep::is_inverted) {
+            return Ok(None);
+        }
+        let mut alternation: Vec<String> = self.highlight.iter().map(|text| regex::escape(text)).collect();
+        alternation.extend(grep.map(|grep| format!("(?:{})", grep.source)));
+        match alternation.as_slice() {
+            [] => Ok(None),
+            _ => options.build(&alternation.join("|")).map(Some),
+        }
+    }
+}
+
+/// A client-side `--grep` regex, applied to messages after any `--filter` has
+/// narrowed them server-side. `--invert-match` keeps the messages it doesn't
+/// match instead.
+#[derive(Debug, Clone)]
+pub struct Grep {
+    pattern: Regex,
+    /// The pattern as given, for combining with other highlights
+    source: String,
+    invert: bool,
+}
+
+impl Grep {
+    pub fn new(pattern: &str, options: &RegexOptions, invert: bool) -> Result<Self, CwlError> {
+        let source = pattern.to_string();
+        let pattern = options.build(pattern)
+            .map_err(|e| CwlError::InvalidArgument(format!("Invalid --grep pattern '{}': {}", pattern, e)))?;
+        Ok(Self { pattern, source, invert })
+    }
+
+    pub fn matches(&self, message: &str) -> bool {
+        self.pattern.is_match(message) != self.invert
+    }
+
+    /// Marks the matches in `text`. Inverted matches have nothing to mark.
+    pub fn highlight(&self, text: &str, style: HighlightStyle) -> String {
+        match self.invert {
+            true => text.to_string(),
+            false => format::highlight_matches(text, &self.pattern, style),
+        }
+    }
+
+    pub fn is_inverted(&self) -> bool {
+        self.invert
+    }
+}
+
+/// Keeps only structured (`--json-only`) or only unstructured
+/// (`--non-json-only`) messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonSelect {
+    Json,
+    NonJson,
+}
+
+impl JsonSelect {
+    pub fn from_flags(json_only: bool, non_json_only: bool) -> Option<Self> {
+        match (json_only, non_json_only) {
+            (true, _) => Some(Self::Json),
+            (_, true) => Some(Self::NonJson),
+            _ => None,
+        }
+    }
+
+    pub fn matches(self, message: &str) -> bool {
+        is_json_object(message) == (self == Self::Json)
+    }
+}
+
+/// Whether the whole message (ignoring surrounding whitespace) is a JSON object.
+pub fn is_json_object(message: &str) -> bool {
+    let message = message.trim();
+    message.starts_with('{')
+        && serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(message).is_ok()
+}
+
+/// Rejects empty or whitespace-only patterns, which match all `what` (e.g.
+/// "events") and usually come from a shell expansion gone wrong.
+pub fn check_not_empty<'a>(flag: &str, patterns: impl IntoIterator<Item = &'a String>, what: &str) -> Result<(), CwlError> {
+    if patterns.into_iter().any(|pattern| pattern.trim().is_empty()) {
+        return Err(CwlError::InvalidArgument(format!(
+            "Empty {} would match all {}; omit the flag or pass a real pattern (or use --allow-empty-filter)",
+            flag, what
+        )));
+    }
+    Ok(())
+}
+
+/// Compiles `--where` conditions such as `level=ERROR` or `status>=500`
+/// into one CloudWatch JSON filter pattern, AND-ing them together. Values
+/// that parse as numbers are compared as numbers unless quoted; `true`,
+/// `false` and `null` become `IS TRUE`, `IS FALSE` and `IS NULL`.
+pub fn where_pattern(conditions: &[String]) -> Result<String, CwlError> {
+    let clauses = conditions.iter()
+        .map(|condition| where_clause(condition)
+            .map_err(|reason| CwlError::InvalidArgument(format!("Invalid --where '{}': {}", condition, reason))))
+        .collect::<Result<Vec<String>, CwlError>>()?;
+
+    Ok(match clauses.as_slice() {
+        [clause] => format!("{{ {} }}", clause),
+        _ => format!("{{ {} }}", clauses.iter().map(|clause| format!("({})", clause)).collect::<Vec<_>>().join(" && ")),
+    })
+}
+
+fn where_clause(condition: &str) -> Result<String, String> {
+    let at = condition.find(['=', '!', '<', '>'])
+        .ok_or("expected FIELD=VALUE, or one of != > >= < <= instead of =")?;
+    let (field, rest) = condition.split_at(at);
+    let op = ["!=", ">=", "<=", "=", ">", "<"].into_iter()
+        .find(|op| rest.starts_with(op))
+        .ok_or("expected = or != after the field")?;
+    let value = rest[op.len()..].trim();
+
+    let field = field.trim();
+    let field = field.strip_prefix("$.").unwrap_or(field);
+    if field.is_empty() || field.contains(|c: char| c.is_whitespace() || "\"'{}()&|$".contains(c)) {
+        return Err(format!("'{}' isn't a field name like level or user.id", field));
+    }
+
+    if let Some(text) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        return string_clause(field, op, text);
+    }
+    if value.parse::<f64>().is_ok_and(f64::is_finite) {
+        return Ok(format!("$.{} {} {}", field, op, value));
+    }
+    match (value, op) {
+        ("true" | "false" | "null", "=") => Ok(format!("$.{} IS {}", field, value.to_uppercase())),
+        ("true" | "false" | "null", _) => Err(format!("{} can only be compared with =", value)),
+        _ => string_clause(field, op, value),
+    }
+}
+
+fn string_clause(field: &str, op: &str, text: &str) -> Result<String, String> {
+    if !matches!(op, "=" | "!=") {
+        return Err(format!("{} compares numbers, and '{}' isn't one", op, text));
+    }
+    if text.contains('"') {
+        return Err("values can't contain double quotes".to_string());
+    }
+    Ok(format!("$.{} {} \"{}\"", field, op, text))
+}
+
+fn field_reference_pattern() -> &'static Regex {
+    static FIELD_REFERENCE_PATTERN: OnceLock<Regex> = OnceLock::new();
+    FIELD_REFERENCE_PATTERN.get_or_init(|| {
+        Regex::new(r#"\$\.([\w.\[\]]*\w\]?)|"([\w.]+)"\s*:|\b([A-Za-z_][\w.]*)="#).unwrap()
+    })
+}
+
+/// The field names a filter or grep pattern refers to, as table column names:
+/// `$.user.id` in JSON patterns, `"key":` quoted keys and `key=` pairs.
+pub fn referenced_fields(pattern: &str) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    for captures in field_reference_pattern().captures_iter(pattern) {
+        if let Some(field) = captures.iter().skip(1).flatten().next() {
+            if !fields.iter().any(|known| known == field.as_str()) {
+                fields.push(field.as_str().to_string());
+            }
+        }
+    }
+    fields
+}
+
+fn classify(filter: &str) -> PatternKind {
+    let filter = filter.trim();
+    if filter.starts_with('{') {
+        PatternKind::Json
+    } else if filter.starts_with('[') {
+        PatternKind::SpaceDelimited
+    } else if filter.starts_with('%') {
+        PatternKind::Regex
+    } else {
+        PatternKind::Terms(parse_terms(filter))
+    }
+}
+
+/// Splits a term expression into terms, keeping `"quoted phrases"` whole and
+/// reading `?` and `-` prefixes.
+fn parse_terms(filter: &str) -> Vec<Term> {
+    let mut terms = Vec::new();
+    let mut chars = filter.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let prefix = match c {
+            '?' | '-' => chars.next(),
+            _ => None,
+        };
+
+        let text: String = if chars.peek() == Some(&'"') {
+            chars.next();
+            chars.by_ref().take_while(|&c| c != '"').collect()
+        } else {
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+            text
+        };
+
+        if text.is_empty() {
+            continue;
+        }
+
+        terms.push(match prefix {
+            Some('?') => Term::Optional(text),
+            Some('-') => Term::Excluded(text),
+            _ => Term::Required(text),
+        });
+    }
+
+    terms
+}
+
+/// The terms worth highlighting: everything but exclusions.
+fn positive_terms(term_lists: &[Vec<Term>]) -> Vec<String> {
+    term_lists.iter()
+        .flatten()
+        .filter_map(|term| match term {
+            Term::Required(text) | Term::Optional(text) => Some(text.clone()),
+            Term::Excluded(_) => None,
+        })
+        .collect()
+}
+
+/// CloudWatch term semantics: every required term must appear, at least one
+/// `?` term (if any) must appear, and no `-` term may appear. Matching is
+/// case-sensitive.
+fn terms_match(terms: &[Term], message: &str) -> bool {
+    let mut any_optional = false;
+    let mut optional_matched = false;
+
+    for term in terms {
+        match term {
+            Term::Required(text) if !message.contains(text.as_str()) => return false,
+            Term::Excluded(text) if message.contains(text.as_str()) => return false,
+            Term::Optional(text) => {
+                any_optional = true;
+                optional_matched |= message.contains(text.as_str());
+            }
+            _ => {}
+        }
+    }
+
+    !any_optional || optional_matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_simple_terms_combine_server_side() {
+        let spec = FilterSpec::parse(&filters(&["error", "\"timed out\"", "?warn"])).unwrap();
+        assert_eq!(spec.server.as_deref(), Some(r#"?"error" ?"timed out" ?"warn""#));
+        assert!(!spec.is_local());
+
+        let spec = FilterSpec::parse(&filters(&["{ $.level = \"ERROR\" }", "{$.status >= 500}"])).unwrap();
+        assert_eq!(spec.server.as_deref(), Some(r#"{ ($.level = "ERROR") || ($.status >= 500) }"#));
+    }
+
+    #[test]
+    fn test_complex_terms_match_locally() {
+        let spec = FilterSpec::parse(&filters(&["ERROR -healthcheck", "timeout"])).unwrap();
+        assert_eq!(spec.server, None);
+        assert!(spec.matches("ERROR db down"));
+        assert!(!spec.matches("ERROR healthcheck failed"));
+        assert!(spec.matches("request timeout"));
+        assert!(!spec.matches("INFO ok"));
+    }
+
+    #[test]
+    fn test_mixed_pattern_types_are_rejected() {
+        assert!(FilterSpec::parse(&filters(&["ERROR", "{ $.level = \"ERROR\" }"])).is_err());
+        assert!(FilterSpec::parse(&filters(&["[ip, user]", "[ip, user, status]"])).is_err());
+        assert!(FilterSpec::parse(&filters(&["%err%", "warn"])).is_err());
+    }
+
+    #[test]
+    fn test_inverted_grep_excludes_matches() {
+        let messages = ["GET /health 200", "GET /orders 500", "POST /orders 201"];
+        let grep = Grep::new("health", &RegexOptions::default(), false).unwrap();
+        let inverted = Grep::new("health", &RegexOptions::default(), true).unwrap();
+
+        let kept: Vec<&str> = messages.iter().copied().filter(|m| inverted.matches(m)).collect();
+        assert_eq!(kept, vec!["GET /orders 500", "POST /orders 201"]);
+        assert!(messages.iter().all(|m| grep.matches(m) != inverted.matches(m)));
+        assert_eq!(inverted.highlight(messages[0], HighlightStyle::default()), messages[0]);
+
+        let spec = FilterSpec::parse(&filters(&["GET"])).unwrap();
+        let highlight = spec.highlight_pattern(Some(&grep), &RegexOptions::default()).unwrap().unwrap();
+        assert_eq!(highlight.find_iter(messages[0]).map(|m| m.as_str()).collect::<Vec<_>>(), ["GET", "health"]);
+        assert!(spec.highlight_pattern(Some(&inverted), &RegexOptions::default()).unwrap().is_none());
+        assert!(Grep::new("(", &RegexOptions::default(), true).is_err());
+    }
+
+    #[test]
+    fn test_where_pattern() {
+        assert_eq!(where_pattern(&filters(&["level=ERROR"])).unwrap(), r#"{ $.level = "ERROR" }"#);
+        assert_eq!(
+            where_pattern(&filters(&["level!=DEBUG", "status>=500", "$.user.id=\"42\"", "retry=true"])).unwrap(),
+            r#"{ ($.level != "DEBUG") && ($.status >= 500) && ($.user.id = "42") && ($.retry IS TRUE) }"#
+        );
+        assert_eq!(where_pattern(&filters(&["latency < 0.5"])).unwrap(), "{ $.latency < 0.5 }");
+        assert!(where_pattern(&filters(&["level>ERROR"])).is_err());
+        assert!(where_pattern(&filters(&["level"])).is_err());
+        assert!(where_pattern(&filters(&["=ERROR"])).is_err());
+        assert!(where_pattern(&filters(&["flag!=null"])).is_err());
+    }
+
+    #[test]
+    fn test_referenced_fields() {
+        assert_eq!(referenced_fields(r#"{ $.level = "ERROR" && $.user.id = 42 }"#), ["level", "user.id"]);
+        assert_eq!(referenced_fields(r#"{ $.items[0].sku = "x" }"#), ["items[0].sku"]);
+        assert_eq!(referenced_fields(r#""status":\s*5\d\d|level=error"#), ["status", "level"]);
+        assert!(referenced_fields("ERROR -healthcheck").is_empty());
+    }
+
+    #[test]
+    fn test_empty_patterns_are_rejected() {
+        assert!(check_not_empty("--filter", &filters(&["ERROR", "  "]), "events").is_err());
+        assert!(check_not_empty("--grep", &Some(String::new()), "events").is_err());
+        assert!(check_not_empty("--filter", &filters(&["ERROR"]), "events").is_ok());
+        assert!(check_not_empty("--grep", &None, "events").is_ok());
+    }
+
+    #[test]
+    fn test_json_select() {
+        let json = JsonSelect::from_flags(true, false).unwrap();
+        assert!(json.matches("  {\"level\":\"INFO\",\"msg\":\"ok\"}\n"));
+        assert!(!json.matches(r#"["not", "an", "object"]"#));
+        assert!(!json.matches("java.lang.NullPointerException: {oops}"));
+
+        let non_json = JsonSelect::from_flags(false, true).unwrap();
+        assert!(non_json.matches("    at com.example.Handler.handle(Handler.java:42)"));
+        assert!(!non_json.matches("{}"));
+        assert_eq!(JsonSelect::from_flags(false, false), None);
+    }
+}
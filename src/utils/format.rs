@@ -1,13 +1,176 @@
-use colored::Colorize;
-use regex::Regex;
+use colored::{Color, ColoredString, Colorize};
+use regex::{Regex, RegexBuilder};
+use crate::error::CwlError;
+use std::sync::OnceLock;
 
-pub fn highlight_matches(text: &str, pattern: &Regex) -> String {
+fn ansi_pattern() -> &'static Regex {
+    static ANSI_PATTERN: OnceLock<Regex> = OnceLock::new();
+    ANSI_PATTERN.get_or_init(|| Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]").unwrap())
+}
+
+/// Flags applied to every client-side regex (highlighting and local
+/// matching) so they behave consistently across commands.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RegexOptions {
+    pub ignore_case: bool,
+    /// Only match when the pattern spans a whole line of the message.
+    pub whole_line: bool,
+}
+
+impl RegexOptions {
+    pub fn build(&self, pattern: &str) -> Result<Regex, regex::Error> {
+        let pattern = if self.whole_line {
+            format!("^(?:{})$", pattern)
+        } else {
+            pattern.to_string()
+        };
+
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.ignore_case)
+            .multi_line(self.whole_line)
+            .build()
+    }
+
+    /// Builds a regex that matches `text` literally.
+    pub fn literal(&self, text: &str) -> Result<Regex, regex::Error> {
+        self.build(&regex::escape(text))
+    }
+}
+
+/// Matches `name` against a pattern where `*` stands for any run of
+/// characters.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let regex = pattern.split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(".*");
+
+    Regex::new(&format!("^{}$", regex)).is_ok_and(|re| re.is_match(name))
+}
+
+/// How `highlight_matches` marks matches (`--highlight-color`, or
+/// `highlight_style` in the config): `+`-separated names such as
+/// `on_red+white` or `bold+underline`. A color sets the foreground and
+/// `on_<color>` the background.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HighlightStyle {
+    foreground: Option<Color>,
+    background: Option<Color>,
+    bold: bool,
+    dimmed: bool,
+    italic: bool,
+    underline: bool,
+    reversed: bool,
+}
+
+impl HighlightStyle {
+    pub fn apply(&self, text: &str) -> ColoredString {
+        let mut styled = text.normal();
+        if let Some(color) = self.foreground {
+            styled = styled.color(color);
+        }
+        if let Some(color) = self.background {
+            styled = styled.on_color(color);
+        }
+        if self.bold {
+            styled = styled.bold();
+        }
+        if self.dimmed {
+            styled = styled.dimmed();
+        }
+        if self.italic {
+            styled = styled.italic();
+        }
+        if self.underline {
+            styled = styled.underline();
+        }
+        if self.reversed {
+            styled = styled.reversed();
+        }
+        styled
+    }
+}
+
+impl Default for HighlightStyle {
+    /// Black on yellow
+    fn default() -> Self {
+        Self {
+            foreground: Some(Color::Black),
+            background: Some(Color::Yellow),
+            bold: false,
+            dimmed: false,
+            italic: false,
+            underline: false,
+            reversed: false,
+        }
+    }
+}
+
+impl std::str::FromStr for HighlightStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Self {
+            foreground: None,
+            background: None,
+            ..Self::default()
+        };
+        for name in s.split('+').map(|name| name.trim().to_ascii_lowercase()) {
+            match name.as_str() {
+                "bold" => style.bold = true,
+                "dimmed" => style.dimmed = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reversed" => style.reversed = true,
+                _ => match name.strip_prefix("on_") {
+                    Some(color) => style.background = Some(parse_color(color).ok_or_else(|| unknown_style(&name))?),
+                    None => style.foreground = Some(parse_color(&name).ok_or_else(|| unknown_style(&name))?),
+                },
+            }
+        }
+        Ok(style)
+    }
+}
+
+const COLOR_NAMES: [(&str, Color); 16] = [
+    ("black", Color::Black),
+    ("red", Color::Red),
+    ("green", Color::Green),
+    ("yellow", Color::Yellow),
+    ("blue", Color::Blue),
+    ("magenta", Color::Magenta),
+    ("cyan", Color::Cyan),
+    ("white", Color::White),
+    ("bright_black", Color::BrightBlack),
+    ("bright_red", Color::BrightRed),
+    ("bright_green", Color::BrightGreen),
+    ("bright_yellow", Color::BrightYellow),
+    ("bright_blue", Color::BrightBlue),
+    ("bright_magenta", Color::BrightMagenta),
+    ("bright_cyan", Color::BrightCyan),
+    ("bright_white", Color::BrightWhite),
+];
+
+fn parse_color(name: &str) -> Option<Color> {
+    COLOR_NAMES.iter().find(|(known, _)| *known == name).map(|(_, color)| *color)
+}
+
+fn unknown_style(name: &str) -> String {
+    let colors: Vec<&str> = COLOR_NAMES.iter().map(|(name, _)| *name).collect();
+    format!(
+        "unknown highlight style '{}': use {}, on_<color>, bold, dimmed, italic, underline or reversed, joined with '+'",
+        name,
+        colors.join(", ")
+    )
+}
+
+pub fn highlight_matches(text: &str, pattern: &Regex, style: HighlightStyle) -> String {
     let mut result = String::new();
     let mut last_end = 0;
 
     for mat in pattern.find_iter(text) {
         result.push_str(&text[last_end..mat.start()]);
-        result.push_str(&text[mat.start()..mat.end()].on_yellow().black().to_string());
+        result.push_str(&style.apply(&text[mat.start()..mat.end()]).to_string());
         last_end = mat.end();
     }
 
@@ -15,6 +178,232 @@ pub fn highlight_matches(text: &str, pattern: &Regex) -> String {
     result
 }
 
+pub fn strip_ansi(text: &str) -> String {
+    ansi_pattern().replace_all(text, "").to_string()
+}
+
+/// Makes `text` safe to print: control characters that could move the
+/// cursor, ring the bell or change terminal modes become visible escapes
+/// such as `\x07`. Newlines, tabs, line-ending carriage returns and color
+/// (SGR) sequences are kept, so multi-line and colored messages still render.
+pub fn sanitize_for_terminal(text: &str) -> String {
+    let mut sanitized = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let keep = match c {
+            '\n' | '\t' => true,
+            '\r' => rest[1..].is_empty() || rest[1..].starts_with('\n'),
+            '\x1b' => {
+                let sgr = sgr_pattern().find(rest).map_or(0, |m| m.end());
+                if sgr > 0 {
+                    sanitized.push_str(&rest[..sgr]);
+                    rest = &rest[sgr..];
+                    continue;
+                }
+                false
+            }
+            c => !c.is_control(),
+        };
+        match (keep, u32::from(c)) {
+            (true, _) => sanitized.push(c),
+            (false, code) if code < 0x80 => sanitized.push_str(&format!("\\x{:02x}", code)),
+            (false, code) => sanitized.push_str(&format!("\\u{{{:x}}}", code)),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    sanitized
+}
+
+fn sgr_pattern() -> &'static Regex {
+    static SGR_PATTERN: OnceLock<Regex> = OnceLock::new();
+    SGR_PATTERN.get_or_init(|| Regex::new(r"^\x1b\[[0-9;]*m").unwrap())
+}
+
+/// `bytes` in the layout of `hexdump -C`: the offset, 16 bytes in hex and
+/// the same bytes as ASCII, with `.` for anything unprintable.
+pub fn hexdump(bytes: &[u8]) -> Vec<String> {
+    bytes.chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = (0..16)
+                .map(|i| chunk.get(i).map_or_else(|| "  ".to_string(), |byte| format!("{:02x}", byte)))
+                .collect();
+            let ascii: String = chunk.iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+            format!("{:08x}  {}  {}  |{}|", row * 16, hex[..8].join(" "), hex[8..].join(" "), ascii)
+        })
+        .collect()
+}
+
+pub fn visible_width(text: &str) -> usize {
+    strip_ansi(text).chars().count()
+}
+
+/// Colors for `[stream]` labels. Blue, magenta and red are left out, as they
+/// mark timestamps, log groups and errors.
+const STREAM_PALETTE: [Color; 7] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+    Color::BrightCyan,
+    Color::BrightGreen,
+    Color::BrightYellow,
+    Color::BrightWhite,
+];
+
+/// A color for `stream` that stays the same across events and runs, so
+/// interleaved streams are easy to tell apart. Uses FNV-1a, which unlike
+/// `DefaultHasher` is stable between Rust releases.
+pub fn stream_color(stream: &str) -> Color {
+    let hash = stream.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    STREAM_PALETTE[(hash % STREAM_PALETTE.len() as u64) as usize]
+}
+
+/// Width of the attached terminal, falling back to 80 columns when output is
+/// not a terminal.
+pub fn terminal_width() -> usize {
+    terminal_size::terminal_size()
+        .map(|(terminal_size::Width(w), _)| w as usize)
+        .unwrap_or(80)
+}
+
+pub fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn variable_token_patterns() -> &'static [(Regex, &'static str); 3] {
+    static PATTERNS: OnceLock<[(Regex, &'static str); 3]> = OnceLock::new();
+    PATTERNS.get_or_init(|| [
+        (Regex::new(r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b").unwrap(), "<uuid>"),
+        (Regex::new(r"(?i)\b(?:0x)?[0-9a-f]{8,}\b").unwrap(), "<hex>"),
+        (Regex::new(r"\d+(?:\.\d+)?").unwrap(), "<num>"),
+    ])
+}
+
+/// Reduces a message to its shape by replacing UUIDs, hex ids and numbers
+/// with placeholders, so that e.g. `timeout after 3012ms (req 9f2c...)` and
+/// `timeout after 120ms (req 77ab...)` group together.
+pub fn normalize_message(message: &str) -> String {
+    let mut shape = strip_ansi(message).trim().to_string();
+    for (pattern, placeholder) in variable_token_patterns() {
+        shape = pattern.replace_all(&shape, |caps: &regex::Captures| {
+            let token = &caps[0];
+            // Hex ids mix digits and letters; `deadbeef` is a word and
+            // `12345678` is left for the number pattern
+            let mixed = token.contains(|c: char| c.is_ascii_digit())
+                && token.contains(|c: char| c.is_ascii_alphabetic());
+            if *placeholder == "<hex>" && !mixed {
+                token.to_string()
+            } else {
+                placeholder.to_string()
+            }
+        }).into_owned();
+    }
+    shape
+}
+
+/// Default for `--max-line-length`: longer messages are cut before they're
+/// highlighted and printed.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Cuts `text` to at most `max_bytes` (on a character boundary) and appends
+/// a `… (N more bytes)` marker, so a multi-megabyte payload neither floods
+/// the terminal nor gets copied around for highlighting. 0 means no limit.
+pub fn truncate_line(text: &mut String, max_bytes: usize) {
+    if max_bytes == 0 || text.len() <= max_bytes {
+        return;
+    }
+
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let more = text.len() - end;
+    text.truncate(end);
+    text.push_str(&format!("… ({} more bytes)", more).bright_black().to_string());
+}
+
+/// Stands in for each newline when a multi-line message is kept on one line.
+pub const NEWLINE_MARKER: &str = "⏎";
+
+/// Lays out a message that may span several lines. With `wrap`, continuation
+/// lines are indented by `indent` columns so they line up under the first;
+/// otherwise newlines become `NEWLINE_MARKER` and the event stays on one line.
+/// Trailing newlines are dropped either way.
+pub fn fold_lines(message: &str, indent: usize, wrap: bool) -> String {
+    let message = message.trim_end_matches(['\n', '\r']);
+    if !message.contains('\n') {
+        return message.to_string();
+    }
+
+    let separator = match wrap {
+        true => format!("\n{}", " ".repeat(indent)),
+        false => NEWLINE_MARKER.to_string(),
+    };
+    message.split('\n')
+        .map(|line| line.trim_end_matches('\r'))
+        .collect::<Vec<_>>()
+        .join(&separator)
+}
+
+/// Formats a count with thousands separators, e.g. `1,234,567`.
+pub fn format_count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut result = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            result.push(',');
+        }
+        result.push(digit);
+    }
+    result
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(a != b);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Formats a millisecond duration compactly: `850ms`, `2.3s` or `4m 05s`.
+pub fn format_delay(millis: i64) -> String {
+    if millis < 0 {
+        return format!("-{}", format_delay(-millis));
+    }
+
+    match millis {
+        0..=999 => format!("{}ms", millis),
+        1_000..=59_999 => format!("{:.1}s", millis as f64 / 1000.0),
+        _ => format!("{}m {:02}s", millis / 60_000, millis % 60_000 / 1000),
+    }
+}
+
 pub fn colorize_log_level(text: &str) -> String {
     let error_pattern = Regex::new(r"(?i)\b(error|err|fatal|panic)\b").unwrap();
     let warn_pattern = Regex::new(r"(?i)\b(warn|warning)\b").unwrap();
@@ -52,21 +441,360 @@ pub fn colorize_log_level(text: &str) -> String {
 
 pub fn format_json_field(json_str: &str, field: &str) -> Option<String> {
     if let Ok(value) = serde_json::from_str::<serde_json::Value>(json_str) {
-        let parts: Vec<&str> = field.split('.').collect();
-        let mut current = &value;
+        json_field_value(&value, field)
+    } else {
+        None
+    }
+}
+
+/// Looks up `field` in a JSON value. Paths use dot notation with optional
+/// array indices (`items[0].id`, matching the column names produced by the
+/// table flattener), or an RFC 6901 JSON Pointer when they start with `/`
+/// (`/items/0/id`, useful for keys that contain dots).
+pub fn json_field_value(value: &serde_json::Value, field: &str) -> Option<String> {
+    let current = if field.starts_with('/') {
+        value.pointer(field)?
+    } else {
+        let mut current = value;
+
+        for part in field.split('.') {
+            let (key, indices) = match part.find('[') {
+                Some(pos) => (&part[..pos], &part[pos..]),
+                None => (part, ""),
+            };
+
+            if !key.is_empty() {
+                current = current.get(key)?;
+            }
 
-        for part in parts {
-            match current.get(part) {
-                Some(v) => current = v,
-                None => return None,
+            for index in indices.split('[').skip(1) {
+                let index: usize = index.strip_suffix(']')?.parse().ok()?;
+                current = current.get(index)?;
             }
         }
 
-        Some(match current {
-            serde_json::Value::String(s) => s.clone(),
-            _ => current.to_string(),
+        current
+    };
+
+    Some(match current {
+        serde_json::Value::String(s) => s.clone(),
+        _ => current.to_string(),
+    })
+}
+
+/// Renders the requested fields of a JSON message as `key=value` pairs,
+/// quoting values that contain whitespace. Returns `None` for non-JSON
+/// messages or when none of the fields are present.
+pub fn project_fields(message: &str, fields: &[String]) -> Option<String> {
+    let value = serde_json::from_str::<serde_json::Value>(message).ok()?;
+
+    let pairs: Vec<String> = fields.iter()
+        .filter_map(|field| {
+            json_field_value(&value, field).map(|v| {
+                if v.is_empty() || v.contains(char::is_whitespace) || v.contains('"') {
+                    format!("{}={:?}", field, v)
+                } else {
+                    format!("{}={}", field, v)
+                }
+            })
         })
-    } else {
+        .collect();
+
+    if pairs.is_empty() {
         None
+    } else {
+        Some(pairs.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Timestamp,
+    Stream,
+    Group,
+    Message,
+    Json(String),
+}
+
+/// A parsed `--template` such as `{timestamp} {json:level} {message}`.
+/// Placeholders are `{timestamp}`, `{stream}`, `{group}`, `{message}` and
+/// `{json:<field>}`, which takes a field from the message parsed as JSON
+/// using the same paths as `--fields`. `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+/// The values a template line is rendered from.
+pub struct TemplateContext<'a> {
+    pub timestamp: &'a str,
+    pub stream: &'a str,
+    pub group: &'a str,
+    pub message: &'a str,
+}
+
+impl Template {
+    /// Parses a template, rejecting unknown placeholders and unbalanced
+    /// braces up front so mistakes surface before any events are fetched.
+    pub fn parse(template: &str) -> Result<Self, CwlError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed {
+                        return Err(CwlError::InvalidArgument(format!("Unclosed placeholder in template: {{{}", name)));
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(match name.as_str() {
+                        "timestamp" => TemplatePart::Timestamp,
+                        "stream" => TemplatePart::Stream,
+                        "group" => TemplatePart::Group,
+                        "message" => TemplatePart::Message,
+                        _ => match name.strip_prefix("json:") {
+                            Some(field) if !field.is_empty() => TemplatePart::Json(field.to_string()),
+                            _ => return Err(CwlError::InvalidArgument(format!(
+                                "Unknown template placeholder {{{}}}. Expected {{timestamp}}, {{stream}}, {{group}}, {{message}} or {{json:<field>}}",
+                                name
+                            ))),
+                        },
+                    });
+                }
+                '}' => {
+                    return Err(CwlError::InvalidArgument(
+                        "Unmatched '}' in template (use '}}' for a literal brace)".to_string()
+                    ));
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Renders one line. The message is only parsed as JSON when the
+    /// template uses a `{json:...}` placeholder; missing fields render empty.
+    pub fn render(&self, context: &TemplateContext) -> String {
+        let json = self.parts.iter()
+            .any(|part| matches!(part, TemplatePart::Json(_)))
+            .then(|| serde_json::from_str::<serde_json::Value>(context.message).ok())
+            .flatten();
+
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => line.push_str(text),
+                TemplatePart::Timestamp => line.push_str(context.timestamp),
+                TemplatePart::Stream => line.push_str(context.stream),
+                TemplatePart::Group => line.push_str(context.group),
+                TemplatePart::Message => line.push_str(context.message.trim_end_matches(['\n', '\r'])),
+                TemplatePart::Json(field) => {
+                    if let Some(value) = json.as_ref().and_then(|json| json_field_value(json, field)) {
+                        line.push_str(&value);
+                    }
+                }
+            }
+        }
+
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_fields() {
+        let message = r#"{"level":"INFO","msg":"user logged in","request_id":"abc","user":{"id":7}}"#;
+        let fields = vec!["level".to_string(), "msg".to_string(), "user.id".to_string(), "missing".to_string()];
+
+        assert_eq!(
+            project_fields(message, &fields).as_deref(),
+            Some(r#"level=INFO msg="user logged in" user.id=7"#)
+        );
+        assert_eq!(project_fields("plain text line", &fields), None);
+    }
+
+    #[test]
+    fn test_format_json_field_array_indices() {
+        let json = r#"{"items":[{"id":"a"},{"id":"b","tags":["x","y"]}],"a.b":1}"#;
+
+        assert_eq!(format_json_field(json, "items[0].id").as_deref(), Some("a"));
+        assert_eq!(format_json_field(json, "items[1].tags[1]").as_deref(), Some("y"));
+        assert_eq!(format_json_field(json, "items[2].id"), None);
+        assert_eq!(format_json_field(json, "items[x].id"), None);
+        assert_eq!(format_json_field(json, "/items/1/id").as_deref(), Some("b"));
+        assert_eq!(format_json_field(json, "/a.b").as_deref(), Some("1"));
+        assert_eq!(format_json_field(json, "/items/5"), None);
+    }
+
+    #[test]
+    fn test_case_insensitive_highlight() {
+        let options = RegexOptions { ignore_case: true, whole_line: false };
+        let pattern = options.literal("error").unwrap();
+        let style = HighlightStyle::default();
+        let highlighted = highlight_matches("Error: ERROR count", &pattern, style);
+
+        // Built with the same style, so this holds whether or not colors are on
+        assert_eq!(highlighted, format!("{}: {} count", style.apply("Error"), style.apply("ERROR")));
+        assert_eq!(strip_ansi(&highlighted), "Error: ERROR count");
+        assert!(!RegexOptions::default().literal("error").unwrap().is_match("ERROR"));
+    }
+
+    #[test]
+    fn test_highlight_style_parsing() {
+        let plain = HighlightStyle { foreground: None, background: None, bold: false, dimmed: false, italic: false, underline: false, reversed: false };
+
+        let style: HighlightStyle = "on_red+white+bold".parse().unwrap();
+        assert_eq!(style, HighlightStyle { foreground: Some(Color::White), background: Some(Color::Red), bold: true, ..plain });
+        let applied = style.apply("x");
+        assert_eq!((applied.input.as_str(), applied.fgcolor, applied.bgcolor), ("x", Some(Color::White), Some(Color::Red)));
+
+        let style: HighlightStyle = "Bright_Cyan + underline".parse().unwrap();
+        assert_eq!(style, HighlightStyle { foreground: Some(Color::BrightCyan), underline: true, ..plain });
+        assert!("on_purple".parse::<HighlightStyle>().unwrap_err().contains("'on_purple'"));
+        assert!("blink".parse::<HighlightStyle>().is_err());
+        assert!("red+".parse::<HighlightStyle>().is_err());
+    }
+
+    #[test]
+    fn test_whole_line_anchoring() {
+        let options = RegexOptions { ignore_case: false, whole_line: true };
+        let pattern = options.literal("done").unwrap();
+
+        assert!(pattern.is_match("done"));
+        assert!(pattern.is_match("starting\ndone"));
+        assert!(!pattern.is_match("not done yet"));
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("/aws/lambda/prod-*", "/aws/lambda/prod-api"));
+        assert!(glob_matches("/aws/lambda/*-api", "/aws/lambda/prod-api"));
+        assert!(!glob_matches("/aws/lambda/prod-*", "/aws/lambda/staging-api"));
+        assert!(!glob_matches("/aws/lambda/prod-*", "/aws/ecs/aws/lambda/prod-api"));
+        assert!(glob_matches("/aws/lambda/prod.*", "/aws/lambda/prod.worker"));
+        assert!(!glob_matches("/aws/lambda/prod.*", "/aws/lambda/prodXworker"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_template_render() {
+        let template = Template::parse("{timestamp} [{json:level}] {{{stream}}} {message}").unwrap();
+        let context = TemplateContext {
+            timestamp: "2024-01-01 00:00:00.000",
+            stream: "abc",
+            group: "/aws/lambda/app",
+            message: "{\"level\":\"WARN\"}\n",
+        };
+        assert_eq!(template.render(&context), r#"2024-01-01 00:00:00.000 [WARN] {abc} {"level":"WARN"}"#);
+
+        let context = TemplateContext { message: "plain", ..context };
+        assert_eq!(template.render(&context), "2024-01-01 00:00:00.000 [] {abc} plain");
+    }
+
+    #[test]
+    fn test_template_rejects_bad_placeholders() {
+        assert!(Template::parse("{level}").is_err());
+        assert!(Template::parse("{json:}").is_err());
+        assert!(Template::parse("{message").is_err());
+        assert!(Template::parse("message}").is_err());
+    }
+
+    #[test]
+    fn test_normalize_message() {
+        assert_eq!(
+            normalize_message("Request 550e8400-e29b-41d4-a716-446655440000 took 35.2ms"),
+            "Request <uuid> took <num>ms"
+        );
+        assert_eq!(normalize_message("trace 5f3a9c0e12ab failed on a1"), "trace <hex> failed on a<num>");
+        assert_eq!(normalize_message("cache miss for deadbeef"), "cache miss for deadbeef");
+        assert_eq!(normalize_message("retry 3 of 5"), normalize_message("retry 1 of 5"));
+    }
+
+    #[test]
+    fn test_truncate_line() {
+        let mut short = "short line".to_string();
+        truncate_line(&mut short, 64);
+        assert_eq!(short, "short line");
+
+        let mut long = "héllo wörld".repeat(10);
+        let len = long.len();
+        truncate_line(&mut long, 2);
+        assert_eq!(strip_ansi(&long), format!("h… ({} more bytes)", len - 1));
+
+        let mut unlimited = "x".repeat(100);
+        truncate_line(&mut unlimited, 0);
+        assert_eq!(unlimited.len(), 100);
+    }
+
+    #[test]
+    fn test_stream_color_is_stable() {
+        assert_eq!(stream_color("2024/01/01/[$LATEST]abc"), stream_color("2024/01/01/[$LATEST]abc"));
+        let colors: std::collections::HashSet<String> = (0..20)
+            .map(|i| format!("{:?}", stream_color(&format!("web-{}", i))))
+            .collect();
+        assert!(colors.len() > 1);
+    }
+
+    #[test]
+    fn test_fold_lines() {
+        let trace = "Exception: boom\r\n  at handler.js:10\n  at main.js:2\n";
+        assert_eq!(fold_lines(trace, 4, true), "Exception: boom\n      at handler.js:10\n      at main.js:2");
+        assert_eq!(fold_lines(trace, 4, false), "Exception: boom⏎  at handler.js:10⏎  at main.js:2");
+        assert_eq!(fold_lines("single line\n", 4, true), "single line");
+    }
+
+    #[test]
+    fn test_sanitize_for_terminal() {
+        assert_eq!(sanitize_for_terminal("ok\tdone\r\nnext\r\n"), "ok\tdone\r\nnext\r\n");
+        assert_eq!(sanitize_for_terminal("\x1b[31mred\x1b[0m"), "\x1b[31mred\x1b[0m");
+        assert_eq!(sanitize_for_terminal("bell\x07 \x1b[2J\0x\rover"), "bell\\x07 \\x1b[2J\\x00x\\x0dover");
+        assert_eq!(sanitize_for_terminal("c1\u{9b}é"), "c1\\u{9b}é");
+
+        assert_eq!(hexdump(b"Hello\x00world, hexdump"), [
+            "00000000  48 65 6c 6c 6f 00 77 6f  72 6c 64 2c 20 68 65 78  |Hello.world, hex|",
+            "00000010  64 75 6d 70                                       |dump|",
+        ]);
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1000), "1,000");
+        assert_eq!(format_count(1234567), "1,234,567");
+
+        // usize is 32 bits on some targets
+        let max = format_count(usize::MAX);
+        assert_eq!(max.replace(',', ""), usize::MAX.to_string());
+        assert!(max.split(',').skip(1).all(|group| group.len() == 3));
+    }
+}
@@ -1,57 +1,302 @@
-use std::collections::{HashMap, BTreeMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use serde_json::Value;
 use colored::Colorize;
+use clap::ValueEnum;
+use regex::Regex;
+use std::sync::OnceLock;
+use crate::utils::format::{colorize_log_level, format_count, format_delay, json_field_value, strip_ansi, visible_width, NEWLINE_MARKER};
+use crate::utils::time::parse_timestamp;
+use crate::utils::tee::TeeWriter;
+
+const LEVEL_COLUMNS: [&str; 3] = ["level", "severity", "log_level"];
+
+/// The column added by `add_ingestion_delays`.
+const INGESTION_DELAY_COLUMN: &str = "ingestion_delay";
+
+/// Widest a column gets by default, even when the terminal has room.
+const MAX_COLUMN_WIDTH: usize = 100;
+
+/// Narrowest a column is squeezed to when fitting the table to a width.
+const MIN_COLUMN_WIDTH: usize = 10;
+
+/// How many layers of stringified JSON `parse_json_payload` will unwrap.
+const MAX_DECODE_DEPTH: usize = 4;
+
+/// How many levels of nested JSON become separate columns by default.
+pub const DEFAULT_FLATTEN_DEPTH: usize = 3;
+
+/// How message payloads are parsed into columns for the formatted table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum FormatHint {
+    /// Try JSON first and fall back to logfmt
+    #[default]
+    Auto,
+    Json,
+    Logfmt,
+}
 
 pub struct ColumnInfo {
     pub name: String,
     pub frequency: usize,
+    /// Display width budget for the column. Starts as the widest value and is
+    /// reduced by `fit_columns`; longer values are truncated when rendered.
     pub max_width: usize,
 }
 
 pub struct FormattedOutput {
     pub columns: Vec<ColumnInfo>,
     pub rows: Vec<Vec<String>>,
+    /// Rows whose ingestion delay is over the lag threshold, drawn in red
+    pub lagging: Vec<bool>,
 }
 
-pub fn flatten_json_to_columns(value: &Value, prefix: &str) -> BTreeMap<String, String> {
+/// Flattens nested JSON into dotted (`a.b`) and indexed (`a[0]`) columns.
+/// Nesting deeper than `max_depth` levels stays in one column as compact
+/// JSON, so deeply nested payloads don't explode into hundreds of columns.
+pub fn flatten_json_to_columns(value: &Value, prefix: &str, max_depth: usize) -> BTreeMap<String, String> {
     let mut result = BTreeMap::new();
+    flatten_json(value, prefix, max_depth, &mut |key, value| {
+        result.insert(key, format_json_value(value));
+    });
+    result
+}
 
+/// Calls `leaf` with each column `flatten_json_to_columns` would produce and
+/// the JSON value behind it.
+fn flatten_json(value: &Value, prefix: &str, max_depth: usize, leaf: &mut impl FnMut(String, &Value)) {
     match value {
-        Value::Object(map) => {
+        Value::Object(map) if max_depth > 0 => {
             for (key, val) in map {
                 let new_prefix = if prefix.is_empty() {
                     key.clone()
                 } else {
                     format!("{}.{}", prefix, key)
                 };
-
-                match val {
-                    Value::Object(_) | Value::Array(_) => {
-                        let nested = flatten_json_to_columns(val, &new_prefix);
-                        result.extend(nested);
-                    }
-                    Value::Null => {
-                        result.insert(new_prefix, String::new());
-                    }
-                    _ => {
-                        result.insert(new_prefix, format_json_value(val));
-                    }
-                }
+                flatten_json(val, &new_prefix, max_depth - 1, leaf);
             }
         }
-        Value::Array(arr) => {
+        Value::Array(arr) if max_depth > 0 => {
             for (i, val) in arr.iter().enumerate() {
                 let new_prefix = format!("{}[{}]", prefix, i);
-                let nested = flatten_json_to_columns(val, &new_prefix);
-                result.extend(nested);
+                flatten_json(val, &new_prefix, max_depth - 1, leaf);
             }
         }
-        _ => {
-            result.insert(prefix.to_string(), format_json_value(value));
+        _ => leaf(prefix.to_string(), value),
+    }
+}
+
+/// Parses a message payload, unwrapping double-encoded JSON (a JSON string
+/// whose contents are themselves JSON, common in Lambda logs) up to
+/// `MAX_DECODE_DEPTH` levels.
+pub fn parse_json_payload(json_str: &str) -> Option<Value> {
+    let mut value = serde_json::from_str::<Value>(json_str).ok()?;
+
+    for _ in 0..MAX_DECODE_DEPTH {
+        let Value::String(ref inner) = value else {
+            break;
+        };
+        match serde_json::from_str::<Value>(inner) {
+            Ok(decoded) => value = decoded,
+            Err(_) => break,
         }
     }
 
-    result
+    Some(value)
+}
+
+/// The application's own time for an event (`--time-field`): `field` of a
+/// JSON message, as a dot path or JSON Pointer, parsed like `--start`.
+/// `None` when the message isn't JSON or the field is missing or unparseable.
+pub fn field_timestamp(message: &str, field: &str) -> Option<i64> {
+    let value = parse_json_payload(message)?;
+    parse_timestamp(&json_field_value(&value, field)?).ok()
+}
+
+fn parse_payload_columns(payload: &str, hint: FormatHint, max_depth: usize, lambda: bool, emf: bool) -> Option<BTreeMap<String, String>> {
+    if lambda {
+        if let Some(columns) = parse_lambda_platform_line(payload) {
+            return Some(columns);
+        }
+    }
+    if emf && hint != FormatHint::Logfmt {
+        if let Some(columns) = parse_json_payload(payload).and_then(|value| parse_emf(&value, max_depth)) {
+            return Some(columns);
+        }
+    }
+
+    let json = || parse_json_payload(payload).map(|value| flatten_json_to_columns(&value, "", max_depth));
+
+    match hint {
+        FormatHint::Json => json(),
+        FormatHint::Logfmt => parse_logfmt(payload),
+        FormatHint::Auto => json().or_else(|| parse_logfmt(payload)),
+    }
+}
+
+fn lambda_field_pattern() -> &'static Regex {
+    static LAMBDA_FIELD_PATTERN: OnceLock<Regex> = OnceLock::new();
+    LAMBDA_FIELD_PATTERN.get_or_init(|| {
+        Regex::new(r"(RequestId|Version|Billed Duration|Init Duration|Restore Duration|Duration|Memory Size|Max Memory Used): (\S+)").unwrap()
+    })
+}
+
+/// Parses the `START`, `END` and `REPORT` lines the Lambda runtime writes for
+/// each invocation into columns: `event`, `request_id`, `version`,
+/// `duration_ms`, `billed_ms`, `init_duration_ms`, `restore_duration_ms`,
+/// `memory_mb` and `max_memory_used_mb`. Returns `None` for any other line.
+pub fn parse_lambda_platform_line(line: &str) -> Option<BTreeMap<String, String>> {
+    let line = line.trim();
+    let (event, rest) = line.split_once(' ')?;
+    if !matches!(event, "START" | "END" | "REPORT") || !rest.starts_with("RequestId: ") {
+        return None;
+    }
+
+    let mut result = BTreeMap::new();
+    result.insert("event".to_string(), event.to_string());
+
+    for captures in lambda_field_pattern().captures_iter(rest) {
+        let column = match &captures[1] {
+            "RequestId" => "request_id",
+            "Version" => "version",
+            "Duration" => "duration_ms",
+            "Billed Duration" => "billed_ms",
+            "Init Duration" => "init_duration_ms",
+            "Restore Duration" => "restore_duration_ms",
+            "Memory Size" => "memory_mb",
+            _ => "max_memory_used_mb",
+        };
+        result.insert(column.to_string(), captures[2].to_string());
+    }
+
+    Some(result)
+}
+
+/// Condenses an embedded metric format document (one with an
+/// `_aws.CloudWatchMetrics` block) into a `metrics` column of
+/// `namespace/metric_name = value unit` entries and a `dimensions` column of
+/// `key=value` pairs. Other properties are flattened as usual; the `_aws`
+/// tree is dropped. Returns `None` for anything that isn't EMF.
+pub fn parse_emf(value: &Value, max_depth: usize) -> Option<BTreeMap<String, String>> {
+    let directives = value.get("_aws")?.get("CloudWatchMetrics")?.as_array()?;
+    let object = value.as_object()?;
+
+    let mut metrics = Vec::new();
+    let mut dimension_keys: Vec<&str> = Vec::new();
+    for directive in directives {
+        let namespace = directive.get("Namespace").and_then(Value::as_str).unwrap_or_default();
+        for metric in directive.get("Metrics").and_then(Value::as_array).into_iter().flatten() {
+            let Some(name) = metric.get("Name").and_then(Value::as_str) else {
+                continue;
+            };
+            let value = object.get(name).map(format_json_value).unwrap_or_default();
+            let mut entry = format!("{}/{} = {}", namespace, name, value);
+            match metric.get("Unit").and_then(Value::as_str) {
+                Some(unit) if unit != "None" => {
+                    entry.push(' ');
+                    entry.push_str(unit);
+                }
+                _ => {}
+            }
+            metrics.push((name, entry));
+        }
+        let sets = directive.get("Dimensions").and_then(Value::as_array).into_iter().flatten();
+        for key in sets.filter_map(Value::as_array).flatten().filter_map(Value::as_str) {
+            if !dimension_keys.contains(&key) {
+                dimension_keys.push(key);
+            }
+        }
+    }
+
+    let mut result = BTreeMap::new();
+    for (key, val) in object {
+        let key = key.as_str();
+        if key == "_aws" || dimension_keys.contains(&key) || metrics.iter().any(|(name, _)| *name == key) {
+            continue;
+        }
+        result.extend(flatten_json_to_columns(val, key, max_depth.saturating_sub(1)));
+    }
+
+    let dimensions: Vec<String> = dimension_keys.iter()
+        .map(|key| format!("{}={}", key, object.get(*key).map(format_json_value).unwrap_or_default()))
+        .collect();
+    result.insert("metrics".to_string(), metrics.into_iter().map(|(_, entry)| entry).collect::<Vec<_>>().join(", "));
+    if !dimensions.is_empty() {
+        result.insert("dimensions".to_string(), dimensions.join(", "));
+    }
+
+    Some(result)
+}
+
+/// Parses a logfmt line (`level=info msg="hello world" dur=12ms`) into
+/// columns. Quoted values may contain spaces and `\"` escapes. Returns `None`
+/// unless every token is a `key=value` pair, so free-form text is not mistaken
+/// for logfmt.
+pub fn parse_logfmt(line: &str) -> Option<BTreeMap<String, String>> {
+    let mut result = BTreeMap::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while chars.peek().is_some() {
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c.is_whitespace() || c == '"' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+
+        if key.is_empty() || chars.next() != Some('=') {
+            return None;
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some(escaped) => value.push(escaped),
+                        None => return None,
+                    },
+                    _ => value.push(c),
+                }
+            }
+            if !closed {
+                return None;
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+        }
+
+        match chars.peek() {
+            Some(c) if !c.is_whitespace() => return None,
+            _ => {}
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+
+        result.insert(key, value);
+    }
+
+    if result.is_empty() {
+        None
+    } else {
+        Some(result)
+    }
 }
 
 fn format_json_value(value: &Value) -> String {
@@ -64,9 +309,120 @@ fn format_json_value(value: &Value) -> String {
     }
 }
 
-pub fn analyze_json_logs(logs: &[String]) -> FormattedOutput {
-    let mut column_frequency: HashMap<String, usize> = HashMap::new();
-    let mut column_max_width: HashMap<String, usize> = HashMap::new();
+/// A field found by `infer_schema`.
+#[derive(Debug, PartialEq)]
+pub struct FieldSchema {
+    /// The column name, with array indexes collapsed to `[]`
+    pub name: String,
+    /// How many messages had the field
+    pub frequency: usize,
+    /// The JSON types it held: `string`, `number`, `boolean`, `null`, and
+    /// `object` or `array` for values nested past the flatten depth
+    pub types: BTreeSet<&'static str>,
+    /// The first non-empty value seen
+    pub example: String,
+    pub max_width: usize,
+}
+
+/// Infers the fields of the JSON object messages among `messages`, most
+/// frequent first like the columns of `analyze_json_logs`. Also returns how
+/// many messages were JSON objects; the rest are skipped.
+pub fn infer_schema<'a>(messages: impl IntoIterator<Item = &'a str>, max_depth: usize) -> (Vec<FieldSchema>, usize) {
+    let mut stats = ColumnStats::default();
+    let mut details: HashMap<String, (BTreeSet<&'static str>, String)> = HashMap::new();
+    let mut json_messages = 0;
+
+    for message in messages {
+        let Some(value) = parse_json_payload(message).filter(Value::is_object) else {
+            continue;
+        };
+        json_messages += 1;
+
+        let mut seen = HashSet::new();
+        flatten_json(&value, "", max_depth, &mut |key, value| {
+            let name = array_index_pattern().replace_all(&key, "[]").into_owned();
+            let text = strip_ansi(&format_json_value(value));
+            // Collapsed array elements count once per message
+            if seen.insert(name.clone()) {
+                stats.record(&name, &text);
+            } else {
+                stats.fit(&name, &text);
+            }
+            let (types, example) = details.entry(name).or_default();
+            types.insert(json_type(value));
+            if example.is_empty() {
+                *example = text;
+            }
+        });
+    }
+
+    let fields = stats.by_frequency()
+        .into_iter()
+        .map(|(name, frequency, max_width)| {
+            let (types, example) = details.remove(name).unwrap_or_default();
+            FieldSchema { name: name.to_string(), frequency, types, example, max_width }
+        })
+        .collect();
+    (fields, json_messages)
+}
+
+/// How many rows have each column and how wide its widest value is, for
+/// `analyze_json_logs` and `infer_schema`.
+#[derive(Default)]
+struct ColumnStats {
+    frequency: HashMap<String, usize>,
+    max_width: HashMap<String, usize>,
+}
+
+impl ColumnStats {
+    /// Counts a row having `column`, widening it to fit `value`.
+    fn record(&mut self, column: &str, value: &str) {
+        *self.frequency.entry(column.to_string()).or_default() += 1;
+        self.fit(column, value);
+    }
+
+    /// Widens `column` to fit `value` without counting a row.
+    fn fit(&mut self, column: &str, value: &str) {
+        let width = self.max_width.entry(column.to_string()).or_default();
+        *width = (*width).max(value.chars().count());
+    }
+
+    fn width(&self, column: &str) -> Option<usize> {
+        self.max_width.get(column).copied()
+    }
+
+    /// Counted columns with their frequency and width, most frequent first
+    /// and then by name.
+    fn by_frequency(&self) -> Vec<(&str, usize, usize)> {
+        let mut columns: Vec<(&str, usize, usize)> = self.frequency.iter()
+            .map(|(name, frequency)| (name.as_str(), *frequency, self.width(name).unwrap_or(0)))
+            .collect();
+        columns.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        columns
+    }
+}
+
+fn array_index_pattern() -> &'static Regex {
+    static ARRAY_INDEX_PATTERN: OnceLock<Regex> = OnceLock::new();
+    ARRAY_INDEX_PATTERN.get_or_init(|| Regex::new(r"\[\d+\]").unwrap())
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "string",
+        Value::Number(_) => "number",
+        Value::Bool(_) => "boolean",
+        Value::Null => "null",
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+    }
+}
+
+/// Builds the table for `--formatted` and CSV output. With `lambda`, Lambda
+/// platform lines are parsed by `parse_lambda_platform_line` first; with
+/// `emf`, metric documents are condensed by `parse_emf`.
+pub fn analyze_json_logs(logs: &[String], hint: FormatHint, max_depth: usize, lambda: bool, emf: bool) -> FormattedOutput {
+    let mut stats = ColumnStats::default();
     let mut all_rows: Vec<BTreeMap<String, String>> = Vec::new();
 
     for log_line in logs.iter() {
@@ -76,138 +432,653 @@ pub fn analyze_json_logs(logs: &[String]) -> FormattedOutput {
         row.insert("timestamp".to_string(), timestamp.clone());
         row.insert("log_group".to_string(), log_group.clone());
 
-        column_max_width.entry("timestamp".to_string())
-            .and_modify(|w| *w = (*w).max(timestamp.len()))
-            .or_insert(timestamp.len());
-        column_max_width.entry("log_group".to_string())
-            .and_modify(|w| *w = (*w).max(log_group.len()))
-            .or_insert(log_group.len());
-
-        if let Ok(json_value) = serde_json::from_str::<Value>(&json_str) {
-            let flattened = flatten_json_to_columns(&json_value, "");
+        stats.fit("timestamp", &timestamp);
+        stats.fit("log_group", &log_group);
 
+        if let Some(flattened) = parse_payload_columns(&json_str, hint, max_depth, lambda, emf) {
             for (key, value) in &flattened {
-                column_frequency.entry(key.clone())
-                    .and_modify(|count| *count += 1)
-                    .or_insert(1);
-
-                column_max_width.entry(key.clone())
-                    .and_modify(|w| *w = (*w).max(value.len().min(100)))
-                    .or_insert(value.len().min(100));
-
-                row.insert(key.clone(), value.clone());
+                // Escape codes embedded by the application would skew widths
+                let value = strip_ansi(value);
+                stats.record(key, &value);
+                row.insert(key.clone(), value);
             }
         }
 
         all_rows.push(row);
     }
 
-    column_frequency.insert("timestamp".to_string(), logs.len());
-    column_frequency.insert("log_group".to_string(), logs.len());
-
     let mut columns: Vec<ColumnInfo> = Vec::new();
 
     columns.push(ColumnInfo {
         name: "timestamp".to_string(),
         frequency: logs.len(),
-        max_width: column_max_width.get("timestamp").copied().unwrap_or(9).max(9),
+        max_width: stats.width("timestamp").unwrap_or(9).max(9),
     });
 
     columns.push(ColumnInfo {
         name: "log_group".to_string(),
         frequency: logs.len(),
-        max_width: column_max_width.get("log_group").copied().unwrap_or(9).max(9),
+        max_width: stats.width("log_group").unwrap_or(9).max(9),
     });
 
-    let mut other_columns: Vec<ColumnInfo> = column_frequency
-        .iter()
-        .filter(|(k, _)| k.as_str() != "timestamp" && k.as_str() != "log_group")
-        .map(|(name, frequency)| ColumnInfo {
-            name: name.clone(),
-            frequency: *frequency,
-            max_width: column_max_width.get(name).copied().unwrap_or(0).max(name.len()),
-        })
+    columns.extend(stats.by_frequency()
+        .into_iter()
+        .filter(|(name, _, _)| *name != "timestamp" && *name != "log_group")
+        .map(|(name, frequency, width)| ColumnInfo {
+            name: name.to_string(),
+            frequency,
+            max_width: width.max(name.len()),
+        }));
+
+    let mut rows = Vec::new();
+    for row_map in all_rows {
+        let row_values = columns.iter()
+            .map(|col| row_map.get(&col.name).cloned().unwrap_or_default())
+            .collect();
+        rows.push(row_values);
+    }
+
+    FormattedOutput { columns, rows, lagging: Vec::new() }
+}
+
+/// Inserts an `ingestion_delay` column (ingestion time minus event time)
+/// after the timestamp and log group. `delays` has one entry per row; rows
+/// delayed by more than `threshold` milliseconds are flagged as lagging.
+pub fn add_ingestion_delays(output: &mut FormattedOutput, delays: &[Option<i64>], threshold: Option<i64>) {
+    let values: Vec<String> = delays.iter()
+        .map(|delay| delay.map(format_delay).unwrap_or_default())
         .collect();
+    let position = output.columns.len().min(2);
 
-    other_columns.sort_by(|a, b| {
-        b.frequency.cmp(&a.frequency)
-            .then_with(|| a.name.cmp(&b.name))
+    output.columns.insert(position, ColumnInfo {
+        name: INGESTION_DELAY_COLUMN.to_string(),
+        frequency: delays.iter().flatten().count(),
+        max_width: values.iter()
+            .map(|value| value.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(INGESTION_DELAY_COLUMN.len()),
     });
+    for (row, value) in output.rows.iter_mut().zip(values) {
+        row.insert(position, value);
+    }
 
-    columns.extend(other_columns);
+    output.lagging = delays.iter()
+        .map(|delay| threshold.zip(*delay).is_some_and(|(threshold, delay)| delay > threshold))
+        .collect();
+}
 
-    let mut rows = Vec::new();
-    for row_map in all_rows {
-        let mut row_values = Vec::new();
-        for col in &columns {
-            let value = row_map.get(&col.name).cloned().unwrap_or_default();
-            row_values.push(truncate_string(&value, 100));
-        }
-        rows.push(row_values);
+/// Drops columns found in fewer than `min_percent` percent of rows, except
+/// the timestamp and log group and any named in `keep`. Returns how many
+/// columns were dropped.
+pub fn prune_sparse_columns(output: &mut FormattedOutput, min_percent: f64, keep: &[String]) -> usize {
+    let rows = output.rows.len();
+    let retained: Vec<bool> = output.columns.iter()
+        .enumerate()
+        .map(|(index, col)| {
+            index < 2
+                || keep.contains(&col.name)
+                || col.frequency as f64 * 100.0 >= min_percent * rows as f64
+        })
+        .collect();
+
+    let mut flags = retained.iter();
+    output.columns.retain(|_| *flags.next().unwrap_or(&true));
+    for row in &mut output.rows {
+        let mut flags = retained.iter();
+        row.retain(|_| *flags.next().unwrap_or(&true));
     }
 
-    FormattedOutput { columns, rows }
+    retained.iter().filter(|&&kept| !kept).count()
 }
 
-fn parse_log_line(line: &str) -> (String, String, String) {
-    if let Some(ts_end) = line.find(']') {
-        if line.starts_with('[') {
-            let timestamp = line[1..ts_end].to_string();
-            let rest = &line[ts_end + 1..].trim();
+/// Parses a `--min-frequency` percentage between 0 and 100.
+pub fn parse_percent(s: &str) -> Result<f64, String> {
+    match s.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("'{}' is not a percentage between 0 and 100 (e.g. 5 for 5%)", s)),
+    }
+}
 
-            if rest.starts_with('[') {
-                if let Some(group_end) = rest.find(']') {
-                    let log_group = rest[1..group_end].to_string();
-                    let json_str = rest[group_end + 1..].trim().to_string();
-                    return (timestamp, log_group, json_str);
-                }
+/// Moves the `pinned` columns, in the given order, to just after the
+/// timestamp and log group. Names with no matching column are ignored.
+pub fn pin_columns(output: &mut FormattedOutput, pinned: &[String]) {
+    let mut order: Vec<usize> = (0..output.columns.len().min(2)).collect();
+    for name in pinned {
+        if let Some(index) = output.columns.iter().position(|col| col.name == *name) {
+            if !order.contains(&index) {
+                order.push(index);
             }
+        }
+    }
+    let rest: Vec<usize> = (0..output.columns.len()).filter(|index| !order.contains(index)).collect();
+    order.extend(rest);
 
-            return (timestamp, String::new(), rest.to_string());
+    let mut columns: Vec<Option<ColumnInfo>> = std::mem::take(&mut output.columns).into_iter().map(Some).collect();
+    output.columns = order.iter().filter_map(|&index| columns[index].take()).collect();
+    for row in &mut output.rows {
+        let mut values: Vec<Option<String>> = std::mem::take(row).into_iter().map(Some).collect();
+        *row = order.iter().map(|&index| values[index].take().unwrap_or_default()).collect();
+    }
+}
+
+/// Caps every column at `MAX_COLUMN_WIDTH` and, when `total_width` is given,
+/// shrinks columns until the rendered table fits in it. The least frequent
+/// columns are truncated first, down to `MIN_COLUMN_WIDTH`; the table may
+/// still overflow if every column is already at the minimum.
+pub fn fit_columns(output: &mut FormattedOutput, total_width: Option<usize>) {
+    for col in &mut output.columns {
+        col.max_width = col.max_width.min(MAX_COLUMN_WIDTH);
+    }
+
+    let Some(total_width) = total_width else {
+        return;
+    };
+
+    let separators = output.columns.len().saturating_sub(1) * 3;
+    let used: usize = output.columns.iter().map(|col| col.max_width).sum::<usize>() + separators;
+    let mut excess = used.saturating_sub(total_width);
+
+    let mut order: Vec<usize> = (0..output.columns.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&output.columns[a], &output.columns[b]);
+        a.frequency.cmp(&b.frequency).then_with(|| b.max_width.cmp(&a.max_width))
+    });
+
+    for index in order {
+        if excess == 0 {
+            break;
         }
+        let col = &mut output.columns[index];
+        let shrink = col.max_width.saturating_sub(MIN_COLUMN_WIDTH).min(excess);
+        col.max_width -= shrink;
+        excess -= shrink;
     }
+}
+
+/// A `--col-width` entry: `N` caps every field column, `FIELD=N` just one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnWidth {
+    All(usize),
+    Column(String, usize),
+}
+
+/// Parses a `--col-width` entry. Widths below 4 would leave no room for the
+/// text next to the `...` of a truncated value.
+pub fn parse_column_width(s: &str) -> Result<ColumnWidth, String> {
+    let (name, width) = match s.rsplit_once('=') {
+        Some((name, width)) if !name.trim().is_empty() => (Some(name.trim()), width),
+        Some(_) => return Err(format!("'{}' is missing the column name before '='", s)),
+        None => (None, s),
+    };
+    let width = match width.trim().parse::<usize>() {
+        Ok(width) if width >= 4 => width,
+        _ => return Err(format!("'{}' is not a column width of at least 4 (e.g. 40 or message=60)", width)),
+    };
+    Ok(match name {
+        Some(name) => ColumnWidth::Column(name.to_string(), width),
+        None => ColumnWidth::All(width),
+    })
+}
 
-    (String::new(), String::new(), line.to_string())
+/// Caps columns at their `--col-width`, before `fit_columns` fits the rest.
+/// A `FIELD=N` entry wins over a plain `N`, which leaves the timestamp and
+/// log group columns alone. The last entry for a column counts.
+pub fn cap_columns(output: &mut FormattedOutput, widths: &[ColumnWidth]) {
+    for (index, col) in output.columns.iter_mut().enumerate() {
+        let named = widths.iter().rev().find_map(|width| match width {
+            ColumnWidth::Column(name, width) if *name == col.name => Some(*width),
+            _ => None,
+        });
+        let all = widths.iter().rev().find_map(|width| match width {
+            ColumnWidth::All(width) if index >= 2 => Some(*width),
+            _ => None,
+        });
+        if let Some(cap) = named.or(all) {
+            col.max_width = col.max_width.min(cap);
+        }
+    }
+}
+
+/// Splits a `[timestamp] [stream] payload` line into its parts. Missing
+/// bracketed parts come back empty. Brackets inside a part, as in Lambda's
+/// `2024/01/01/[$LATEST]abc` stream names, are matched, and the payload
+/// keeps any embedded newlines.
+pub fn parse_log_line(line: &str) -> (String, String, String) {
+    let Some((timestamp, rest)) = split_bracketed(line) else {
+        return (String::new(), String::new(), line.to_string());
+    };
+
+    let rest = rest.trim();
+    match split_bracketed(rest) {
+        Some((stream, payload)) => (timestamp.to_string(), stream.to_string(), payload.trim().to_string()),
+        None => (timestamp.to_string(), String::new(), rest.to_string()),
+    }
+}
+
+/// Splits `[inner] rest` at the bracket closing the leading one. The closing
+/// bracket has to be on the first line.
+fn split_bracketed(text: &str) -> Option<(&str, &str)> {
+    if !text.starts_with('[') {
+        return None;
+    }
+
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 1 => return Some((&text[1..i], &text[i + 1..])),
+            ']' => depth -= 1,
+            '\n' => return None,
+            _ => {}
+        }
+    }
+    None
 }
 
 fn truncate_string(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
+    if s.chars().count() <= max_len {
         s.to_string()
     } else {
-        format!("{}...", &s[..max_len - 3])
+        let kept: String = s.chars().take(max_len.saturating_sub(3)).collect();
+        format!("{}...", kept)
     }
 }
 
-pub fn print_formatted_table(output: &FormattedOutput) {
+fn pad_to_width(rendered: &str, width: usize) -> String {
+    let padding = width.saturating_sub(visible_width(rendered));
+    format!("{}{}", rendered, " ".repeat(padding))
+}
+
+pub fn render_formatted_table(output: &FormattedOutput, color_levels: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+
     let mut header = Vec::new();
     for col in &output.columns {
-        header.push(format!("{:width$}", col.name.bright_cyan().bold(), width = col.max_width));
+        let name = truncate_string(&col.name, col.max_width);
+        header.push(pad_to_width(&name.bright_cyan().bold().to_string(), col.max_width));
     }
-    println!("{}", header.join(" │ "));
+    lines.push(header.join(" │ "));
 
     let separator: Vec<String> = output.columns.iter()
         .map(|col| "─".repeat(col.max_width))
         .collect();
-    println!("{}", separator.join("─┼─").bright_black());
+    lines.push(separator.join("─┼─").bright_black().to_string());
 
-    for row in &output.rows {
+    for (row_index, row) in output.rows.iter().enumerate() {
+        let lagging = output.lagging.get(row_index).copied().unwrap_or(false);
         let mut formatted_row = Vec::new();
         for (i, value) in row.iter().enumerate() {
             let width = output.columns[i].max_width;
+            // A newline inside a cell would break the row
+            let value = &truncate_string(&value.replace("\r\n", "\n").replace('\n', NEWLINE_MARKER), width);
             let formatted_value = if i < 2 {
-                format!("{:width$}", value.bright_blue(), width = width)
-            } else if value.is_empty() {
-                format!("{:width$}", "", width = width)
+                pad_to_width(&value.bright_blue().to_string(), width)
+            } else if lagging && output.columns[i].name == INGESTION_DELAY_COLUMN {
+                pad_to_width(&value.red().bold().to_string(), width)
+            } else if color_levels && LEVEL_COLUMNS.contains(&output.columns[i].name.as_str()) {
+                pad_to_width(&colorize_log_level(value), width)
             } else {
-                format!("{:width$}", value, width = width)
+                pad_to_width(value, width)
             };
             formatted_row.push(formatted_value);
         }
-        println!("{}", formatted_row.join(" │ "));
+        lines.push(formatted_row.join(" │ "));
+    }
+
+    lines
+}
+
+/// Renders the table as RFC 4180 CSV: a header row of column names followed
+/// by one record per event, with full (untruncated) values.
+pub fn render_csv(output: &FormattedOutput) -> anyhow::Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer.write_record(output.columns.iter().map(|col| col.name.as_str()))?;
+    for row in &output.rows {
+        writer.write_record(row)?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+pub fn print_formatted_table(
+    output: &FormattedOutput,
+    quiet: bool,
+    color_levels: bool,
+    out: &mut TeeWriter,
+) {
+    for line in render_formatted_table(output, color_levels) {
+        out.write_line(&line);
+    }
+
+    if !quiet {
+        println!("\n{} columns, {} rows",
+            format_count(output.columns.len()).bright_yellow(),
+            format_count(output.rows.len()).bright_yellow()
+        );
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_alignment_ignores_ansi_codes() {
+        // Cells are padded by visible width, so color codes don't shift the
+        // separators. Checked on literal escapes rather than by forcing the
+        // process-wide color override, which would leak into other tests.
+        let red = "\x1b[31mERROR\x1b[0m";
+        assert_eq!(visible_width(red), 5);
+        assert_eq!(strip_ansi(&pad_to_width(red, 8)), "ERROR   ");
 
-    println!("\n{} columns, {} rows",
-        output.columns.len().to_string().bright_yellow(),
-        output.rows.len().to_string().bright_yellow()
-    );
-}
\ No newline at end of file
+        let logs = vec![
+            r#"[2024-01-01 10:00:00.000] [app] {"level":"ERROR","msg":"boom"}"#.to_string(),
+            r#"[2024-01-01 10:00:01.000] [app-long-stream] {"level":"INFO","msg":"ok"}"#.to_string(),
+        ];
+        let output = analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false);
+        let lines: Vec<String> = render_formatted_table(&output, true)
+            .iter()
+            .map(|line| strip_ansi(line))
+            .collect();
+
+        let separators = |line: &str| -> Vec<usize> {
+            line.chars()
+                .enumerate()
+                .filter(|(_, c)| *c == '│' || *c == '┼')
+                .map(|(i, _)| i)
+                .collect()
+        };
+
+        let expected = separators(&lines[0]);
+        assert_eq!(expected.len(), output.columns.len() - 1);
+        for line in &lines[1..] {
+            assert_eq!(separators(line), expected);
+        }
+    }
+
+    #[test]
+    fn test_double_encoded_payload_is_flattened() {
+        let inner = r#"{"level":"WARN","request":{"id":"r-1"}}"#;
+        let once = serde_json::to_string(inner).unwrap();
+        let twice = serde_json::to_string(&once).unwrap();
+
+        for payload in [&once, &twice] {
+            let logs = vec![format!("[2024-01-01 10:00:00.000] [app] {}", payload)];
+            let output = analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false);
+            let names: Vec<&str> = output.columns.iter().map(|c| c.name.as_str()).collect();
+
+            assert!(names.contains(&"level"));
+            assert!(names.contains(&"request.id"));
+        }
+
+        assert_eq!(parse_json_payload(r#""not json""#), Some(Value::String("not json".to_string())));
+    }
+
+    #[test]
+    fn test_parse_logfmt() {
+        let parsed = parse_logfmt(r#"level=info msg="hello \"big\" world" dur=12ms empty="""#).unwrap();
+
+        assert_eq!(parsed["level"], "info");
+        assert_eq!(parsed["msg"], r#"hello "big" world"#);
+        assert_eq!(parsed["dur"], "12ms");
+        assert_eq!(parsed["empty"], "");
+
+        assert_eq!(parse_logfmt("plain text line"), None);
+        assert_eq!(parse_logfmt(r#"msg="unterminated"#), None);
+    }
+
+    #[test]
+    fn test_format_hint_selects_parser() {
+        let logs = vec![r#"[2024-01-01 10:00:00.000] [app] level=warn msg="disk almost full""#.to_string()];
+
+        let auto = analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false);
+        assert!(auto.columns.iter().any(|c| c.name == "msg"));
+
+        let json_only = analyze_json_logs(&logs, FormatHint::Json, DEFAULT_FLATTEN_DEPTH, false, false);
+        assert_eq!(json_only.columns.len(), 2);
+    }
+
+    #[test]
+    fn test_fit_columns_shrinks_least_frequent_first() {
+        let column = |name: &str, frequency, max_width| ColumnInfo {
+            name: name.to_string(),
+            frequency,
+            max_width,
+        };
+        let mut output = FormattedOutput {
+            columns: vec![column("timestamp", 3, 23), column("msg", 3, 60), column("stack", 1, 300)],
+            rows: Vec::new(),
+            lagging: Vec::new(),
+        };
+
+        fit_columns(&mut output, Some(120));
+        let widths: Vec<usize> = output.columns.iter().map(|c| c.max_width).collect();
+        assert_eq!(widths, vec![23, 60, 31]);
+
+        fit_columns(&mut output, Some(40));
+        let widths: Vec<usize> = output.columns.iter().map(|c| c.max_width).collect();
+        assert_eq!(widths, vec![14, 10, 10]);
+
+        let mut wide = FormattedOutput {
+            columns: vec![column("stack", 1, 300)],
+            rows: Vec::new(),
+            lagging: Vec::new(),
+        };
+        fit_columns(&mut wide, None);
+        assert_eq!(wide.columns[0].max_width, MAX_COLUMN_WIDTH);
+    }
+
+    #[test]
+    fn test_cap_columns_prefers_named_widths() {
+        let column = |name: &str, max_width| ColumnInfo { name: name.to_string(), frequency: 1, max_width };
+        let mut output = FormattedOutput {
+            columns: vec![column("timestamp", 23), column("log_group", 30), column("message", 90), column("url", 70), column("id", 8)],
+            rows: vec![vec![String::new(), String::new(), String::new(), "https://example.com/a/very/long/path".to_string(), String::new()]],
+            lagging: Vec::new(),
+        };
+        let widths: Vec<ColumnWidth> = ["20", "message=60", "url=12"].iter()
+            .map(|entry| parse_column_width(entry).unwrap())
+            .collect();
+
+        cap_columns(&mut output, &widths);
+        let capped: Vec<usize> = output.columns.iter().map(|c| c.max_width).collect();
+        assert_eq!(capped, vec![23, 30, 60, 12, 8]);
+        let rendered = strip_ansi(&render_formatted_table(&output, false)[2]);
+        assert!(rendered.contains("https://e..."), "{}", rendered);
+
+        assert!(parse_column_width("=5").is_err());
+        assert!(parse_column_width("url=3").is_err());
+    }
+
+    #[test]
+    fn test_csv_round_trips_special_characters() {
+        let message = "say \"a,b\nc\"";
+        let logs = vec![format!("[2024-01-01 10:00:00.000] [app] {}", serde_json::json!({ "msg": message }))];
+        let rendered = render_csv(&analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false)).unwrap();
+
+        let mut reader = csv::Reader::from_reader(rendered.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        let records: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+
+        assert_eq!(&headers, vec!["timestamp", "log_group", "msg"]);
+        assert_eq!(records.len(), 1);
+        assert_eq!(&records[0][2], message);
+    }
+
+    #[test]
+    fn test_embedded_ansi_codes_are_stripped() {
+        let logs = vec![
+            "[2024-01-01 10:00:00.000] [app] level=error msg=\"\x1b[31mdisk failure\x1b[0m\"".to_string(),
+            r#"[2024-01-01 10:00:01.000] [app] {"level":"\u001b[32minfo\u001b[0m"}"#.to_string(),
+        ];
+        let output = analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false);
+
+        let level = output.columns.iter().position(|c| c.name == "level").unwrap();
+        let msg = output.columns.iter().position(|c| c.name == "msg").unwrap();
+
+        assert_eq!(output.rows[0][msg], "disk failure");
+        assert_eq!(output.rows[1][level], "info");
+        assert_eq!(output.columns[msg].max_width, "disk failure".len());
+        assert_eq!(output.columns[level].max_width, "level".len());
+    }
+
+    #[test]
+    fn test_flatten_depth_keeps_deep_subtrees_as_json() {
+        let value: Value = serde_json::from_str(r#"{"a":{"b":{"c":{"d":1}}},"tags":["x"],"n":null}"#).unwrap();
+
+        let columns = flatten_json_to_columns(&value, "", 2);
+        assert_eq!(columns.get("a.b").map(String::as_str), Some(r#"{"c":{"d":1}}"#));
+        assert_eq!(columns.get("tags[0]").map(String::as_str), Some("x"));
+        assert_eq!(columns.get("n").map(String::as_str), Some(""));
+
+        let columns = flatten_json_to_columns(&value, "", usize::MAX);
+        assert_eq!(columns.get("a.b.c.d").map(String::as_str), Some("1"));
+
+        // --compact: one column per top-level key
+        let columns = flatten_json_to_columns(&value, "", 1);
+        assert_eq!(columns.get("a").map(String::as_str), Some(r#"{"b":{"c":{"d":1}}}"#));
+    }
+
+    #[test]
+    fn test_infer_schema_reports_types_per_field() {
+        let messages = [
+            r#"{"level":"INFO","status":200,"items":[{"id":1},{"id":"b"}]}"#,
+            r#"{"level":"ERROR","status":"timeout","ok":false}"#,
+            "plain text",
+            r#""{\"level\":\"WARN\"}""#,
+        ];
+        let (fields, json_messages) = infer_schema(messages, DEFAULT_FLATTEN_DEPTH);
+        assert_eq!(json_messages, 3);
+
+        let names: Vec<&str> = fields.iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(names, ["level", "status", "items[].id", "ok"]);
+        assert_eq!(fields[0].frequency, 3);
+        assert_eq!(fields[0].example, "INFO");
+        assert_eq!(fields[1].types, BTreeSet::from(["number", "string"]));
+        assert_eq!(fields[2].frequency, 1);
+        assert_eq!(fields[2].types, BTreeSet::from(["number", "string"]));
+        assert_eq!(fields[3].types, BTreeSet::from(["boolean"]));
+    }
+
+    #[test]
+    fn test_lambda_platform_lines() {
+        let report = "REPORT RequestId: 3a1b7c9e-5f2d-4e8a-9b1c-0d2e3f4a5b6c\tDuration: 2.16 ms\tBilled Duration: 3 ms\tMemory Size: 128 MB\tMax Memory Used: 69 MB\tInit Duration: 181.31 ms\t\n";
+        let parsed = parse_lambda_platform_line(report).unwrap();
+        assert_eq!(parsed["event"], "REPORT");
+        assert_eq!(parsed["request_id"], "3a1b7c9e-5f2d-4e8a-9b1c-0d2e3f4a5b6c");
+        assert_eq!(parsed["duration_ms"], "2.16");
+        assert_eq!(parsed["billed_ms"], "3");
+        assert_eq!(parsed["memory_mb"], "128");
+        assert_eq!(parsed["max_memory_used_mb"], "69");
+        assert_eq!(parsed["init_duration_ms"], "181.31");
+
+        let start = parse_lambda_platform_line("START RequestId: 8f5c2a1e-1111-2222-3333-444455556666 Version: $LATEST").unwrap();
+        assert_eq!(start["version"], "$LATEST");
+        assert_eq!(start["request_id"], "8f5c2a1e-1111-2222-3333-444455556666");
+
+        assert!(parse_lambda_platform_line("END RequestId: 8f5c2a1e-1111-2222-3333-444455556666").is_some());
+        assert!(parse_lambda_platform_line("REPORT generated in 5 ms").is_none());
+
+        let logs = vec![format!("[2024-01-01 10:00:00.000] [app] {}", report.trim())];
+        let names = |lambda| -> Vec<String> {
+            analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, lambda, false)
+                .columns.into_iter().map(|c| c.name).collect()
+        };
+        assert!(names(true).contains(&"duration_ms".to_string()));
+        assert!(!names(false).contains(&"duration_ms".to_string()));
+    }
+
+    #[test]
+    fn test_ingestion_delay_column() {
+        let logs = vec![
+            r#"[2024-01-01 10:00:00.000] [app] {"msg":"a"}"#.to_string(),
+            r#"[2024-01-01 10:00:01.000] [app] {"msg":"b"}"#.to_string(),
+        ];
+        let mut output = analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false);
+        add_ingestion_delays(&mut output, &[Some(850), Some(12_300)], Some(5_000));
+
+        assert_eq!(output.columns[2].name, INGESTION_DELAY_COLUMN);
+        assert_eq!(output.rows[0][2], "850ms");
+        assert_eq!(output.rows[1][2], "12.3s");
+        assert_eq!(output.rows[1][3], "b");
+        assert_eq!(output.lagging, vec![false, true]);
+        assert_eq!(format_delay(245_000), "4m 05s");
+    }
+
+    #[test]
+    fn test_pin_columns_follow_timestamp_and_group() {
+        let logs = vec![
+            r#"[2024-01-01 10:00:00.000] [app] {"a":"1","b":"2","user":{"id":"u1"}}"#.to_string(),
+            r#"[2024-01-01 10:00:01.000] [app] {"a":"3","b":"4"}"#.to_string(),
+        ];
+        let mut output = analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false);
+        pin_columns(&mut output, &["user.id".to_string(), "missing".to_string(), "b".to_string()]);
+
+        let names: Vec<&str> = output.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["timestamp", "log_group", "user.id", "b", "a"]);
+        assert_eq!(output.rows[0][2..], ["u1", "2", "1"]);
+        assert_eq!(output.rows[1][2..], ["", "4", "3"]);
+    }
+
+    #[test]
+    fn test_prune_sparse_columns() {
+        let mut logs: Vec<String> = (0..19)
+            .map(|i| format!(r#"[2024-01-01 10:00:00.000] [app] {{"msg":"m{}"}}"#, i))
+            .collect();
+        logs.push(r#"[2024-01-01 10:00:01.000] [app] {"msg":"rare","debug":"x","trace":"y"}"#.to_string());
+        let mut output = analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false);
+
+        assert_eq!(prune_sparse_columns(&mut output, 5.0, &[]), 0);
+        assert_eq!(prune_sparse_columns(&mut output, 10.0, &["trace".to_string()]), 1);
+
+        let names: Vec<&str> = output.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["timestamp", "log_group", "msg", "trace"]);
+        assert_eq!(output.rows[19][2..], ["rare", "y"]);
+        assert_eq!(parse_percent("5%"), Ok(5.0));
+        assert!(parse_percent("150").is_err());
+    }
+
+    #[test]
+    fn test_emf_document() {
+        let emf = r#"{"_aws":{"Timestamp":1700000000000,"CloudWatchMetrics":[{"Namespace":"checkout","Dimensions":[["service","stage"]],"Metrics":[{"Name":"latency","Unit":"Milliseconds"},{"Name":"orders","Unit":"Count"},{"Name":"ratio","Unit":"None"}]}]},"service":"api","stage":"prod","latency":42.5,"orders":3,"ratio":0.9,"requestId":"r-1"}"#;
+        let parsed = parse_emf(&serde_json::from_str(emf).unwrap(), DEFAULT_FLATTEN_DEPTH).unwrap();
+        assert_eq!(parsed["metrics"], "checkout/latency = 42.5 Milliseconds, checkout/orders = 3 Count, checkout/ratio = 0.9");
+        assert_eq!(parsed["dimensions"], "service=api, stage=prod");
+        assert_eq!(parsed["requestId"], "r-1");
+        assert_eq!(parsed.len(), 3);
+
+        assert!(parse_emf(&serde_json::json!({"latency": 1}), DEFAULT_FLATTEN_DEPTH).is_none());
+
+        let logs = vec![format!("[2024-01-01 10:00:00.000] [app] {}", emf)];
+        let names: Vec<String> = analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, true)
+            .columns.into_iter().map(|c| c.name).collect();
+        assert!(names.contains(&"metrics".to_string()));
+        assert!(!names.iter().any(|name| name.starts_with("_aws")));
+    }
+
+    #[test]
+    fn test_multi_line_messages() {
+        let line = "[2024-01-01 10:00:00.000] [2024/01/01/[$LATEST]abc123] Error: boom\n    at handler (index.js:3)";
+        let (timestamp, stream, message) = parse_log_line(line);
+        assert_eq!(timestamp, "2024-01-01 10:00:00.000");
+        assert_eq!(stream, "2024/01/01/[$LATEST]abc123");
+        assert_eq!(message, "Error: boom\n    at handler (index.js:3)");
+
+        let logs = vec![r#"[2024-01-01 10:00:00.000] [app] {"stack":"Error: boom\n    at handler (index.js:3)"}"#.to_string()];
+        let table = render_formatted_table(&analyze_json_logs(&logs, FormatHint::Auto, DEFAULT_FLATTEN_DEPTH, false, false), false);
+        assert_eq!(table.len(), 3);
+        assert!(strip_ansi(&table[2]).contains("Error: boom⏎    at handler"));
+    }
+
+    #[test]
+    fn test_field_timestamp_reads_application_time() {
+        assert_eq!(field_timestamp(r#"{"@timestamp":"2024-01-01T10:00:00.250Z","msg":"hi"}"#, "@timestamp"), Some(1_704_103_200_250));
+        assert_eq!(field_timestamp(r#"{"meta":{"ts":1704103200}}"#, "meta.ts"), Some(1_704_103_200_000));
+        assert_eq!(field_timestamp(r#"{"meta":{"ts":1704103200}}"#, "/meta/ts"), Some(1_704_103_200_000));
+        assert_eq!(field_timestamp(r#"{"time":"yesterday"}"#, "time"), None);
+        assert_eq!(field_timestamp("plain text", "time"), None);
+    }
+}
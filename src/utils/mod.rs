@@ -1,3 +1,11 @@
 pub mod format;
 pub mod time;
-pub mod json_formatter;
\ No newline at end of file
+pub mod json_formatter;
+pub mod tee;
+pub mod dedup;
+pub mod output;
+pub mod filter;
+pub mod pager;
+pub mod sample;
+pub mod checkpoint;
+pub mod progress;
\ No newline at end of file
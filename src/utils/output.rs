@@ -0,0 +1,120 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use clap::ValueEnum;
+use crate::utils::json_formatter::parse_json_payload;
+use serde_json::Value;
+
+/// Output mode selected with the global `--output` flag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable, colored output
+    #[default]
+    Colored,
+    /// A JSON array of events; errors are reported as JSON on stderr
+    Json,
+    /// One compact JSON object per line, for piping into jq
+    Ndjson,
+    /// Only each message, verbatim
+    Raw,
+    /// RFC 4180 CSV with one column per discovered field
+    Csv,
+    /// A YAML document per event, with JSON messages as nested maps
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Whether this is `json` or `ndjson`.
+    pub fn is_json(self) -> bool {
+        matches!(self, Self::Json | Self::Ndjson)
+    }
+}
+
+/// Renders `records` as a pretty-printed JSON array, or for `ndjson` as one
+/// compact object per line.
+pub fn render_json<T: Serialize>(records: &[T], output: OutputFormat) -> Result<String> {
+    if output != OutputFormat::Ndjson {
+        return Ok(serde_json::to_string_pretty(records)?);
+    }
+    let lines = records.iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(lines.join("\n"))
+}
+
+/// Renders event records (`timestamp`, `stream` and `message`) for `json`,
+/// `ndjson` or `yaml` output. In JSON a message holding a JSON object or
+/// array is also included parsed, as `parsed`.
+pub fn render_events(mut records: Vec<Value>, output: OutputFormat) -> Result<String> {
+    if output == OutputFormat::Yaml {
+        return render_yaml(records);
+    }
+    for record in &mut records {
+        if let Some(payload) = message_payload(record) {
+            record["parsed"] = payload;
+        }
+    }
+    render_json(&records, output)
+}
+
+/// The message of an event record parsed, when it's a JSON object or array.
+fn message_payload(record: &Value) -> Option<Value> {
+    record.get("message")
+        .and_then(Value::as_str)
+        .and_then(parse_json_payload)
+        .filter(|payload| payload.is_object() || payload.is_array())
+}
+
+/// Renders event records (`timestamp`, `stream` and `message`) as a YAML
+/// document stream, one `---` document per event. Messages holding JSON are
+/// embedded as maps and sequences rather than quoted strings.
+pub fn render_yaml(records: Vec<Value>) -> Result<String> {
+    let mut yaml = String::new();
+    for mut record in records {
+        if let Some(payload) = message_payload(&record) {
+            record["message"] = payload;
+        }
+        yaml.push_str("---\n");
+        yaml.push_str(&serde_norway::to_string(&record)?);
+    }
+    Ok(yaml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[test]
+    fn test_ndjson_puts_one_event_per_line() {
+        let records = vec![
+            serde_json::json!({"timestamp": "2024-01-01T10:00:00.000Z", "stream": "web", "message": r#"{"level":"error"}"#}),
+            serde_json::json!({"timestamp": "2024-01-01T10:00:01.000Z", "stream": "web", "message": "line\nbreak"}),
+        ];
+        let ndjson = render_events(records, OutputFormat::Ndjson).unwrap();
+
+        let lines: Vec<Value> = ndjson.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["parsed"], serde_json::json!({"level": "error"}));
+        assert_eq!(lines[1]["message"], "line\nbreak");
+        assert!(lines[1].get("parsed").is_none());
+        assert_eq!(render_events(Vec::new(), OutputFormat::Json).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_yaml_round_trip() {
+        let records = vec![
+            serde_json::json!({"timestamp": "2024-01-01T10:00:00.000Z", "stream": "web", "message": r#"{"level":"error","user":{"id":7},"tags":["a","b"]}"#}),
+            serde_json::json!({"timestamp": "2024-01-01T10:00:01.000Z", "stream": "web", "message": "plain: text with a colon"}),
+        ];
+        let yaml = render_yaml(records).unwrap();
+
+        let parsed: Vec<Value> = serde_norway::Deserializer::from_str(&yaml)
+            .map(|document| Value::deserialize(document).unwrap())
+            .collect();
+        assert_eq!(parsed, [
+            serde_json::json!({"timestamp": "2024-01-01T10:00:00.000Z", "stream": "web", "message": {"level": "error", "user": {"id": 7}, "tags": ["a", "b"]}}),
+            serde_json::json!({"timestamp": "2024-01-01T10:00:01.000Z", "stream": "web", "message": "plain: text with a colon"}),
+        ]);
+    }
+}
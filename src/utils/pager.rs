@@ -0,0 +1,70 @@
+use colored::Colorize;
+use std::io::{ErrorKind, IsTerminal, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// Used when `$PAGER` is unset. `-R` keeps colors intact.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Output piped through the user's `$PAGER`.
+pub struct Pager {
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Pager {
+    /// Starts the pager when stdout is a terminal. Returns `None` when not
+    /// paging, including when the pager can't be started (after a warning),
+    /// so output falls back to stdout.
+    pub fn spawn() -> Option<Self> {
+        if !std::io::stdout().is_terminal() {
+            return None;
+        }
+
+        let command = std::env::var("PAGER")
+            .ok()
+            .filter(|pager| !pager.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+        let mut parts = command.split_whitespace();
+        let program = parts.next()?;
+
+        match Command::new(program).args(parts).stdin(Stdio::piped()).spawn() {
+            Ok(mut child) => {
+                let stdin = child.stdin.take();
+                Some(Self { child, stdin })
+            }
+            Err(e) => {
+                eprintln!("{} Failed to start pager '{}': {}. Printing directly instead.",
+                    "Warning:".yellow().bold(),
+                    command,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Writes a line to the pager. Once the pager has been quit, further
+    /// lines are silently dropped.
+    pub fn write_line(&mut self, line: &str) {
+        if let Some(ref mut stdin) = self.stdin {
+            if let Err(e) = writeln!(stdin, "{}", line) {
+                if e.kind() != ErrorKind::BrokenPipe {
+                    eprintln!("{} Failed to write to pager: {}", "Warning:".yellow().bold(), e);
+                }
+                self.stdin = None;
+            }
+        }
+    }
+
+    /// Closes the pager's input and waits for the user to quit it.
+    pub fn finish(&mut self) {
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
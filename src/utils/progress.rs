@@ -0,0 +1,63 @@
+use clap::ValueEnum;
+use indicatif::ProgressBar;
+use std::io::IsTerminal;
+
+/// When to draw spinners and progress bars, selected with `--progress`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// Print plain status lines instead
+    Never,
+    /// Only on an interactive terminal outside CI
+    #[default]
+    Auto,
+    /// Even when `$CI` is set
+    Always,
+}
+
+impl ProgressMode {
+    pub fn draws(self) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Auto => std::io::stdout().is_terminal() && !in_ci(std::env::var("CI").ok().as_deref()),
+            Self::Always => true,
+        }
+    }
+
+    /// The bar from `bar` with `message` set when progress is drawn.
+    /// Otherwise a hidden bar, with `message` printed once to stderr (where
+    /// the bar would have been drawn) unless `quiet`, so CI logs stay readable.
+    pub fn bar(self, quiet: bool, message: &str, bar: impl FnOnce() -> ProgressBar) -> ProgressBar {
+        if quiet {
+            return ProgressBar::hidden();
+        }
+        if !self.draws() {
+            eprintln!("{}", message);
+            return ProgressBar::hidden();
+        }
+        let bar = bar();
+        bar.set_message(message.to_string());
+        bar
+    }
+}
+
+/// CI services set `CI`, usually to `true`; `CI=false` or `CI=0` opts out.
+fn in_ci(value: Option<&str>) -> bool {
+    value.is_some_and(|value| !matches!(value.trim().to_ascii_lowercase().as_str(), "" | "0" | "false"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_ci_accepts_any_truthy_value() {
+        assert!(in_ci(Some("true")));
+        assert!(in_ci(Some("1")));
+        assert!(in_ci(Some("woodpecker")));
+        assert!(!in_ci(Some("false")));
+        assert!(!in_ci(Some("0")));
+        assert!(!in_ci(Some("")));
+        assert!(!in_ci(None));
+        assert!(!ProgressMode::Never.draws());
+    }
+}
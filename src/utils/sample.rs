@@ -0,0 +1,99 @@
+/// How `--sample`/`--sample-n` thin out results.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// Keep each event independently with this probability
+    Rate(f64),
+    /// Keep exactly this many events (or all of them, if there are fewer),
+    /// chosen uniformly
+    Count(usize),
+}
+
+/// Downsamples a stream of items one at a time. Rate sampling keeps only the
+/// chosen items; count sampling uses a reservoir (Algorithm R), so at most
+/// `n` items are ever held. Kept items come back in their original order.
+pub struct Sampler<T> {
+    sampling: Sampling,
+    rng: fastrand::Rng,
+    seen: usize,
+    kept: Vec<(usize, T)>,
+}
+
+impl<T> Sampler<T> {
+    /// A fixed `seed` makes the selection reproducible across runs over the
+    /// same input.
+    pub fn new(sampling: Sampling, seed: Option<u64>) -> Self {
+        Self {
+            sampling,
+            rng: seed.map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed),
+            seen: 0,
+            kept: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        let index = self.seen;
+        self.seen += 1;
+
+        match self.sampling {
+            Sampling::Rate(rate) => {
+                if self.rng.f64() < rate {
+                    self.kept.push((index, item));
+                }
+            }
+            Sampling::Count(n) if self.kept.len() < n => self.kept.push((index, item)),
+            Sampling::Count(n) => {
+                let slot = self.rng.usize(..=index);
+                if slot < n {
+                    self.kept[slot] = (index, item);
+                }
+            }
+        }
+    }
+
+    /// How many items have been pushed.
+    pub fn seen(&self) -> usize {
+        self.seen
+    }
+
+    pub fn finish(mut self) -> Vec<T> {
+        self.kept.sort_by_key(|(index, _)| *index);
+        self.kept.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+/// Parses a `--sample` rate: a fraction in (0, 1].
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate <= 1.0 => Ok(rate),
+        _ => Err(format!("'{}' is not a sampling rate between 0 and 1 (e.g. 0.01 for 1%)", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(sampling: Sampling, seed: u64, count: usize) -> Vec<usize> {
+        let mut sampler = Sampler::new(sampling, Some(seed));
+        (0..count).for_each(|i| sampler.push(i));
+        sampler.finish()
+    }
+
+    #[test]
+    fn test_reservoir_keeps_exactly_n_in_order() {
+        let kept = sample(Sampling::Count(10), 7, 1000);
+        assert_eq!(kept.len(), 10);
+        assert!(kept.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(kept, sample(Sampling::Count(10), 7, 1000));
+
+        assert_eq!(sample(Sampling::Count(10), 7, 3), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_rate_sampling_is_reproducible() {
+        let kept = sample(Sampling::Rate(0.1), 42, 10_000);
+        assert!((800..1200).contains(&kept.len()));
+        assert_eq!(kept, sample(Sampling::Rate(0.1), 42, 10_000));
+        assert!(sample(Sampling::Rate(0.0), 42, 100).is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{Result, Context};
+use colored::Colorize;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::utils::format::strip_ansi;
+use crate::utils::pager::Pager;
+
+/// Prints rendered lines to stdout (or a pager) and, when an output file is
+/// configured, appends an ANSI-stripped copy of each line to it.
+pub struct TeeWriter {
+    file: Option<BufWriter<File>>,
+    path: PathBuf,
+    flush_each_line: bool,
+    pager: Option<Pager>,
+}
+
+impl TeeWriter {
+    pub fn new(path: Option<&Path>, flush_each_line: bool) -> Result<Self> {
+        let file = match path {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open output file: {}", path.display()))?;
+                Some(BufWriter::new(file))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            file,
+            path: path.map(Path::to_path_buf).unwrap_or_default(),
+            flush_each_line,
+            pager: None,
+        })
+    }
+
+    /// Sends terminal output through `pager` instead of stdout.
+    pub fn with_pager(mut self, pager: Option<Pager>) -> Self {
+        self.pager = pager;
+        self
+    }
+
+    pub fn write_line(&mut self, line: &str) {
+        self.print_line(line);
+        self.write_file_line(line);
+    }
+
+    /// Prints a line to the terminal only. The output file is left untouched.
+    pub fn print_line(&mut self, line: &str) {
+        match self.pager {
+            Some(ref mut pager) => pager.write_line(line),
+            None => println!("{}", line),
+        }
+    }
+
+    /// Replaces the previously printed terminal line. The output file is left
+    /// untouched.
+    pub fn rewrite_last_line(&mut self, line: &str) {
+        match self.pager {
+            Some(ref mut pager) => pager.write_line(line),
+            None => println!("\x1b[1A\x1b[2K{}", line),
+        }
+    }
+
+    /// Waits for the user to quit the pager, if any, so that anything
+    /// printed afterwards isn't hidden behind it.
+    pub fn close_pager(&mut self) {
+        self.pager = None;
+    }
+
+    /// Appends a line to the output file only.
+    pub fn write_file_line(&mut self, line: &str) {
+        if let Some(ref mut file) = self.file {
+            let mut result = writeln!(file, "{}", strip_ansi(line));
+            if result.is_ok() && self.flush_each_line {
+                result = file.flush();
+            }
+
+            if let Err(e) = result {
+                self.disable(e);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(ref mut file) = self.file {
+            if let Err(e) = file.flush() {
+                self.disable(e);
+            }
+        }
+    }
+
+    /// Stops writing to the output file after an I/O error (e.g. a full disk)
+    /// so that terminal output keeps working.
+    fn disable(&mut self, error: std::io::Error) {
+        eprintln!("{} Failed to write to {}: {}. Continuing without output file.",
+            "Warning:".yellow().bold(),
+            self.path.display(),
+            error
+        );
+        self.file = None;
+    }
+}
+
+impl Drop for TeeWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
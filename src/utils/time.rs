@@ -1,20 +1,32 @@
 use anyhow::{Result, Context, bail};
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, Local, Utc};
+use chrono::format::{Item, StrftimeItems};
+use chrono_tz::Tz;
+use colored::Colorize;
 use regex::Regex;
+use crate::error::CwlError;
 
 pub fn parse_time_range(
     since: Option<String>,
     start: Option<String>,
     end: Option<String>,
+    default_since: Option<&str>,
 ) -> Result<(Option<i64>, Option<i64>)> {
     let mut start_time = None;
     let mut end_time = None;
+    let now = Utc::now().timestamp_millis();
+
+    if since.is_some() && (start.is_some() || end.is_some()) {
+        bail!(CwlError::InvalidArgument(
+            "--since can't be combined with --start/--end; use --since for a window ending now, or --start/--end for a fixed range".to_string()
+        ));
+    }
 
     if let Some(since_str) = since {
         let duration = parse_duration(&since_str)
             .context("Invalid duration format. Use formats like '1h', '30m', '2d'")?;
-        start_time = Some(Utc::now().timestamp_millis() - duration.num_milliseconds());
-        end_time = Some(Utc::now().timestamp_millis());
+        start_time = Some(now - duration.num_milliseconds());
+        end_time = Some(now);
     } else {
         if let Some(start_str) = start {
             start_time = Some(parse_timestamp(&start_str)
@@ -27,42 +39,218 @@ pub fn parse_time_range(
         }
     }
 
+    if let (Some(start), Some(end)) = (start_time, end_time) {
+        if start > end {
+            bail!(CwlError::InvalidArgument(format!(
+                "--start ({}) is after --end ({}); swap them or adjust the range",
+                format_timestamp(start, Tz::UTC, "%Y-%m-%d %H:%M:%S").unwrap_or_default(),
+                format_timestamp(end, Tz::UTC, "%Y-%m-%d %H:%M:%S").unwrap_or_default()
+            )));
+        }
+    }
+
     if start_time.is_none() && end_time.is_none() {
-        start_time = Some(Utc::now().timestamp_millis() - 3600000);
-        end_time = Some(Utc::now().timestamp_millis());
+        let window = match default_since {
+            Some(default_str) => parse_duration(default_str)
+                .context("Invalid default_since in config")?
+                .num_milliseconds(),
+            None => 3600000,
+        };
+        start_time = Some(now - window);
+        end_time = Some(now);
+    }
+
+    if let Some(warning) = range_warning(start_time, end_time, now) {
+        eprintln!("{} {}", "Warning:".yellow().bold(), warning);
     }
 
     Ok((start_time, end_time))
 }
 
+/// Flags ranges that are valid but can't match anything useful.
+fn range_warning(start: Option<i64>, end: Option<i64>, now: i64) -> Option<String> {
+    match (start, end) {
+        (Some(start), Some(end)) if start == end => {
+            Some("The time range is empty (--start equals --end), so only events at exactly that millisecond match".to_string())
+        }
+        (Some(start), _) if start > now => {
+            Some("The time range starts in the future, so no events will match yet".to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Parses a `START..END` window where each side is anything `--start`
+/// accepts (e.g. `2h ago..1h ago`). An empty end means now.
+pub fn parse_window(s: &str) -> Result<(i64, i64)> {
+    let Some((start, end)) = s.split_once("..") else {
+        bail!(CwlError::InvalidArgument(format!(
+            "Invalid window '{}'. Use START..END, e.g. '2h ago..1h ago'", s
+        )));
+    };
+
+    let start = parse_timestamp(start).context("Invalid window start")?;
+    let end = match end.trim() {
+        "" => Utc::now().timestamp_millis(),
+        end => parse_timestamp(end).context("Invalid window end")?,
+    };
+    if start >= end {
+        bail!(CwlError::InvalidArgument(format!("Window '{}' ends before it starts", s)));
+    }
+
+    Ok((start, end))
+}
+
+/// Picks the display timezone: the `--tz` flag, then the config default,
+/// then `$TZ`, falling back to UTC. An unparseable `$TZ` (e.g. a POSIX rule)
+/// is ignored rather than treated as an error. `local` names the system
+/// timezone (`--local`).
+pub fn resolve_timezone(flag: Option<&str>, config: Option<&str>) -> Result<Tz> {
+    if let Some(name) = flag.or(config) {
+        return parse_timezone(name);
+    }
+
+    Ok(std::env::var("TZ").ok()
+        .and_then(|name| name.trim_start_matches(':').parse().ok())
+        .unwrap_or(Tz::UTC))
+}
+
+fn parse_timezone(name: &str) -> Result<Tz> {
+    if name.eq_ignore_ascii_case("local") {
+        return Ok(local_timezone());
+    }
+    name.parse::<Tz>()
+        .map_err(|_| CwlError::InvalidArgument(format!("Unknown timezone: {}. Use an IANA name like 'America/New_York'", name)).into())
+}
+
+/// The system timezone: `$TZ`, then the zone `/etc/localtime` links to or
+/// `/etc/timezone` names, then an `Etc/GMT±N` zone matching the current
+/// `chrono::Local` offset (UTC if that isn't a whole number of hours).
+pub fn local_timezone() -> Tz {
+    std::env::var("TZ").ok()
+        .and_then(|name| name.trim_start_matches(':').parse().ok())
+        .or_else(|| std::fs::read_link("/etc/localtime").ok().and_then(|target| zone_from_path(&target.to_string_lossy())))
+        .or_else(|| std::fs::read_to_string("/etc/timezone").ok().and_then(|name| name.trim().parse().ok()))
+        .unwrap_or_else(|| fixed_offset_zone(Local::now().offset().local_minus_utc()))
+}
+
+/// The zone of a tzdata path like `/usr/share/zoneinfo/Europe/Berlin`.
+fn zone_from_path(path: &str) -> Option<Tz> {
+    path.split_once("zoneinfo/")?.1.parse().ok()
+}
+
+fn fixed_offset_zone(offset_seconds: i32) -> Tz {
+    if offset_seconds % 3600 != 0 {
+        return Tz::UTC;
+    }
+    // Etc zones are signed the POSIX way: UTC+2 is Etc/GMT-2
+    let name = match offset_seconds / 3600 {
+        0 => return Tz::UTC,
+        hours if hours > 0 => format!("Etc/GMT-{}", hours),
+        hours => format!("Etc/GMT+{}", -hours),
+    };
+    name.parse().unwrap_or(Tz::UTC)
+}
+
+/// Formats epoch milliseconds in `tz`, appending the zone abbreviation (`UTC`,
+/// `EST`) or offset so the output is unambiguous, unless `format` already
+/// includes an offset or is an epoch.
+pub fn format_timestamp(millis: i64, tz: Tz, format: &str) -> Option<String> {
+    let dt = DateTime::<Utc>::from_timestamp_millis(millis)?.with_timezone(&tz);
+    let zoned = ["%z", "%:z", "%::z", "%#z", "%Z", "%+", "%s"].iter().any(|spec| format.contains(spec));
+
+    Some(if zoned {
+        dt.format(format).to_string()
+    } else {
+        format!("{} {}", dt.format(format), dt.format("%Z"))
+    })
+}
+
+/// How event timestamps are printed (`--time-format`): a strftime string or
+/// one of the named presets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeFormat(String);
+
+impl TimeFormat {
+    pub const DEFAULT: &'static str = "%Y-%m-%d %H:%M:%S%.3f";
+
+    pub const PRESETS: [(&'static str, &'static str); 5] = [
+        ("default", Self::DEFAULT),
+        ("iso", "%Y-%m-%dT%H:%M:%S%.3f"),
+        ("rfc3339", "%Y-%m-%dT%H:%M:%S%.3f%:z"),
+        ("epoch", "%s%3f"),
+        ("short", "%H:%M:%S"),
+    ];
+
+    pub fn format(&self, millis: i64, tz: Tz) -> Option<String> {
+        format_timestamp(millis, tz, &self.0)
+    }
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        Self(Self::DEFAULT.to_string())
+    }
+}
+
+impl std::str::FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, format)) = Self::PRESETS.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+            return Ok(Self(format.to_string()));
+        }
+
+        let invalid = StrftimeItems::new(s).any(|item| matches!(item, Item::Error));
+        if invalid || !s.contains('%') {
+            let presets: Vec<&str> = Self::PRESETS.iter().map(|(name, _)| *name).collect();
+            return Err(format!("'{}' is not a strftime format or one of: {}", s, presets.join(", ")));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
 pub fn parse_duration(s: &str) -> Result<Duration> {
-    let re = Regex::new(r"^(\d+)([smhd])$")?;
+    let re = Regex::new(r"^(\d+)(ms|[smhd])$")?;
 
     if let Some(captures) = re.captures(s) {
         let value: i64 = captures[1].parse()?;
         let unit = &captures[2];
 
         let duration = match unit {
+            "ms" => Duration::milliseconds(value),
             "s" => Duration::seconds(value),
             "m" => Duration::minutes(value),
             "h" => Duration::hours(value),
             "d" => Duration::days(value),
-            _ => bail!("Invalid duration unit: {}", unit),
+            _ => bail!(CwlError::InvalidArgument(format!("Invalid duration unit: {}", unit))),
         };
 
         Ok(duration)
     } else {
-        bail!("Invalid duration format: {}. Use formats like '1h', '30m', '2d'", s)
+        bail!(CwlError::InvalidArgument(format!("Invalid duration format: {}. Use formats like '1h', '30m', '2d'", s)))
     }
 }
 
+/// Numeric epochs above this are treated as milliseconds rather than seconds.
+pub const EPOCH_MILLIS_THRESHOLD: i64 = 1_000_000_000_000;
+/// Numeric epochs above this (16+ digits) are treated as microseconds.
+pub const EPOCH_MICROS_THRESHOLD: i64 = 1_000_000_000_000_000;
+/// Numeric epochs above this (19 digits) are treated as nanoseconds.
+pub const EPOCH_NANOS_THRESHOLD: i64 = 1_000_000_000_000_000_000;
+
 pub fn parse_timestamp(s: &str) -> Result<i64> {
+    let s = s.trim();
+
+    if let Some(duration_str) = s.strip_suffix(" ago") {
+        let duration = parse_duration(duration_str.trim())?;
+        return Ok(Utc::now().timestamp_millis() - duration.num_milliseconds());
+    }
+
     if let Ok(ts) = s.parse::<i64>() {
-        if ts > 1_000_000_000_000 {
-            Ok(ts)
-        } else {
-            Ok(ts * 1000)
-        }
+        Ok(epoch_to_millis(ts))
+    } else if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        Ok(dt.with_timezone(&Utc).timestamp_millis())
     } else {
         let formats = [
             "%Y-%m-%d %H:%M:%S",
@@ -79,11 +267,25 @@ pub fn parse_timestamp(s: &str) -> Result<i64> {
             }
         }
 
-        if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
-            return Ok(DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc).timestamp_millis());
+        for format in &formats {
+            if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(s, format) {
+                return Ok(DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc).timestamp_millis());
+            }
         }
 
-        bail!("Could not parse timestamp: {}", s)
+        bail!(CwlError::InvalidArgument(format!("Could not parse timestamp: {}", s)))
+    }
+}
+
+fn epoch_to_millis(ts: i64) -> i64 {
+    if ts > EPOCH_NANOS_THRESHOLD {
+        ts / 1_000_000
+    } else if ts > EPOCH_MICROS_THRESHOLD {
+        ts / 1000
+    } else if ts > EPOCH_MILLIS_THRESHOLD {
+        ts
+    } else {
+        ts * 1000
     }
 }
 
@@ -97,9 +299,22 @@ mod tests {
         assert!(parse_duration("30m").is_ok());
         assert!(parse_duration("2h").is_ok());
         assert!(parse_duration("1d").is_ok());
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::milliseconds(250));
         assert!(parse_duration("invalid").is_err());
     }
 
+    #[test]
+    fn test_parse_time_range_default_window() {
+        let (start, end) = parse_time_range(None, None, None, None).unwrap();
+        assert_eq!(end.unwrap() - start.unwrap(), 3600000);
+
+        let (start, end) = parse_time_range(None, None, None, Some("6h")).unwrap();
+        assert_eq!(end.unwrap() - start.unwrap(), 6 * 3600000);
+
+        let (start, end) = parse_time_range(Some("30m".to_string()), None, None, Some("6h")).unwrap();
+        assert_eq!(end.unwrap() - start.unwrap(), 30 * 60000);
+    }
+
     #[test]
     fn test_parse_timestamp() {
         assert!(parse_timestamp("1234567890").is_ok());
@@ -107,4 +322,87 @@ mod tests {
         assert!(parse_timestamp("2024-01-01 12:00:00").is_ok());
         assert!(parse_timestamp("2024-01-01T12:00:00Z").is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_timestamp_rfc3339_offsets() {
+        let utc = parse_timestamp("2024-01-01T12:00:00Z").unwrap();
+        assert_eq!(parse_timestamp("2024-01-01T14:00:00+02:00").unwrap(), utc);
+        assert_eq!(parse_timestamp("2024-01-01T07:00:00-05:00").unwrap(), utc);
+        assert_eq!(parse_timestamp("2024-01-01T14:00:00.250+02:00").unwrap(), utc + 250);
+    }
+
+    #[test]
+    fn test_parse_timestamp_epoch_units() {
+        assert_eq!(parse_timestamp("1704110400").unwrap(), 1_704_110_400_000);
+        assert_eq!(parse_timestamp("1704110400123").unwrap(), 1_704_110_400_123);
+        assert_eq!(parse_timestamp("1704110400123456").unwrap(), 1_704_110_400_123);
+        assert_eq!(parse_timestamp("1704110400123456789").unwrap(), 1_704_110_400_123);
+    }
+
+    #[test]
+    fn test_parse_timestamp_ago() {
+        let before = Utc::now().timestamp_millis();
+        let ts = parse_timestamp("5m ago").unwrap();
+        let after = Utc::now().timestamp_millis();
+
+        assert!(ts >= before - 300_000 && ts <= after - 300_000);
+        assert!(parse_timestamp("5x ago").is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp_in_timezone() {
+        let millis = 1_704_067_200_000; // 2024-01-01T00:00:00Z
+        let new_york: Tz = "America/New_York".parse().unwrap();
+
+        assert_eq!(format_timestamp(millis, Tz::UTC, "%Y-%m-%d %H:%M:%S").as_deref(), Some("2024-01-01 00:00:00 UTC"));
+        assert_eq!(format_timestamp(millis, Tz::UTC, "%Y-%m-%dT%H:%M:%S%:z").as_deref(), Some("2024-01-01T00:00:00+00:00"));
+        assert_eq!(format_timestamp(millis, new_york, "%Y-%m-%d %H:%M:%S").as_deref(), Some("2023-12-31 19:00:00 EST"));
+        assert!(resolve_timezone(Some("Mars/Olympus_Mons"), None).is_err());
+
+        // --local resolves to the system zone, or a fixed offset without one
+        assert_eq!(zone_from_path("/usr/share/zoneinfo/Europe/Berlin"), Some(chrono_tz::Europe::Berlin));
+        assert_eq!(format_timestamp(millis, fixed_offset_zone(2 * 3600), "%Y-%m-%d %H:%M:%S").as_deref(), Some("2024-01-01 02:00:00 +02"));
+        assert_eq!(format_timestamp(millis, fixed_offset_zone(-5 * 3600), "%H:%M").as_deref(), Some("19:00 -05"));
+        assert_eq!(fixed_offset_zone(0), Tz::UTC);
+        assert!(resolve_timezone(Some("local"), None).is_ok());
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("1700000000000..1700000060000").unwrap(), (1700000000000, 1700000060000));
+
+        let (start, end) = parse_window("2h ago..").unwrap();
+        assert!((end - start - 2 * 3600 * 1000).abs() < 1000);
+
+        assert!(parse_window("1h ago").is_err());
+        assert!(parse_window("1h ago..2h ago").is_err());
+    }
+
+    #[test]
+    fn test_time_format_presets() {
+        let millis = 1_704_067_200_123;
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        let format = |name: &str, tz| name.parse::<TimeFormat>().unwrap().format(millis, tz).unwrap();
+
+        assert_eq!(format("default", Tz::UTC), "2024-01-01 00:00:00.123 UTC");
+        assert_eq!(format("epoch", new_york), "1704067200123");
+        assert_eq!(format("rfc3339", new_york), "2023-12-31T19:00:00.123-05:00");
+        assert_eq!(format("short", new_york), "19:00:00 EST");
+        assert_eq!(format("%H:%M", Tz::UTC), "00:00 UTC");
+        assert!("%Q".parse::<TimeFormat>().is_err());
+        assert!("bogus".parse::<TimeFormat>().is_err());
+    }
+
+    #[test]
+    fn test_parse_time_range_validation() {
+        let since_and_start = parse_time_range(Some("1h".to_string()), Some("2024-01-01 00:00:00".to_string()), None, None);
+        assert!(since_and_start.unwrap_err().to_string().contains("--since can't be combined"));
+
+        let reversed = parse_time_range(None, Some("1700000060000".to_string()), Some("1700000000000".to_string()), None);
+        assert!(reversed.unwrap_err().to_string().contains("is after --end"));
+
+        assert!(range_warning(Some(5), Some(5), 10).is_some());
+        assert!(range_warning(Some(20), None, 10).is_some());
+        assert!(range_warning(Some(5), Some(8), 10).is_none());
+    }
+}